@@ -1,52 +1,72 @@
-use crate::asset::{Asset, AssetReturn};
-use crate::config::Allocation;
+use crate::asset::Asset;
+use crate::config::{Allocation, Person, Portfolio};
+use crate::error::{Error, Result};
+use crate::histret::HistoricalYear;
+use crate::mix::AssetMix;
 
 use std::fmt::Debug;
 
 #[derive(Debug, Clone)]
 pub struct Account {
-    pub stocks: Asset,
-    pub bonds: Asset,
+    pub stocks: AssetMix,
+    pub bonds: AssetMix,
 }
 
-// TODO: pub enum AssetClass { Stocks(Asset), Bonds(Asset), Cash(Asset)
-
 impl Account {
-    pub fn new(stocks: f64, bonds: f64) -> Account {
+    // Holds US stocks and treasury bonds.
+    pub fn new(stocks: f64, bonds: f64) -> Result<Account> {
         Account::new_with_basis(stocks, 0.0, bonds, 0.0)
     }
-    pub fn new_with_basis(stocks: f64, stocks_basis: f64, bonds: f64, bonds_basis: f64) -> Account {
-        assert!(stocks >= 0.0);
-        assert!(stocks_basis >= 0.0);
-        assert!(bonds >= 0.0);
-        assert!(bonds_basis >= 0.0);
-        Account {
-            stocks: Asset::new_with_basis(stocks, stocks_basis),
-            bonds: Asset::new_with_basis(bonds, bonds_basis),
+    pub fn new_with_basis(
+        stocks: f64,
+        stocks_basis: f64,
+        bonds: f64,
+        bonds_basis: f64,
+    ) -> Result<Account> {
+        Account::new_with_portfolio(
+            stocks,
+            stocks_basis,
+            bonds,
+            bonds_basis,
+            &Portfolio::default(),
+        )
+    }
+    pub fn new_with_portfolio(
+        stocks: f64,
+        stocks_basis: f64,
+        bonds: f64,
+        bonds_basis: f64,
+        p: &Portfolio,
+    ) -> Result<Account> {
+        Ok(Account {
+            stocks: AssetMix::new(Asset::new_with_basis(stocks, stocks_basis)?, &p.stocks)?,
+            bonds: AssetMix::new(Asset::new_with_basis(bonds, bonds_basis)?, &p.bonds)?,
+        })
+    }
+    pub fn from_allocation(a: &Allocation, p: &Portfolio) -> Result<Account> {
+        Account::from_allocation_and_basis(a, 0.0, p)
+    }
+    pub fn from_allocation_and_basis(a: &Allocation, basis: f64, p: &Portfolio) -> Result<Account> {
+        if !(0.0..=100.0).contains(&a.bond_percent) {
+            return Err(Error::InvalidAllocation(format!(
+                "bond percent should be between 0 and 100 but was {}",
+                a.bond_percent
+            )));
         }
-    }
-    pub fn from_allocation(a: &Allocation) -> Account {
-        Account::from_allocation_and_basis(a, 0.0)
-    }
-    pub fn from_allocation_and_basis(a: &Allocation, basis: f64) -> Account {
-        assert!(a.value >= 0.0);
-        assert!(a.bond_percent >= 0.0);
-        assert!(a.bond_percent <= 100.0);
-        assert!(basis >= 0.0);
         let bonds = a.value * a.bond_percent / 100.0;
         let stocks = a.value - bonds;
         // Allocate all capital gains to stocks.
-        Account::new_with_basis(stocks, basis, bonds, bonds)
+        Account::new_with_portfolio(stocks, basis, bonds, bonds, p)
     }
 
     // Accessors.
 
     pub fn value(&self) -> f64 {
-        self.stocks.value + self.bonds.value
+        self.stocks.value() + self.bonds.value()
     }
     pub fn bond_fraction(&self) -> f64 {
         if self.value() > 0.0 {
-            self.bonds.value / self.value()
+            self.bonds.value() / self.value()
         } else {
             0.0
         }
@@ -64,18 +84,16 @@ impl Account {
 
     // Market growth methods. All return interest and dividends.
 
-    pub fn grow(&mut self, s: &AssetReturn, b: &AssetReturn, e: f64) -> f64 {
+    pub fn grow(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
         let mut id: f64 = 0.0;
-        id += self.stocks.grow(s, e);
-        id += self.bonds.grow(b, e);
-        id
+        id += self.stocks.grow(r, e)?;
+        id += self.bonds.grow(r, e)?;
+        Ok(id)
     }
-    pub fn grow_and_reinvest(&mut self, s: &AssetReturn, b: &AssetReturn, e: f64) -> f64 {
-        let sid = self.stocks.grow(s, e);
-        self.stocks.invest(sid);
-        let bid = self.bonds.grow(b, e);
-        self.bonds.invest(bid);
-        sid + bid
+    pub fn grow_and_reinvest(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
+        let sid = self.stocks.grow_and_reinvest(r, e)?;
+        let bid = self.bonds.grow_and_reinvest(r, e)?;
+        Ok(sid + bid)
     }
 
     // Methods for investing new funds.
@@ -91,88 +109,217 @@ impl Account {
     }
     // Invest $a, with a fraction b going to bonds, and the rest to stocks.
     pub fn invest_allocation(&mut self, a: f64, b: f64) {
-        assert!(a >= 0.0);
-        assert!(b >= 0.0);
-        assert!(b <= 1.0);
+        debug_assert!(a >= 0.0);
+        debug_assert!((0.0..=1.0).contains(&b));
         let bonds = a * b;
         self.invest(a - bonds, bonds);
     }
-    // Invest $a, and try to make the resulting bond fraction b. But, do not rebalance,
-    // so the desired bond fraction may not be achievable.
+    // Invest $a, and try to make the resulting bond fraction b, with each asset class at its
+    // target weight. New money goes to the classes below their target. But, do not rebalance,
+    // so the desired allocation may not be achievable.
     pub fn invest_with_goal_allocation(&mut self, a: f64, b: f64) {
-        assert!(a >= 0.0);
-        assert!(b >= 0.0);
-        assert!(b <= 1.0);
-        let bond_goal = (self.value() + a) * b;
-        let stock_goal = self.value() + a - bond_goal;
-        self.invest(
-            (stock_goal - self.stocks.value).min(a).max(0.0),
-            (bond_goal - self.bonds.value).min(a).max(0.0),
-        );
+        debug_assert!(a >= 0.0);
+        debug_assert!((0.0..=1.0).contains(&b));
+        let (stocks, bonds) = self.excess(self.value() + a, b);
+        let short: Vec<f64> = stocks.iter().chain(&bonds).map(|x| (-x).max(0.0)).collect();
+        let amounts = split(a, &short);
+        let (to_stocks, to_bonds) = amounts.split_at(stocks.len());
+        self.stocks.invest_each(to_stocks);
+        self.bonds.invest_each(to_bonds);
     }
 
     // Methods for selling assets. Returns realized capital gains.
 
     // Sell stocks and bonds, preserving the same overall asset allocation in the account.
-    pub fn sell_preserving_allocation(&mut self, a: f64) -> f64 {
-        assert!(a >= 0.0);
-        assert!(a <= self.value());
+    pub fn sell_preserving_allocation(&mut self, a: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        if a > self.value() {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: self.value(),
+            });
+        }
         let bond_sales = a * self.bond_fraction();
-        self.bonds.sell_preserving_cg_ratio(bond_sales)
-            + self.stocks.sell_preserving_cg_ratio(a - bond_sales)
+        Ok(self.bonds.sell_preserving_cg_ratio(bond_sales)?
+            + self.stocks.sell_preserving_cg_ratio(a - bond_sales)?)
     }
     // Sell $a in stocks, preserving the same fraction of capital gains. If not enough stocks,
     // sell some bonds as well.
-    pub fn sell_stocks_first(&mut self, a: f64) -> f64 {
-        assert!(a >= 0.0);
-        assert!(a <= self.value());
-        let stock_sales = self.stocks.value.min(a);
-        self.stocks.sell_preserving_cg_ratio(stock_sales)
-            + self.bonds.sell_preserving_cg_ratio(a - stock_sales)
-    }
-    pub fn sell_bonds_first(&mut self, a: f64) -> f64 {
-        assert!(a >= 0.0);
-        assert!(a <= self.value());
-        let bond_sales = self.bonds.value.min(a);
-        self.bonds.sell_preserving_cg_ratio(bond_sales)
-            + self.stocks.sell_preserving_cg_ratio(a - bond_sales)
-    }
-    // Sell $a, and try to achieve an overall bond fraction of b. But, do not rebalance,
-    // so the desired bond fraction may not be achievable.
-    pub fn sell_with_goal_allocation(&mut self, a: f64, b: f64) -> f64 {
-        assert!(a >= 0.0);
-        assert!(
-            a <= self.value(),
-            "Trying to sell {} but only have {}",
-            a,
-            self.value()
-        );
-        assert!(b >= 0.0, "bond fraction should be >= 0 but was {}", b);
-        assert!(b <= 1.0, "bond fraction should be <= 1 but was {}", b);
-        let bond_goal = (self.value() - a) * b;
-        let stock_goal = self.value() - a - bond_goal;
-        self.stocks
-            .sell_preserving_cg_ratio((self.stocks.value - stock_goal).min(a).max(0.0))
-            + self
-                .bonds
-                .sell_preserving_cg_ratio((self.bonds.value - bond_goal).min(a).max(0.0))
-    }
-
-    // Rebalance to a bond fraction of b. Returns realized capital gains.
-    pub fn rebalance(&mut self, b: f64) -> f64 {
-        let bond_target = self.value() * b;
-        if bond_target > self.bonds.value {
-            let buy_bonds = (bond_target - self.bonds.value).min(self.stocks.value);
-            assert!(buy_bonds >= 0.0);
-            self.invest(0.0, buy_bonds);
-            self.stocks.sell_preserving_cg_ratio(buy_bonds)
+    pub fn sell_stocks_first(&mut self, a: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        if a > self.value() {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: self.value(),
+            });
+        }
+        let stock_sales = self.stocks.value().min(a);
+        Ok(self.stocks.sell_preserving_cg_ratio(stock_sales)?
+            + self.bonds.sell_preserving_cg_ratio(a - stock_sales)?)
+    }
+    pub fn sell_bonds_first(&mut self, a: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        if a > self.value() {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: self.value(),
+            });
+        }
+        let bond_sales = self.bonds.value().min(a);
+        Ok(self.bonds.sell_preserving_cg_ratio(bond_sales)?
+            + self.stocks.sell_preserving_cg_ratio(a - bond_sales)?)
+    }
+    // Sell $a, and try to achieve an overall bond fraction of b, with each asset class at its
+    // target weight. Only the classes above their target are sold. But, do not rebalance, so the
+    // desired allocation may not be achievable.
+    pub fn sell_with_goal_allocation(&mut self, a: f64, b: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        if a > self.value() {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: self.value(),
+            });
+        }
+        debug_assert!((0.0..=1.0).contains(&b), "bond fraction was {}", b);
+        let (stocks, bonds) = self.excess(self.value() - a, b);
+        let over: Vec<f64> = stocks.iter().chain(&bonds).map(|x| x.max(0.0)).collect();
+        let amounts = split(a, &over);
+        let (from_stocks, from_bonds) = amounts.split_at(stocks.len());
+        Ok(self.stocks.sell_each(from_stocks)? + self.bonds.sell_each(from_bonds)?)
+    }
+
+    // Rebalance to a bond fraction of b, with each asset class at its target weight. Only the
+    // classes above their target are sold. Returns realized capital gains.
+    pub fn rebalance(&mut self, b: f64) -> Result<f64> {
+        let (stocks, bonds) = self.excess(self.value(), b);
+        let sell = |x: &[f64]| -> Vec<f64> { x.iter().map(|x| x.max(0.0)).collect() };
+        let buy = |x: &[f64]| -> Vec<f64> { x.iter().map(|x| (-x).max(0.0)).collect() };
+        let cg = self.stocks.sell_each(&sell(&stocks))? + self.bonds.sell_each(&sell(&bonds))?;
+        self.stocks.invest_each(&buy(&stocks));
+        self.bonds.invest_each(&buy(&bonds));
+        Ok(cg)
+    }
+    // How far each asset class, in stocks and in bonds, is above its target when the account is
+    // worth v with a bond fraction of b. Negative for classes below their target.
+    fn excess(&self, v: f64, b: f64) -> (Vec<f64>, Vec<f64>) {
+        let bond_goal = v * b;
+        (
+            self.stocks.excess(v - bond_goal),
+            self.bonds.excess(bond_goal),
+        )
+    }
+}
+
+// Splits $a in proportion to the amounts by which asset classes are above or below their target,
+// which add up to at least $a. When they add up to no more than $a, each gets its whole amount,
+// so that selling everything leaves nothing behind. Otherwise, the last class with an amount gets
+// what rounding leaves, so that the split adds up to exactly $a.
+fn split(a: f64, amounts: &[f64]) -> Vec<f64> {
+    let total: f64 = amounts.iter().sum();
+    if total <= a {
+        return amounts.to_vec();
+    }
+    let last = amounts.iter().rposition(|x| *x > 0.0);
+    let mut split = Vec::with_capacity(amounts.len());
+    let mut sum = 0.0;
+    for (j, x) in amounts.iter().enumerate() {
+        let y = if Some(j) == last {
+            a - sum
         } else {
-            let buy_stocks = (self.bonds.value - bond_target).min(self.bonds.value);
-            assert!(buy_stocks >= 0.0);
-            self.invest(buy_stocks, 0.0);
-            self.bonds.sell_preserving_cg_ratio(buy_stocks)
+            a * x / total
+        };
+        sum += y;
+        split.push(y);
+    }
+    split
+}
+
+// A pre-tax or Roth account for each spouse. They are invested and sold as one, in proportion
+// to their values, but RMDs and Roth conversions are per owner. Without a spouse, the spouse's
+// account stays empty.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub own: Account,
+    pub spouse: Account,
+}
+
+impl Joint {
+    // The spouse's account starts empty, holding the asset classes in p.
+    pub fn new(own: Account, p: &Portfolio) -> Result<Joint> {
+        Ok(Joint {
+            own,
+            spouse: Account::new_with_portfolio(0.0, 0.0, 0.0, 0.0, p)?,
+        })
+    }
+    pub fn with_spouse(mut self, spouse: Account) -> Joint {
+        self.spouse = spouse;
+        self
+    }
+
+    pub fn value(&self) -> f64 {
+        self.own.value() + self.spouse.value()
+    }
+    pub fn bonds_value(&self) -> f64 {
+        self.own.bonds.value() + self.spouse.bonds.value()
+    }
+    pub fn bond_fraction(&self) -> f64 {
+        if self.value() > 0.0 {
+            self.bonds_value() / self.value()
+        } else {
+            0.0
+        }
+    }
+    // Splits $a between the owners in proportion to their values.
+    pub fn split(&self, a: f64) -> (f64, f64) {
+        if self.spouse.value() <= 0.0 {
+            (a, 0.0)
+        } else if self.own.value() <= 0.0 {
+            (0.0, a)
+        } else {
+            let own = (a * self.own.value() / self.value()).min(self.own.value());
+            (own, (a - own).min(self.spouse.value()))
         }
     }
+
+    pub fn grow_and_reinvest(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
+        Ok(self.own.grow_and_reinvest(r, e)? + self.spouse.grow_and_reinvest(r, e)?)
+    }
+    // New money goes to the first person's account.
+    pub fn invest_with_goal_allocation(&mut self, a: f64, b: f64) {
+        self.own.invest_with_goal_allocation(a, b);
+    }
+    pub fn sell_with_goal_allocation(&mut self, a: f64, b: f64) -> Result<f64> {
+        let (own, spouse) = self.split(a);
+        Ok(self.own.sell_with_goal_allocation(own, b)?
+            + self.spouse.sell_with_goal_allocation(spouse, b)?)
+    }
+    pub fn rebalance(&mut self, b: f64) -> Result<f64> {
+        Ok(self.own.rebalance(b)? + self.spouse.rebalance(b)?)
+    }
+    // Moves $a into each owner's account in `to`, preserving each account's allocation.
+    pub fn convert(&mut self, a: f64, to: &mut Joint) -> Result<()> {
+        let (own, spouse) = self.split(a);
+        let b = self.own.bond_fraction();
+        self.own.sell_preserving_allocation(own)?;
+        to.own.invest_allocation(own, b);
+        let b = self.spouse.bond_fraction();
+        self.spouse.sell_preserving_allocation(spouse)?;
+        to.spouse.invest_allocation(spouse, b);
+        Ok(())
+    }
+    // Moves everything in the deceased's account to the survivor's.
+    pub fn inherit(&mut self, deceased: Person) -> Result<()> {
+        let (from, to) = match deceased {
+            Person::First => (&mut self.own, &mut self.spouse),
+            Person::Spouse => (&mut self.spouse, &mut self.own),
+        };
+        let stocks = from.stocks.value();
+        let bonds = from.bonds.value();
+        from.stocks.sell_preserving_cg_ratio(stocks)?;
+        from.bonds.sell_preserving_cg_ratio(bonds)?;
+        to.invest(stocks, bonds);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -182,33 +329,55 @@ mod account_tests {
     use crate::assert_eq_cents;
     #[cfg(test)]
     use crate::asset::AssetReturn;
+    #[cfg(test)]
+    use crate::config::{AssetClass, Person, Weight};
+    #[cfg(test)]
+    use std::collections::BTreeMap;
+
+    #[cfg(test)]
+    fn year(stocks: AssetReturn, tbonds: AssetReturn) -> HistoricalYear {
+        HistoricalYear {
+            year: 0,
+            stocks,
+            tbonds,
+            tbills: AssetReturn { cg: 0.0, id: 0.0 },
+            aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
+            inflation: 0.0,
+            series: BTreeMap::new(),
+        }
+    }
 
     #[test]
     fn new() {
-        let account = Account::new(3.0, 4.0);
-        assert_eq!(account.stocks.value, 3.0);
-        assert_eq!(account.bonds.value, 4.0);
+        let account = Account::new(3.0, 4.0).unwrap();
+        assert_eq!(account.stocks.value(), 3.0);
+        assert_eq!(account.bonds.value(), 4.0);
         assert_eq!(account.bond_fraction(), 4.0 / 7.0);
     }
 
     #[test]
     fn new_with_basis() {
-        let account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0);
-        assert_eq!(account.stocks.value, 3.0);
+        let account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0).unwrap();
+        assert_eq!(account.stocks.value(), 3.0);
         assert_eq!(account.stocks.capital_gains(), 2.0);
-        assert_eq!(account.bonds.value, 4.0);
+        assert_eq!(account.bonds.value(), 4.0);
         assert_eq!(account.bonds.capital_gains(), 3.0);
         assert_eq!(account.bond_fraction(), 4.0 / 7.0);
     }
 
     #[test]
     fn from_allocation() {
-        let account = Account::from_allocation(&Allocation {
-            value: 10.0,
-            bond_percent: 20.0,
-        });
-        assert_eq!(account.stocks.value, 8.0);
-        assert_eq!(account.bonds.value, 2.0);
+        let account = Account::from_allocation(
+            &Allocation {
+                value: 10.0,
+                bond_percent: 20.0,
+                portfolio: None,
+            },
+            &Portfolio::default(),
+        )
+        .unwrap();
+        assert_eq!(account.stocks.value(), 8.0);
+        assert_eq!(account.bonds.value(), 2.0);
     }
 
     #[test]
@@ -217,23 +386,30 @@ mod account_tests {
             &Allocation {
                 value: 10.0,
                 bond_percent: 20.0,
+                portfolio: None,
             },
             4.0,
-        );
-        assert_eq!(account.stocks.value, 8.0);
+            &Portfolio::default(),
+        )
+        .unwrap();
+        assert_eq!(account.stocks.value(), 8.0);
         assert_eq!(account.stocks.capital_gains(), 4.0);
-        assert_eq!(account.bonds.value, 2.0);
+        assert_eq!(account.bonds.value(), 2.0);
         assert_eq!(account.bonds.capital_gains(), 0.0);
     }
 
     #[test]
     fn grow() {
-        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0);
-        let id = account.grow(
-            &AssetReturn { cg: 0.01, id: 0.02 },
-            &AssetReturn { cg: 0.03, id: 0.04 },
-            0.0,
-        );
+        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0).unwrap();
+        let id = account
+            .grow(
+                &year(
+                    AssetReturn { cg: 0.01, id: 0.02 },
+                    AssetReturn { cg: 0.03, id: 0.04 },
+                ),
+                0.0,
+            )
+            .unwrap();
         assert_eq!(id, 6.0);
         assert_eq!(account.value(), 204.0);
         assert_eq!(account.capital_gains(), 4.0);
@@ -241,12 +417,16 @@ mod account_tests {
 
     #[test]
     fn grow_expense_ratio() {
-        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0);
-        let id = account.grow(
-            &AssetReturn { cg: 0.01, id: 0.02 },
-            &AssetReturn { cg: 0.03, id: 0.04 },
-            0.01,
-        );
+        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0).unwrap();
+        let id = account
+            .grow(
+                &year(
+                    AssetReturn { cg: 0.01, id: 0.02 },
+                    AssetReturn { cg: 0.03, id: 0.04 },
+                ),
+                0.01,
+            )
+            .unwrap();
         assert_eq!(id, 6.0);
         assert_eq_cents!(account.value(), 204.0 * 0.99);
         assert_eq_cents!(account.capital_gains(), 204.0 * 0.99 - 200.0);
@@ -254,12 +434,16 @@ mod account_tests {
 
     #[test]
     fn grow_and_reinvest() {
-        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0);
-        let id = account.grow_and_reinvest(
-            &AssetReturn { cg: 0.01, id: 0.02 },
-            &AssetReturn { cg: 0.03, id: 0.04 },
-            0.0,
-        );
+        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0).unwrap();
+        let id = account
+            .grow_and_reinvest(
+                &year(
+                    AssetReturn { cg: 0.01, id: 0.02 },
+                    AssetReturn { cg: 0.03, id: 0.04 },
+                ),
+                0.0,
+            )
+            .unwrap();
         assert_eq!(id, 6.0);
         assert_eq!(account.value(), 210.0);
         assert_eq!(account.capital_gains(), 4.0);
@@ -267,12 +451,16 @@ mod account_tests {
 
     #[test]
     fn grow_and_reinvest_expense_ratio() {
-        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0);
-        let id = account.grow_and_reinvest(
-            &AssetReturn { cg: 0.01, id: 0.02 },
-            &AssetReturn { cg: 0.03, id: 0.04 },
-            0.01,
-        );
+        let mut account = Account::new_with_basis(100.0, 100.0, 100.0, 100.0).unwrap();
+        let id = account
+            .grow_and_reinvest(
+                &year(
+                    AssetReturn { cg: 0.01, id: 0.02 },
+                    AssetReturn { cg: 0.03, id: 0.04 },
+                ),
+                0.01,
+            )
+            .unwrap();
         assert_eq!(id, 6.0);
         assert_eq_cents!(account.value(), 204.0 * 0.99 + 6.0);
         assert_eq_cents!(account.capital_gains(), 204.0 * 0.99 - 200.0);
@@ -280,7 +468,7 @@ mod account_tests {
 
     #[test]
     fn invest() {
-        let mut account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0);
+        let mut account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0).unwrap();
         let cg = account.capital_gains();
         account.invest(1.0, 2.0);
         assert_eq!(account.value(), 10.0);
@@ -289,7 +477,7 @@ mod account_tests {
 
     #[test]
     fn invest_preserving_allocation() {
-        let mut account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0);
+        let mut account = Account::new_with_basis(3.0, 1.0, 4.0, 1.0).unwrap();
         let cg = account.capital_gains();
         let bf = account.bond_fraction();
         account.invest_preserving_allocation(7.0);
@@ -300,58 +488,58 @@ mod account_tests {
 
     #[test]
     fn invest_allocation() {
-        let mut account = Account::new_with_basis(100.0, 17.0, 100.0, 23.0);
+        let mut account = Account::new_with_basis(100.0, 17.0, 100.0, 23.0).unwrap();
         let cg = account.capital_gains();
         account.invest_allocation(5.0, 0.2);
         assert_eq!(account.value(), 205.0);
-        assert_eq!(account.stocks.value, 104.0);
-        assert_eq!(account.bonds.value, 101.0);
+        assert_eq!(account.stocks.value(), 104.0);
+        assert_eq!(account.bonds.value(), 101.0);
         assert_eq!(account.capital_gains(), cg);
     }
 
     #[test]
     fn sell_preserving_allocation() {
-        let mut account = Account::new_with_basis(80.0, 40.0, 20.0, 20.0);
+        let mut account = Account::new_with_basis(80.0, 40.0, 20.0, 20.0).unwrap();
         let bf = account.bond_fraction();
         assert_eq!(bf, 0.20);
         let initial_cg = account.capital_gains();
         assert_eq!(initial_cg, 40.0);
-        let realized_cg = account.sell_preserving_allocation(10.0);
+        let realized_cg = account.sell_preserving_allocation(10.0).unwrap();
         assert_eq!(realized_cg, 4.0);
         assert_eq!(account.bond_fraction(), bf);
         assert_eq!(account.value(), 90.0);
-        assert_eq!(account.stocks.value, 72.0);
-        assert_eq!(account.bonds.value, 18.0);
+        assert_eq!(account.stocks.value(), 72.0);
+        assert_eq!(account.bonds.value(), 18.0);
         assert_eq!(account.capital_gains(), initial_cg - realized_cg);
     }
 
     #[test]
     fn sell_stocks_first() {
-        let mut account = Account::new(100.0, 100.0);
-        account.sell_stocks_first(50.0);
-        assert_eq!(account.stocks.value, 50.0);
-        assert_eq!(account.bonds.value, 100.0);
-        account.sell_stocks_first(100.0);
-        assert_eq!(account.stocks.value, 0.0);
-        assert_eq!(account.bonds.value, 50.0);
+        let mut account = Account::new(100.0, 100.0).unwrap();
+        account.sell_stocks_first(50.0).unwrap();
+        assert_eq!(account.stocks.value(), 50.0);
+        assert_eq!(account.bonds.value(), 100.0);
+        account.sell_stocks_first(100.0).unwrap();
+        assert_eq!(account.stocks.value(), 0.0);
+        assert_eq!(account.bonds.value(), 50.0);
     }
 
     #[test]
     fn sell_bonds_first() {
-        let mut account = Account::new(100.0, 100.0);
-        account.sell_bonds_first(50.0);
-        assert_eq!(account.stocks.value, 100.0);
-        assert_eq!(account.bonds.value, 50.0);
-        account.sell_bonds_first(100.0);
-        assert_eq!(account.stocks.value, 50.0);
-        assert_eq!(account.bonds.value, 0.0);
+        let mut account = Account::new(100.0, 100.0).unwrap();
+        account.sell_bonds_first(50.0).unwrap();
+        assert_eq!(account.stocks.value(), 100.0);
+        assert_eq!(account.bonds.value(), 50.0);
+        account.sell_bonds_first(100.0).unwrap();
+        assert_eq!(account.stocks.value(), 50.0);
+        assert_eq!(account.bonds.value(), 0.0);
     }
 
     #[test]
     fn sell_with_goal_allocation() {
-        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0);
+        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0).unwrap();
         let cg = account.capital_gains();
-        let realized_cg = account.sell_with_goal_allocation(20.0, 0.5);
+        let realized_cg = account.sell_with_goal_allocation(20.0, 0.5).unwrap();
         assert_eq!(account.value(), 180.0);
         assert_eq!(account.capital_gains(), cg - realized_cg);
         assert_eq!(account.bond_fraction(), 0.5);
@@ -360,9 +548,9 @@ mod account_tests {
 
     #[test]
     fn sell_with_goal_allocation_all_stocks() {
-        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0);
+        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0).unwrap();
         let cg = account.capital_gains();
-        let realized_cg = account.sell_with_goal_allocation(20.0, 1.0);
+        let realized_cg = account.sell_with_goal_allocation(20.0, 1.0).unwrap();
         assert_eq!(account.value(), 180.0);
         assert_eq!(account.capital_gains(), cg - realized_cg);
         assert_eq!(account.bond_fraction(), 100.0 / 180.0);
@@ -371,9 +559,9 @@ mod account_tests {
 
     #[test]
     fn sell_with_goal_allocation_all_bonds() {
-        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0);
+        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0).unwrap();
         let cg = account.capital_gains();
-        let realized_cg = account.sell_with_goal_allocation(20.0, 0.0);
+        let realized_cg = account.sell_with_goal_allocation(20.0, 0.0).unwrap();
         assert_eq!(account.value(), 180.0);
         assert_eq!(account.capital_gains(), cg - realized_cg);
         assert_eq!(account.bond_fraction(), 80.0 / 180.0);
@@ -382,10 +570,10 @@ mod account_tests {
 
     #[test]
     fn rebalance_to_stocks() {
-        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0);
+        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0).unwrap();
         let orig_value = account.value();
         let cg = account.capital_gains();
-        let realized_cg = account.rebalance(0.2);
+        let realized_cg = account.rebalance(0.2).unwrap();
         assert_eq!(account.value(), orig_value);
         assert_eq!(account.capital_gains(), cg - realized_cg);
         assert_eq!(account.bond_fraction(), 0.2);
@@ -394,10 +582,10 @@ mod account_tests {
 
     #[test]
     fn rebalance_to_bonds() {
-        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0);
+        let mut account = Account::new_with_basis(100.0, 50.0, 100.0, 90.0).unwrap();
         let orig_value = account.value();
         let cg = account.capital_gains();
-        let realized_cg = account.rebalance(0.8);
+        let realized_cg = account.rebalance(0.8).unwrap();
         assert_eq!(account.value(), orig_value);
         assert_eq!(account.capital_gains(), cg - realized_cg);
         assert_eq!(account.bond_fraction(), 0.8);
@@ -406,37 +594,140 @@ mod account_tests {
 
     #[test]
     fn invest_with_goal_allocation() {
-        let mut account = Account::new(100.0, 100.0);
+        let mut account = Account::new(100.0, 100.0).unwrap();
         account.invest_with_goal_allocation(10.0, 0.5);
         assert_eq!(account.value(), 210.0);
-        assert_eq!(account.stocks.value, 105.0);
-        assert_eq!(account.bonds.value, 105.0);
+        assert_eq!(account.stocks.value(), 105.0);
+        assert_eq!(account.bonds.value(), 105.0);
     }
 
     #[test]
     fn invest_with_goal_allocation_all_stocks() {
-        let mut account = Account::new(100.0, 100.0);
+        let mut account = Account::new(100.0, 100.0).unwrap();
         account.invest_with_goal_allocation(10.0, 0.2);
         assert_eq!(account.value(), 210.0);
-        assert_eq!(account.stocks.value, 110.0);
-        assert_eq!(account.bonds.value, 100.0);
+        assert_eq!(account.stocks.value(), 110.0);
+        assert_eq!(account.bonds.value(), 100.0);
     }
 
     #[test]
     fn invest_with_goal_allocation_all_bonds() {
-        let mut account = Account::new(100.0, 100.0);
+        let mut account = Account::new(100.0, 100.0).unwrap();
         account.invest_with_goal_allocation(10.0, 0.8);
         assert_eq!(account.value(), 210.0);
-        assert_eq!(account.stocks.value, 100.0);
-        assert_eq!(account.bonds.value, 110.0);
+        assert_eq!(account.stocks.value(), 100.0);
+        assert_eq!(account.bonds.value(), 110.0);
     }
 
     #[test]
     fn capital_gains_ratio() {
-        let account = Account::new_with_basis(0.0, 0.0, 0.0, 0.0);
+        let account = Account::new_with_basis(0.0, 0.0, 0.0, 0.0).unwrap();
         assert_eq!(account.capital_gains_fraction(), 0.0);
 
-        let account = Account::new_with_basis(10.0, 0.0, 10.0, 10.0);
+        let account = Account::new_with_basis(10.0, 0.0, 10.0, 10.0).unwrap();
         assert_eq!(account.capital_gains_fraction(), 0.5);
     }
+
+    #[test]
+    fn rebalance_asset_classes() {
+        let p = Portfolio {
+            stocks: vec![Weight {
+                asset: AssetClass::UsStocks,
+                percent: 100.0,
+            }],
+            bonds: vec![
+                Weight {
+                    asset: AssetClass::TBonds,
+                    percent: 50.0,
+                },
+                Weight {
+                    asset: AssetClass::AaaBonds,
+                    percent: 50.0,
+                },
+            ],
+        };
+        let mut account = Account::new_with_portfolio(100.0, 100.0, 100.0, 100.0, &p).unwrap();
+        let mut r = year(
+            AssetReturn { cg: 0.0, id: 0.0 },
+            AssetReturn { cg: 0.2, id: 0.0 },
+        );
+        r.aaabonds = AssetReturn { cg: 0.0, id: 0.0 };
+        account.grow(&r, 0.0).unwrap();
+        assert_eq!(account.value(), 210.0);
+        let realized_cg = account.rebalance(0.5).unwrap();
+        assert_eq!(account.bond_fraction(), 0.5);
+        assert_eq!(
+            account.bonds.values(),
+            vec![(AssetClass::TBonds, 52.5), (AssetClass::AaaBonds, 52.5)]
+        );
+        assert_eq_cents!(realized_cg, 7.5 / 60.0 * 10.0);
+    }
+
+    #[test]
+    fn sell_with_goal_allocation_asset_classes() {
+        let p = Portfolio {
+            stocks: vec![Weight {
+                asset: AssetClass::UsStocks,
+                percent: 100.0,
+            }],
+            bonds: vec![
+                Weight {
+                    asset: AssetClass::TBonds,
+                    percent: 50.0,
+                },
+                Weight {
+                    asset: AssetClass::AaaBonds,
+                    percent: 50.0,
+                },
+            ],
+        };
+        let mut account = Account::new_with_portfolio(100.0, 100.0, 100.0, 100.0, &p).unwrap();
+        account
+            .grow(
+                &year(
+                    AssetReturn { cg: 0.0, id: 0.0 },
+                    AssetReturn { cg: 0.2, id: 0.0 },
+                ),
+                0.0,
+            )
+            .unwrap();
+        // Only T-bonds are above their target, so only they are sold.
+        let realized_cg = account.sell_with_goal_allocation(10.0, 0.5).unwrap();
+        assert_eq!(account.stocks.value(), 100.0);
+        assert_eq!(
+            account.bonds.values(),
+            vec![(AssetClass::TBonds, 50.0), (AssetClass::AaaBonds, 50.0)]
+        );
+        assert_eq_cents!(realized_cg, 10.0 / 60.0 * 10.0);
+    }
+
+    #[test]
+    fn joint() {
+        let mut pre_tax = Joint::new(Account::new(60.0, 0.0).unwrap(), &Portfolio::default())
+            .unwrap()
+            .with_spouse(Account::new(20.0, 20.0).unwrap());
+        assert_eq!(pre_tax.value(), 100.0);
+        assert_eq!(pre_tax.bond_fraction(), 0.2);
+        assert_eq!(pre_tax.split(10.0), (6.0, 4.0));
+        assert_eq!(
+            Joint::new(Account::new(60.0, 0.0).unwrap(), &Portfolio::default())
+                .unwrap()
+                .split(10.0),
+            (10.0, 0.0)
+        );
+
+        // Conversions come from each owner in proportion, and go to their own Roth.
+        let mut roth = Joint::new(Account::new(0.0, 0.0).unwrap(), &Portfolio::default()).unwrap();
+        pre_tax.convert(10.0, &mut roth).unwrap();
+        assert_eq!(pre_tax.own.value(), 54.0);
+        assert_eq!(pre_tax.spouse.value(), 36.0);
+        assert_eq!(roth.own.value(), 6.0);
+        assert_eq!(roth.spouse.value(), 4.0);
+        assert_eq!(roth.spouse.bond_fraction(), 0.5);
+
+        pre_tax.inherit(Person::Spouse).unwrap();
+        assert_eq!(pre_tax.spouse.value(), 0.0);
+        assert_eq!(pre_tax.own.value(), 90.0);
+        assert_eq!(pre_tax.own.bonds.value(), 18.0);
+    }
 }
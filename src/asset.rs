@@ -1,3 +1,5 @@
+use crate::error::{Error, Result};
+
 use std::fmt::Debug;
 
 #[derive(Debug, Clone)]
@@ -6,7 +8,7 @@ pub struct Asset {
     cost_basis: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AssetReturn {
     pub cg: f64, // Capital gains (long-term).
     pub id: f64, // Interest and dividends.
@@ -14,16 +16,29 @@ pub struct AssetReturn {
 
 impl Asset {
     pub fn new(value: f64) -> Asset {
-        Asset::new_with_basis(value, value)
+        debug_assert!(value >= 0.0);
+        Asset {
+            value,
+            cost_basis: value,
+        }
     }
-    pub fn new_with_basis(value: f64, cost_basis: f64) -> Asset {
-        assert!(value >= 0.0);
-        assert!(cost_basis >= 0.0);
-        assert!(cost_basis <= value);
-        Asset { value, cost_basis }
+    pub fn new_with_basis(value: f64, cost_basis: f64) -> Result<Asset> {
+        if value < 0.0 || cost_basis < 0.0 {
+            return Err(Error::InvalidAllocation(format!(
+                "value {} and cost basis {} should not be negative",
+                value, cost_basis
+            )));
+        }
+        if cost_basis > value {
+            return Err(Error::InvalidAllocation(format!(
+                "cost basis {} is more than value {}",
+                cost_basis, value
+            )));
+        }
+        Ok(Asset { value, cost_basis })
     }
     pub fn grow(&mut self, r: &AssetReturn, e: f64) -> f64 {
-        assert!(r.id >= 0.0);
+        debug_assert!(r.id >= 0.0);
         let id = self.value * r.id;
         self.value += self.value * r.cg;
         // TODO: How does expense ratio affect capital gains?
@@ -31,7 +46,7 @@ impl Asset {
         id
     }
     pub fn invest(&mut self, amt: f64) {
-        assert!(amt >= 0.0);
+        debug_assert!(amt >= 0.0);
         self.value += amt;
         self.cost_basis += amt;
     }
@@ -45,18 +60,23 @@ impl Asset {
             0.0
         }
     }
-    pub fn sell_preserving_cg_ratio(&mut self, a: f64) -> f64 {
-        assert!(a >= 0.0);
-        assert!(
-            a <= self.value,
-            "Trying to sell {} but only have {}",
-            a,
-            self.value
-        );
+    pub fn sell_preserving_cg_ratio(&mut self, a: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        if a > self.value {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: self.value,
+            });
+        }
         let cg_ratio = self.capital_gains_ratio();
         self.value -= a;
         self.cost_basis = self.value * (1.0 - cg_ratio);
-        a * cg_ratio
+        Ok(a * cg_ratio)
+    }
+    // Splits off $a into a new asset, with the same fraction of capital gains.
+    pub fn split_off(&mut self, a: f64) -> Result<Asset> {
+        let cg = self.sell_preserving_cg_ratio(a)?;
+        Asset::new_with_basis(a, a - cg)
     }
 }
 
@@ -74,18 +94,26 @@ mod asset_tests {
 
     #[test]
     fn new_with_basis() {
-        let asset = Asset::new_with_basis(100.0, 50.0);
+        let asset = Asset::new_with_basis(100.0, 50.0).unwrap();
         assert_eq!(asset.value, 100.0);
         assert_eq!(asset.cost_basis, 50.0);
         assert_eq!(asset.capital_gains(), 50.0);
     }
 
+    #[test]
+    fn basis_greater_than_value() {
+        assert!(matches!(
+            Asset::new_with_basis(100.0, 150.0),
+            Err(Error::InvalidAllocation(_))
+        ));
+    }
+
     #[test]
     fn capital_gains_ratio() {
-        let asset = Asset::new_with_basis(5.0, 1.0);
+        let asset = Asset::new_with_basis(5.0, 1.0).unwrap();
         assert_eq!(asset.capital_gains_ratio(), 0.8);
 
-        let asset = Asset::new_with_basis(0.0, 0.0);
+        let asset = Asset::new_with_basis(0.0, 0.0).unwrap();
         assert_eq!(asset.capital_gains_ratio(), 0.0);
     }
 
@@ -118,16 +146,39 @@ mod asset_tests {
 
     #[test]
     fn sell_preserving_cg_ratio() {
-        let mut asset = Asset::new_with_basis(100.0, 50.0);
+        let mut asset = Asset::new_with_basis(100.0, 50.0).unwrap();
         assert_eq!(asset.capital_gains_ratio(), 0.5);
-        assert_eq!(asset.sell_preserving_cg_ratio(10.0), 5.0);
+        assert_eq!(asset.sell_preserving_cg_ratio(10.0).unwrap(), 5.0);
         assert_eq!(asset.value, 90.0);
         assert_eq!(asset.capital_gains_ratio(), 0.5);
 
-        let mut asset = Asset::new_with_basis(100.0, 100.0);
+        let mut asset = Asset::new_with_basis(100.0, 100.0).unwrap();
         assert_eq!(asset.capital_gains_ratio(), 0.0);
-        assert_eq!(asset.sell_preserving_cg_ratio(10.0), 0.0);
+        assert_eq!(asset.sell_preserving_cg_ratio(10.0).unwrap(), 0.0);
         assert_eq!(asset.value, 90.0);
         assert_eq!(asset.capital_gains_ratio(), 0.0);
     }
+
+    #[test]
+    fn sell_too_much() {
+        let mut asset = Asset::new(100.0);
+        assert!(matches!(
+            asset.sell_preserving_cg_ratio(150.0),
+            Err(Error::InsufficientFunds {
+                requested: _,
+                available: _
+            })
+        ));
+        assert_eq!(asset.value, 100.0);
+    }
+
+    #[test]
+    fn split_off() {
+        let mut asset = Asset::new_with_basis(100.0, 50.0).unwrap();
+        let split = asset.split_off(40.0).unwrap();
+        assert_eq!(asset.value, 60.0);
+        assert_eq!(asset.capital_gains(), 30.0);
+        assert_eq!(split.value, 40.0);
+        assert_eq!(split.capital_gains(), 20.0);
+    }
 }
@@ -1,5 +1,8 @@
+use chrono::{Datelike, Utc};
 use serde::{Deserialize, Serialize};
 use std::option::Option;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InitialBalance {
@@ -7,6 +10,9 @@ pub struct InitialBalance {
     pub roth: Allocation,
     pub after_tax: Allocation,
     pub after_tax_cost_basis: Option<f64>,
+    // Held in T-bills, in a taxable account.
+    #[serde(default)]
+    pub cash: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,6 +33,10 @@ pub enum PhaseType {
 pub struct SimpleWithdrawal {
     pub amount: f64,
     pub bond_percent: f64,
+    #[serde(default)]
+    pub cash_bucket: Option<CashBucket>,
+    #[serde(default)]
+    pub spending: SpendingRule,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -34,6 +44,87 @@ pub struct TaxAwareWithdrawal {
     pub living_expenses: f64,
     pub birth_year: i32,
     pub bond_percent: f64,
+    #[serde(default)]
+    pub roth_conversion: Option<RothConversion>,
+    #[serde(default)]
+    pub cash_bucket: Option<CashBucket>,
+    #[serde(default)]
+    pub spending: SpendingRule,
+}
+
+// How much to spend each year of a withdrawal phase. The phase's amount or living_expenses, in
+// today's dollars, is what the rule starts from.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub enum SpendingRule {
+    // The same real amount every year.
+    #[default]
+    Constant,
+    // This percent of the portfolio's value at the start of each year.
+    PercentOfPortfolio(f64),
+    // Variable percentage withdrawal: the payment of an annuity that would use up the portfolio
+    // over the years left in the plan, assuming this real return in percent.
+    VariablePercentage(f64),
+    Guardrails(Guardrails),
+    FloorAndCeiling(FloorAndCeiling),
+}
+
+// Guyton-Klinger guardrails. Spending starts at the phase's amount and keeps up with inflation,
+// except after a year in which the portfolio lost value. When the withdrawal rate rises more than
+// upper percent above the first year's rate, spending is cut by adjustment percent. When it falls
+// more than lower percent below, spending is raised by adjustment percent.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Guardrails {
+    #[serde(default = "default_guardrail")]
+    pub upper: f64,
+    #[serde(default = "default_guardrail")]
+    pub lower: f64,
+    #[serde(default = "default_adjustment")]
+    pub adjustment: f64,
+}
+
+fn default_guardrail() -> f64 {
+    20.0
+}
+
+fn default_adjustment() -> f64 {
+    10.0
+}
+
+// Spends percent of the portfolio's value, but no less than floor percent and no more than
+// ceiling percent of the phase's amount.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FloorAndCeiling {
+    pub percent: f64,
+    pub floor: f64,
+    pub ceiling: f64,
+}
+
+// Keeps some years of expenses in cash. After a year in which the portfolio's stocks returned
+// less than refill_threshold percent, expenses are paid from cash first. Otherwise, cash is
+// refilled from the portfolio. Cash always pays whatever the portfolio can't.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CashBucket {
+    pub years: f64,
+    #[serde(default)]
+    pub refill_threshold: f64,
+}
+
+// Moves money from pre-tax to Roth each year, before the given age if there is one. The age is
+// the first person's, or the survivor's after spouse.first_death. Taxes on conversions are paid
+// along with living expenses, from after-tax funds first.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RothConversion {
+    pub amount: ConversionAmount,
+    #[serde(default)]
+    pub until_age: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ConversionAmount {
+    // Fill ordinary income up to the top of the federal bracket with this rate, in percent.
+    FillBracket(f64),
+    // In today's dollars.
+    Fixed(f64),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -44,10 +135,40 @@ pub struct YearlyContribution {
     pub target_bond_percent: f64,
 }
 
+// The spouse's own pre-tax and Roth accounts, from which their RMDs are taken and into which
+// their Roth conversions go. After-tax money and cash are shared.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Spouse {
+    pub birth_year: i32,
+    pub pre_tax: Allocation,
+    pub roth: Allocation,
+    #[serde(default)]
+    pub first_death: Option<FirstDeath>,
+}
+
+// One person dies at the end of the given calendar year. From the next year, the survivor
+// inherits their accounts, keeps the larger of the two Social Security benefits, and files as
+// single.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FirstDeath {
+    pub person: Person,
+    pub year: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Person {
+    // The person described by initial_balance.
+    First,
+    Spouse,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Allocation {
     pub value: f64,
     pub bond_percent: f64,
+    // The asset classes this account holds. Defaults to the top-level portfolio.
+    #[serde(default)]
+    pub portfolio: Option<Portfolio>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -56,9 +177,475 @@ pub struct InitialState {
     pub phases: Vec<Phase>,
     pub expense_ratio: f64,
     pub report: Vec<ReportField>,
+    #[serde(default)]
+    pub growth_model: GrowthModel,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub tax: TaxConfig,
+    #[serde(default)]
+    pub portfolio: Portfolio,
+    // One entry for each person receiving benefits.
+    #[serde(default)]
+    pub social_security: Vec<SocialSecurity>,
+    // A second person in the household. initial_balance and the birth_year of withdrawal phases
+    // describe the first.
+    #[serde(default)]
+    pub spouse: Option<Spouse>,
+    // CSV or YAML files with yearly returns, which extend or replace the built-in historical
+    // data. Relative paths are resolved against the config file's directory by resolve_paths().
+    #[serde(default)]
+    pub return_data: Vec<PathBuf>,
+    // Settings to vary with `isim sweep`. Ignored by `isim run`.
+    #[serde(default)]
+    pub sweep: Option<Sweep>,
+    // Target for `isim solve`. Ignored by `isim run`.
+    #[serde(default)]
+    pub solve: Option<Solve>,
+    // Calendar year in which the plan starts, from which ages, tax law changes and the
+    // CalendarYear report field are counted. Defaults to this year.
+    #[serde(default = "this_year")]
+    pub start_year: i32,
+}
+
+pub fn this_year() -> i32 {
+    Utc::now().year()
+}
+
+impl InitialState {
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        for f in &mut self.return_data {
+            if f.is_relative() {
+                *f = dir.join(&f);
+            }
+        }
+    }
+    // The asset classes held by the account with the given allocation.
+    pub fn portfolio_of<'a>(&'a self, a: &'a Allocation) -> &'a Portfolio {
+        a.portfolio.as_ref().unwrap_or(&self.portfolio)
+    }
+    // Replaces the seed of a random growth model.
+    pub fn set_seed(&mut self, seed: u64) -> Result<(), String> {
+        match &mut self.growth_model {
+            GrowthModel::Bootstrap(b) => b.seed = seed,
+            GrowthModel::BlockBootstrap(b) => b.seed = seed,
+            GrowthModel::Parametric(p) => p.seed = seed,
+            GrowthModel::HistoricalPath | GrowthModel::Fixed(_) => {
+                return Err(String::from("The growth model does not use a seed"))
+            }
+        }
+        Ok(())
+    }
+    // Lengthens or shortens the last phase so the plan lasts the given number of years.
+    pub fn set_years(&mut self, years: usize) -> Result<(), String> {
+        let earlier: usize = self.phases.iter().rev().skip(1).map(|p| p.years).sum();
+        match self.phases.last_mut() {
+            Some(p) if earlier < years => {
+                p.years = years - earlier;
+                Ok(())
+            }
+            Some(_) => Err(format!(
+                "Phases before the last already last {} years",
+                earlier
+            )),
+            None => Err(String::from("There are no phases")),
+        }
+    }
+}
+
+// Runs the config once for each value of one or two settings, and reports a metric at the end
+// of each run. Rows of the output follow the first axis, and columns the second.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Sweep {
+    #[serde(default = "success_rate")]
+    pub metric: ReportField,
+    pub axes: Vec<SweepAxis>,
+}
+
+fn success_rate() -> ReportField {
+    ReportField::SuccessRate
+}
+
+// Searches for the value that just meets a target. A path succeeds if it never runs out of money
+// and ends with at least min_ending_balance, in today's dollars.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Solve {
+    #[serde(default)]
+    pub find: SolveFor,
+    // Percent of paths that must succeed.
+    pub success_rate: f64,
+    #[serde(default)]
+    pub min_ending_balance: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+pub enum SolveFor {
+    // The highest yearly withdrawal. Every withdrawal phase is scaled along with the first.
+    #[default]
+    MaxWithdrawal,
+    // The fewest years of the first Accumulation phase. Later phases keep their length.
+    MinWorkingYears,
+    // The lowest total yearly contribution of the first Accumulation phase. Every Accumulation
+    // phase is scaled along with it, keeping the split between accounts.
+    MinContribution,
+}
+
+// Settings located by YAML path, such as phases[0].config.SimpleWithdrawAndRebalance.amount.
+// With one path, each value replaces the setting. With several, each value is a list with one
+// entry per path, so settings that must change together, like a phase's bond percent and the
+// initial bond percent, form a single axis.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SweepAxis {
+    // Shown in the output instead of the paths.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub paths: Vec<String>,
+    pub values: Vec<serde_yaml::Value>,
+    // Shown in the output instead of the values. Either empty or one per value.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl SweepAxis {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.paths.join(", "))
+    }
+    pub fn label(&self, i: usize) -> String {
+        match (self.labels.get(i), &self.values[i]) {
+            (Some(l), _) => l.clone(),
+            (None, serde_yaml::Value::String(s)) => s.clone(),
+            (None, v) => serde_json::to_string(v).unwrap_or_default(),
+        }
+    }
+}
+
+// Parses PATH=V1,V2,... where each value is YAML.
+impl FromStr for SweepAxis {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, values) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected PATH=V1,V2,... but got '{}'", s))?;
+        let values = values
+            .split(',')
+            .map(|v| serde_yaml::from_str(v.trim()).map_err(|e| format!("{}: {}", v, e)))
+            .collect::<Result<Vec<serde_yaml::Value>, String>>()?;
+        Ok(SweepAxis {
+            name: None,
+            paths: vec![path.trim().to_string()],
+            values,
+            labels: Vec::new(),
+        })
+    }
+}
+
+// The asset classes making up the stock and bond parts of an account. Bond percentages elsewhere
+// in the config refer to the bond part, which is split between bond classes by weight. Likewise
+// for stocks. Each class's target is its weight times the target for its part, and goal
+// allocation, rebalancing and selling buy and sell each class towards its own target.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Portfolio {
+    #[serde(default = "us_stocks")]
+    pub stocks: Vec<Weight>,
+    #[serde(default = "treasury_bonds")]
+    pub bonds: Vec<Weight>,
+}
+
+impl Default for Portfolio {
+    fn default() -> Self {
+        Portfolio {
+            stocks: us_stocks(),
+            bonds: treasury_bonds(),
+        }
+    }
+}
+
+fn us_stocks() -> Vec<Weight> {
+    vec![Weight {
+        asset: AssetClass::UsStocks,
+        percent: 100.0,
+    }]
+}
+
+fn treasury_bonds() -> Vec<Weight> {
+    vec![Weight {
+        asset: AssetClass::TBonds,
+        percent: 100.0,
+    }]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Weight {
+    pub asset: AssetClass,
+    pub percent: f64,
+}
+
+// Asset classes with historical returns.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AssetClass {
+    // S&P 500.
+    UsStocks,
+    // 10-year treasury bonds.
+    TBonds,
+    // Aaa corporate bonds.
+    AaaBonds,
+    // 3-month treasury bills.
+    TBills,
+    // A return series from one of the return_data files, by column name.
+    Series(String),
+}
+
+impl AssetClass {
+    // The variant, or the column name for a series.
+    pub fn name(&self) -> String {
+        match self {
+            AssetClass::Series(name) => name.clone(),
+            _ => format!("{:?}", self),
+        }
+    }
+}
+
+// Benefits are received during tax-aware withdrawal phases.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocialSecurity {
+    // Monthly benefit at full retirement age, in today's dollars.
+    pub primary_insurance_amount: f64,
+    pub birth_year: i32,
+    // Between 62 and 70. Benefits start in the year this age is reached.
+    pub claiming_age: i32,
+}
+
+// Defaults to a married couple in Illinois, filing jointly.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaxConfig {
+    #[serde(default)]
+    pub filing_status: FilingStatus,
+    #[serde(default = "illinois")]
+    pub state: StateTax,
+    // Replaces the built-in 2020 federal tables for the filing status. In start_year dollars.
+    #[serde(default)]
+    pub federal: Option<FederalTaxTable>,
+    #[serde(default)]
+    pub bracket_indexing: BracketIndexing,
+    // Changes to tax law during the simulation, such as the TCJA sunset.
+    #[serde(default)]
+    pub changes: Vec<TaxLawChange>,
+    // Built-in federal tables are in dollars of the year they are from, and are brought to
+    // start_year dollars with the historical inflation. This yearly rate, in percent, is used for
+    // years the data doesn't cover. Defaults to the mean inflation in the data.
+    #[serde(default)]
+    pub table_inflation: Option<f64>,
+}
+
+impl Default for TaxConfig {
+    fn default() -> Self {
+        TaxConfig {
+            filing_status: FilingStatus::default(),
+            state: illinois(),
+            federal: None,
+            bracket_indexing: BracketIndexing::default(),
+            changes: Vec::new(),
+            table_inflation: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum FilingStatus {
+    Single,
+    #[default]
+    MarriedFilingJointly,
+    HeadOfHousehold,
+}
+
+// Tax law in force from the given year onwards. Anything not given is unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaxLawChange {
+    pub year: TaxYear,
+    #[serde(default)]
+    pub federal: Option<FederalTaxLaw>,
+    #[serde(default)]
+    pub state: Option<StateTax>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TaxYear {
+    Calendar(i32),
+    // The number of years elapsed since the start of the simulation.
+    Simulation(usize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FederalTaxLaw {
+    // Built-in tables for the filing status, like the default.
+    Tcja2020,
+    // Built-in tables from before the Tax Cuts and Jobs Act, for the filing status.
+    PreTcja2017,
+    Custom(FederalTaxTable),
+}
+
+// How tax brackets, deductions and thresholds grow with the simulated path's inflation.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum BracketIndexing {
+    #[default]
+    Cpi,
+    // Indexed to inflation minus a lag, in percent per year.
+    ChainedCpi {
+        lag: f64,
+    },
+    // Brackets stay at their nominal values.
+    Frozen,
+}
+
+// State income tax, applied to ordinary income plus capital gains.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum StateTax {
+    None,
+    // Rate in percent.
+    Flat(f64),
+    Progressive(Vec<TaxBracket>),
+}
+
+fn illinois() -> StateTax {
+    StateTax::Flat(4.95)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FederalTaxTable {
+    pub standard_deduction: f64,
+    pub income_brackets: Vec<TaxBracket>,
+    // Applied to ordinary income plus capital gains, with capital gains taxed last.
+    pub capital_gains_brackets: Vec<TaxBracket>,
+    // Net investment income tax applies to income above this amount.
+    pub niit_threshold: f64,
+}
+
+// The rate, in percent, applies to income above the threshold, up to the next bracket's
+// threshold.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaxBracket {
+    pub rate: f64,
+    pub threshold: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "Unknown output format '{}'. Expected table, csv or json.",
+                s
+            )),
+        }
+    }
+}
+
+// How market returns are generated for each simulated path.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub enum GrowthModel {
+    // One path per starting year in the historical data. Paths that run off the end of the data
+    // are dropped.
+    #[default]
+    HistoricalPath,
+    // Each year is drawn at random, with replacement, from the historical data.
+    Bootstrap(Bootstrap),
+    // Runs of consecutive historical years are drawn at random, wrapping around at the end of the
+    // data, and concatenated.
+    BlockBootstrap(BlockBootstrap),
+    // Lognormal stock and bond returns and an AR(1) inflation process, with correlated shocks.
+    Parametric(Parametric),
+    // The same returns every year, so every instance follows the same deterministic path. Useful
+    // for checking results against closed-form calculations.
+    Fixed(FixedReturns),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Bootstrap {
+    pub paths: usize,
+    pub seed: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockBootstrap {
+    pub paths: usize,
+    pub seed: u64,
+    pub block_years: usize,
+}
+
+// All rates are in percent. Stock and bond returns are total returns, and are real or nominal
+// according to the adjustment. Stock returns include the dividend yield, and bond returns are
+// paid entirely as interest.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FixedReturns {
+    pub stocks: f64,
+    pub bonds: f64,
+    #[serde(default)]
+    pub cash: f64,
+    pub dividend_yield: f64,
+    pub inflation: f64,
+    pub adjustment: InflationAdjustment,
+}
+
+// All rates are in percent. Bonds are treasury bonds. T-bills aren't modeled separately: they
+// yield the year's inflation, but never less than 0.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Parametric {
+    pub paths: usize,
+    pub seed: u64,
+    pub stocks: ReturnDistribution,
+    pub bonds: ReturnDistribution,
+    pub inflation: InflationProcess,
+    // Correlation between the stock, bond, and inflation shocks, in that order.
+    #[serde(default = "identity_correlation")]
+    pub correlation: [[f64; 3]; 3],
+    // Needed to hold AaaBonds. Without it, they return exactly what treasury bonds do.
+    #[serde(default)]
+    pub aaabonds: Option<CorrelatedDistribution>,
+}
+
+// A return with its own shock, correlated with the stock, bond and inflation shocks.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CorrelatedDistribution {
+    #[serde(flatten)]
+    pub returns: ReturnDistribution,
+    // Correlation with the stock, bond, and inflation shocks, in that order.
+    pub correlation: [f64; 3],
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct ReturnDistribution {
+    // Arithmetic mean and standard deviation of the nominal total return.
+    pub mean: f64,
+    pub std_dev: f64,
+    // The part of the total return paid out as interest and dividends.
+    pub income_yield: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InflationProcess {
+    pub mean: f64,
+    pub std_dev: f64,
+    // Fraction of last year's deviation from the mean that carries over to this year.
+    #[serde(default)]
+    pub persistence: f64,
+}
+
+fn identity_correlation() -> [[f64; 3]; 3] {
+    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum AccountType {
     Total,
     PreTax,
@@ -66,58 +653,111 @@ pub enum AccountType {
     AfterTax,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Selects an instance, ranked by real total value, or the mean across all instances.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Measure {
     Median,
-    // TODO: FifthPercentile,
     Worst,
+    Best,
+    Mean,
+    // Between 0 and 100. Percentile(0.0) is the worst instance, and Percentile(100.0) the best.
+    Percentile(f64),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum InflationAdjustment {
     Real,
     Nominal,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ReportField {
     YearsElapsed,
+    CalendarYear,
     WorstYears,
     Value(Measure, AccountType, InflationAdjustment),
-    BondPercent(AccountType),
-    CapGainsPercent,
+    // The instance is chosen by value, like the Value field.
+    BondPercent(Measure, AccountType),
+    CapGainsPercent(Measure),
     StartingYear(Measure),
-    InterestAndDividends,
-    StocksSold,
-    StocksBought,
-    CapitalGains,
+    InterestAndDividends(Measure),
+    StocksSold(Measure),
+    StocksBought(Measure),
+    CapitalGains(Measure),
     SuccessRate,
-    RequiredMinimumDistribution,
-    Taxes,
-    Cash,
-    ExpensesDoubleCheck,
-    TaxRate,
+    RequiredMinimumDistribution(Measure),
+    Taxes(Measure),
+    SocialSecurity(Measure),
+    RothConversion(Measure),
+    Cash(Measure),
+    ExpensesDoubleCheck(Measure),
+    TaxRate(Measure),
+    // Real spending, ranked by itself rather than by value.
+    Spending(Measure),
+    // Years until the portfolio ran out, counting the years elapsed for those that have not.
+    // Ranked by itself, and rounded for the mean.
+    YearsUntilDepletion(Measure),
+    // Real spending that could not be paid so far, and the number of years it fell short. Ranked
+    // by themselves, so Percentile(90) is larger than nine in ten paths. Worst is the largest,
+    // and Best the smallest.
+    ShortfallTotal(Measure),
+    ShortfallYears(Measure),
+    // The largest fall in real total value from its highest point so far, and the ulcer index:
+    // the root mean square of that fall each year. Ranked like the shortfall fields.
+    MaxDrawdown(Measure),
+    UlcerIndex(Measure),
+    // The constant real withdrawal, as a fraction of the value at the start of the first
+    // withdrawal year, that would leave exactly nothing after the withdrawal years so far. It
+    // depends only on each path's returns and the withdrawal phases' bond percent, and ignores
+    // taxes and cash. Ranked by itself.
+    PerfectWithdrawalRate(Measure),
+    // Starting year, or path number for random paths, of the path with the perfect withdrawal
+    // rate selected by the measure. For the mean, the path whose rate is closest to it.
+    PerfectWithdrawalRateStartingYear(Measure),
+    // Standard deviation of the value across all paths.
+    ValueStdDev(AccountType, InflationAdjustment),
 }
 
 impl ReportField {
     pub fn title(&self) -> String {
         match self {
             ReportField::YearsElapsed => String::from("Year"),
+            ReportField::CalendarYear => String::from("Calendar\nyear"),
             ReportField::WorstYears => String::from("Worst Years"),
-            ReportField::CapGainsPercent => String::from("CG%"),
-            ReportField::BondPercent(a) => format!("Bond%,\n{:#?}", a),
+            ReportField::CapGainsPercent(m) => format!("CG%,\n{:?}", m),
+            ReportField::BondPercent(m, a) => format!("Bond%,\n{:?},\n{:#?}", m, a),
             ReportField::Value(_, _, _) => format!("{:#?}", self),
             ReportField::StartingYear(m) => format!("Starting\nyear of\n{:#?}", m),
-            ReportField::InterestAndDividends => String::from("I&D"),
-            ReportField::StocksSold => String::from("Sold"),
-            ReportField::StocksBought => String::from("Bought"),
-            ReportField::CapitalGains => String::from("Cap\nGains"),
+            ReportField::InterestAndDividends(m) => format!("I&D,\n{:?}", m),
+            ReportField::StocksSold(m) => format!("Sold,\n{:?}", m),
+            ReportField::StocksBought(m) => format!("Bought,\n{:?}", m),
+            ReportField::CapitalGains(m) => format!("Cap\nGains,\n{:?}", m),
             ReportField::SuccessRate => String::from("Success\nrate"),
-            ReportField::RequiredMinimumDistribution => String::from("RMD"),
-            ReportField::Taxes => String::from("Taxes"),
-            ReportField::Cash => String::from("Cash"),
-            ReportField::ExpensesDoubleCheck => String::from("Calculated\nExpenses"),
-            ReportField::TaxRate => String::from("Tax\nrate"),
+            ReportField::RequiredMinimumDistribution(m) => format!("RMD,\n{:?}", m),
+            ReportField::Taxes(m) => format!("Taxes,\n{:?}", m),
+            ReportField::SocialSecurity(m) => format!("Social\nSecurity,\n{:?}", m),
+            ReportField::RothConversion(m) => format!("Roth\nconversion,\n{:?}", m),
+            ReportField::Cash(m) => format!("Cash,\n{:?}", m),
+            ReportField::ExpensesDoubleCheck(m) => format!("Calculated\nExpenses,\n{:?}", m),
+            ReportField::TaxRate(m) => format!("Tax\nrate,\n{:?}", m),
+            ReportField::Spending(m) => format!("Spending,\n{:?}", m),
+            ReportField::YearsUntilDepletion(m) => format!("Years until\ndepletion,\n{:?}", m),
+            ReportField::ShortfallTotal(m) => format!("Shortfall,\n{:?}", m),
+            ReportField::ShortfallYears(m) => format!("Shortfall\nyears,\n{:?}", m),
+            ReportField::MaxDrawdown(m) => format!("Max\ndrawdown,\n{:?}", m),
+            ReportField::UlcerIndex(m) => format!("Ulcer\nindex,\n{:?}", m),
+            ReportField::PerfectWithdrawalRate(m) => format!("Perfect\nwithdrawal\nrate,\n{:?}", m),
+            ReportField::PerfectWithdrawalRateStartingYear(m) => {
+                format!("Starting\nyear of\nPWR,\n{:?}", m)
+            }
+            ReportField::ValueStdDev(a, inf) => format!("Value\nstd dev,\n{:?},\n{:?}", a, inf),
+        }
+    }
+    // Single-line name, used as the column header in machine-readable output.
+    pub fn key(&self) -> String {
+        match self {
+            ReportField::Value(_, _, _) => format!("{:?}", self),
+            _ => self.title().replace(",\n", ", ").replace('\n', " "),
         }
     }
 }
@@ -0,0 +1,54 @@
+use crate::validate::ConfigError;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    // Tried to sell more than an asset, account or instance holds.
+    InsufficientFunds { requested: f64, available: f64 },
+    // A negative balance, a cost basis above value, or asset class weights that don't add up.
+    InvalidAllocation(String),
+    Config(ConfigError),
+    // A year of returns with nothing for a series the portfolio holds.
+    MissingReturns { series: String, year: i32 },
+    // A solver target that no value can meet, or that every value meets.
+    NoSolution(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InsufficientFunds {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Trying to sell {} but only have {}",
+                requested, available
+            ),
+            Error::InvalidAllocation(s) => write!(f, "Invalid allocation: {}", s),
+            Error::Config(e) => e.fmt(f),
+            Error::MissingReturns { series, year } => {
+                write!(f, "No returns for {} in year {}", series, year)
+            }
+            Error::NoSolution(s) => write!(f, "No solution: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
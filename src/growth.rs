@@ -0,0 +1,491 @@
+use crate::asset::AssetReturn;
+use crate::config::{
+    BlockBootstrap, Bootstrap, FixedReturns, GrowthModel, InflationAdjustment, Parametric,
+    ReturnDistribution,
+};
+use crate::histret::HistoricalYear;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::StandardNormal;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+// The sequence of market returns experienced by a single Instance.
+#[derive(Debug)]
+pub struct Path {
+    // Starting year for historical paths, or a 1-based path number for random ones.
+    pub id: i32,
+    pub returns: Vec<HistoricalYear>,
+}
+
+// Generates the paths for a plan lasting the given number of years. Historical models draw from
+// the given data. Random models are seeded, so the same config always produces the same paths.
+// Fails rather than generating nothing, or sampling from nothing.
+pub fn paths(m: &GrowthModel, data: &[HistoricalYear], years: usize) -> Result<Vec<Path>, String> {
+    match m {
+        GrowthModel::HistoricalPath => Ok(historical_paths(data, years)),
+        GrowthModel::Bootstrap(b) => {
+            check_paths(b.paths)?;
+            check_data(data)?;
+            Ok(bootstrap_paths(b, data, years))
+        }
+        GrowthModel::BlockBootstrap(b) => {
+            check_paths(b.paths)?;
+            check_data(data)?;
+            if b.block_years == 0 {
+                return Err(String::from("block_years must be positive"));
+            }
+            Ok(block_bootstrap_paths(b, data, years))
+        }
+        GrowthModel::Parametric(p) => {
+            check_paths(p.paths)?;
+            parametric_paths(p, years)
+        }
+        GrowthModel::Fixed(f) => Ok(vec![fixed_path(f, years)]),
+    }
+}
+
+fn check_paths(paths: usize) -> Result<(), String> {
+    if paths == 0 {
+        Err(String::from("paths must be positive"))
+    } else {
+        Ok(())
+    }
+}
+
+fn check_data(data: &[HistoricalYear]) -> Result<(), String> {
+    if data.is_empty() {
+        Err(String::from("There is no historical data to sample from"))
+    } else {
+        Ok(())
+    }
+}
+
+fn historical_paths(data: &[HistoricalYear], years: usize) -> Vec<Path> {
+    (0..data.len())
+        .map(|i| Path {
+            id: data[i].year,
+            returns: data[i..(i + years).min(data.len())].to_vec(),
+        })
+        .collect()
+}
+
+fn bootstrap_paths(b: &Bootstrap, data: &[HistoricalYear], years: usize) -> Vec<Path> {
+    let mut rng = ChaCha8Rng::seed_from_u64(b.seed);
+    (0..b.paths)
+        .map(|p| Path {
+            id: p as i32 + 1,
+            returns: (0..years)
+                .map(|_| data[rng.gen_range(0..data.len())].clone())
+                .collect(),
+        })
+        .collect()
+}
+
+fn block_bootstrap_paths(b: &BlockBootstrap, data: &[HistoricalYear], years: usize) -> Vec<Path> {
+    let mut rng = ChaCha8Rng::seed_from_u64(b.seed);
+    (0..b.paths)
+        .map(|p| {
+            let mut returns = Vec::with_capacity(years);
+            while returns.len() < years {
+                let start = rng.gen_range(0..data.len());
+                for j in 0..b.block_years.min(years - returns.len()) {
+                    returns.push(data[(start + j) % data.len()].clone());
+                }
+            }
+            Path {
+                id: p as i32 + 1,
+                returns,
+            }
+        })
+        .collect()
+}
+
+fn parametric_paths(p: &Parametric, years: usize) -> Result<Vec<Path>, String> {
+    let l = try_cholesky(&p.correlation)
+        .ok_or_else(|| String::from("correlation must be positive definite"))?;
+    // With their own distribution, Aaa bonds get a fourth shock. Otherwise, only three are drawn,
+    // so that paths are the same as before they could be modeled.
+    let aaa = match &p.aaabonds {
+        Some(a) => Some((
+            &a.returns,
+            try_cholesky(&with_row(&p.correlation, &a.correlation)).ok_or_else(|| {
+                String::from("correlation with aaabonds must be positive definite")
+            })?,
+        )),
+        None => None,
+    };
+    let mut rng = ChaCha8Rng::seed_from_u64(p.seed);
+    let inflation_mean = p.inflation.mean / 100.0;
+    let inflation_std_dev = p.inflation.std_dev / 100.0;
+    let mut normal = || -> f64 { rng.sample(StandardNormal) };
+    Ok((0..p.paths)
+        .map(|i| {
+            let mut inflation = inflation_mean;
+            let mut returns = Vec::with_capacity(years);
+            for _ in 0..years {
+                // Stocks, bonds, inflation and Aaa bonds.
+                let (z, aaabonds) = match &aaa {
+                    Some((d, l)) => {
+                        let z = correlate(l, &[normal(), normal(), normal(), normal()]);
+                        (z, *d)
+                    }
+                    None => {
+                        let z = correlate(&l, &[normal(), normal(), normal()]);
+                        ([z[0], z[1], z[2], z[1]], &p.bonds)
+                    }
+                };
+                inflation = inflation_mean
+                    + p.inflation.persistence * (inflation - inflation_mean)
+                    + inflation_std_dev * z[2];
+                returns.push(HistoricalYear {
+                    year: 0,
+                    stocks: lognormal_return(&p.stocks, z[0]),
+                    tbonds: lognormal_return(&p.bonds, z[1]),
+                    // Cash keeps up with inflation, but never has a negative yield.
+                    tbills: AssetReturn {
+                        cg: 0.0,
+                        id: inflation.max(0.0),
+                    },
+                    aaabonds: lognormal_return(aaabonds, z[3]),
+                    inflation,
+                    series: BTreeMap::new(),
+                });
+            }
+            Path {
+                id: i as i32 + 1,
+                returns,
+            }
+        })
+        .collect())
+}
+
+// Adds a row and column to a correlation matrix, with the given correlations with the others.
+pub fn with_row(c: &[[f64; 3]; 3], row: &[f64; 3]) -> [[f64; 4]; 4] {
+    let mut m = [[1.0; 4]; 4];
+    for i in 0..3 {
+        m[i][..3].copy_from_slice(&c[i]);
+        m[i][3] = row[i];
+        m[3][i] = row[i];
+    }
+    m
+}
+
+fn fixed_path(f: &FixedReturns, years: usize) -> Path {
+    let inflation = f.inflation / 100.0;
+    let nominal = |r: f64| match f.adjustment {
+        InflationAdjustment::Real => (1.0 + r / 100.0) * (1.0 + inflation) - 1.0,
+        InflationAdjustment::Nominal => r / 100.0,
+    };
+    let id = f.dividend_yield / 100.0;
+    let stocks = AssetReturn {
+        cg: nominal(f.stocks) - id,
+        id,
+    };
+    let bonds = AssetReturn {
+        cg: 0.0,
+        id: nominal(f.bonds),
+    };
+    let tbills = AssetReturn {
+        cg: 0.0,
+        id: nominal(f.cash).max(0.0),
+    };
+    Path {
+        id: 1,
+        returns: vec![
+            HistoricalYear {
+                year: 0,
+                stocks,
+                tbonds: bonds.clone(),
+                tbills,
+                aaabonds: bonds,
+                inflation,
+                series: BTreeMap::new(),
+            };
+            years
+        ],
+    }
+}
+
+// Converts a standard normal shock into a return whose arithmetic mean and standard deviation
+// match the distribution.
+fn lognormal_return(d: &ReturnDistribution, z: f64) -> AssetReturn {
+    let m = 1.0 + d.mean / 100.0;
+    let s = d.std_dev / 100.0;
+    let sigma_sq = (1.0 + s * s / (m * m)).ln();
+    let mu = m.ln() - sigma_sq / 2.0;
+    let total = (mu + sigma_sq.sqrt() * z).exp() - 1.0;
+    let id = d.income_yield / 100.0;
+    AssetReturn {
+        cg: (total - id).max(-1.0),
+        id,
+    }
+}
+
+// Lower-triangular Cholesky factor of a correlation matrix, or None if the matrix is not positive
+// definite.
+pub fn try_cholesky<const N: usize>(c: &[[f64; N]; N]) -> Option<[[f64; N]; N]> {
+    let mut l = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let d = c[i][i] - sum;
+                if d <= 0.0 || d.is_nan() {
+                    return None;
+                }
+                l[i][j] = d.sqrt();
+            } else {
+                l[i][j] = (c[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+fn correlate<const N: usize>(l: &[[f64; N]; N], n: &[f64; N]) -> [f64; N] {
+    let mut z = [0.0; N];
+    for i in 0..N {
+        z[i] = (0..=i).map(|k| l[i][k] * n[k]).sum();
+    }
+    z
+}
+
+#[cfg(test)]
+mod growth_tests {
+    #[cfg(test)]
+    use crate::assert_eq_decimal_places;
+    use crate::config::{CorrelatedDistribution, InflationProcess};
+    use crate::growth::*;
+    use crate::histret::RETURNS;
+
+    fn parametric(seed: u64) -> Parametric {
+        Parametric {
+            paths: 500,
+            seed,
+            stocks: ReturnDistribution {
+                mean: 10.0,
+                std_dev: 20.0,
+                income_yield: 2.0,
+            },
+            bonds: ReturnDistribution {
+                mean: 5.0,
+                std_dev: 8.0,
+                income_yield: 5.0,
+            },
+            inflation: InflationProcess {
+                mean: 3.0,
+                std_dev: 4.0,
+                persistence: 0.0,
+            },
+            correlation: [[1.0, 0.2, -0.1], [0.2, 1.0, -0.3], [-0.1, -0.3, 1.0]],
+            aaabonds: None,
+        }
+    }
+
+    fn mean(x: &[f64]) -> f64 {
+        x.iter().sum::<f64>() / x.len() as f64
+    }
+
+    #[test]
+    fn historical() {
+        let p = paths(&GrowthModel::HistoricalPath, &RETURNS, 30).unwrap();
+        assert_eq!(p.len(), RETURNS.len());
+        assert_eq!(p[0].id, 1928);
+        assert_eq!(p[0].returns.len(), 30);
+        assert_eq!(p[0].returns[1].year, 1929);
+        assert_eq!(p[p.len() - 1].returns.len(), 1);
+    }
+
+    #[test]
+    fn bootstrap() {
+        let b = Bootstrap { paths: 10, seed: 1 };
+        let p = paths(&GrowthModel::Bootstrap(b), &RETURNS, 40).unwrap();
+        assert_eq!(p.len(), 10);
+        assert_eq!(p[9].id, 10);
+        assert!(p.iter().all(|x| x.returns.len() == 40));
+        assert!(p[0]
+            .returns
+            .iter()
+            .all(|y| y.year >= 1928 && y.year <= 2020));
+    }
+
+    #[test]
+    fn bootstrap_is_reproducible() {
+        let a = paths(
+            &GrowthModel::Bootstrap(Bootstrap { paths: 5, seed: 7 }),
+            &RETURNS,
+            20,
+        )
+        .unwrap();
+        let b = paths(
+            &GrowthModel::Bootstrap(Bootstrap { paths: 5, seed: 7 }),
+            &RETURNS,
+            20,
+        )
+        .unwrap();
+        let c = paths(
+            &GrowthModel::Bootstrap(Bootstrap { paths: 5, seed: 8 }),
+            &RETURNS,
+            20,
+        )
+        .unwrap();
+        let years = |p: &Vec<Path>| -> Vec<i32> {
+            p.iter()
+                .flat_map(|x| x.returns.iter().map(|y| y.year))
+                .collect()
+        };
+        assert_eq!(years(&a), years(&b));
+        assert_ne!(years(&a), years(&c));
+    }
+
+    #[test]
+    fn block_bootstrap() {
+        let b = BlockBootstrap {
+            paths: 20,
+            seed: 3,
+            block_years: 10,
+        };
+        let p = paths(&GrowthModel::BlockBootstrap(b), &RETURNS, 25).unwrap();
+        assert_eq!(p.len(), 20);
+        for path in &p {
+            assert_eq!(path.returns.len(), 25);
+            // Within a block, years are consecutive, wrapping from 2020 back to 1928.
+            for block in path.returns.chunks(10) {
+                for w in block.windows(2) {
+                    assert!(w[1].year == w[0].year + 1 || (w[0].year == 2020 && w[1].year == 1928));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_paths() {
+        let b = Bootstrap { paths: 0, seed: 1 };
+        assert!(paths(&GrowthModel::Bootstrap(b), &RETURNS, 10).is_err());
+        let b = BlockBootstrap {
+            paths: 10,
+            seed: 1,
+            block_years: 0,
+        };
+        assert!(paths(&GrowthModel::BlockBootstrap(b), &RETURNS, 10).is_err());
+        let b = Bootstrap { paths: 10, seed: 1 };
+        assert!(paths(&GrowthModel::Bootstrap(b), &[], 10).is_err());
+    }
+
+    #[test]
+    fn parametric_moments() {
+        let p = paths(&GrowthModel::Parametric(parametric(42)), &RETURNS, 40).unwrap();
+        assert_eq!(p.len(), 500);
+        let years: Vec<&HistoricalYear> = p.iter().flat_map(|x| x.returns.iter()).collect();
+        let stocks: Vec<f64> = years.iter().map(|y| y.stocks.cg + y.stocks.id).collect();
+        let bonds: Vec<f64> = years.iter().map(|y| y.tbonds.cg + y.tbonds.id).collect();
+        let inflation: Vec<f64> = years.iter().map(|y| y.inflation).collect();
+        assert_eq_decimal_places!(mean(&stocks), 0.10, 2);
+        assert_eq_decimal_places!(mean(&bonds), 0.05, 2);
+        assert_eq_decimal_places!(mean(&inflation), 0.03, 2);
+        assert!(years.iter().all(|y| y.stocks.id == 0.02));
+    }
+
+    #[test]
+    fn parametric_is_reproducible() {
+        let a = paths(&GrowthModel::Parametric(parametric(1)), &RETURNS, 10).unwrap();
+        let b = paths(&GrowthModel::Parametric(parametric(1)), &RETURNS, 10).unwrap();
+        assert_eq!(a[17].returns[3].stocks.cg, b[17].returns[3].stocks.cg);
+        assert_eq!(a[17].returns[3].inflation, b[17].returns[3].inflation);
+    }
+
+    #[test]
+    fn parametric_aaabonds() {
+        let p = paths(&GrowthModel::Parametric(parametric(7)), &RETURNS, 10).unwrap();
+        assert!(p[0].returns.iter().all(|y| y.aaabonds.cg == y.tbonds.cg));
+
+        let mut a = parametric(7);
+        a.aaabonds = Some(CorrelatedDistribution {
+            returns: ReturnDistribution {
+                mean: 6.0,
+                std_dev: 9.0,
+                income_yield: 6.0,
+            },
+            correlation: [0.3, 0.8, -0.3],
+        });
+        let a = paths(&GrowthModel::Parametric(a), &RETURNS, 40).unwrap();
+        let years: Vec<&HistoricalYear> = a.iter().flat_map(|x| x.returns.iter()).collect();
+        let aaa: Vec<f64> = years
+            .iter()
+            .map(|y| y.aaabonds.cg + y.aaabonds.id)
+            .collect();
+        assert_eq_decimal_places!(mean(&aaa), 0.06, 2);
+        assert!(years.iter().all(|y| y.aaabonds.id == 0.06));
+        assert!(years.iter().any(|y| y.aaabonds.cg != y.tbonds.cg));
+    }
+
+    #[test]
+    fn aaabonds_correlation_not_positive_definite() {
+        let mut p = parametric(1);
+        p.aaabonds = Some(CorrelatedDistribution {
+            returns: ReturnDistribution {
+                mean: 5.0,
+                std_dev: 8.0,
+                income_yield: 5.0,
+            },
+            correlation: [1.0, 1.0, 1.0],
+        });
+        assert!(paths(&GrowthModel::Parametric(p), &RETURNS, 10).is_err());
+    }
+
+    #[test]
+    fn fixed() {
+        let f = FixedReturns {
+            stocks: 5.0,
+            bonds: 2.0,
+            cash: 0.0,
+            dividend_yield: 1.5,
+            inflation: 3.0,
+            adjustment: InflationAdjustment::Real,
+        };
+        let p = paths(&GrowthModel::Fixed(f), &RETURNS, 10).unwrap();
+        assert_eq!(p.len(), 1);
+        assert_eq!(p[0].returns.len(), 10);
+        let y = &p[0].returns[9];
+        assert_eq_decimal_places!(y.stocks.cg + y.stocks.id, 1.05 * 1.03 - 1.0, 10);
+        assert_eq!(y.stocks.id, 0.015);
+        assert_eq_decimal_places!(y.tbonds.id, 1.02 * 1.03 - 1.0, 10);
+        assert_eq!(y.tbonds.cg, 0.0);
+        assert_eq_decimal_places!(y.tbills.id, 0.03, 10);
+        assert_eq!(y.inflation, 0.03);
+
+        let f = FixedReturns {
+            stocks: 5.0,
+            bonds: 2.0,
+            cash: 1.0,
+            dividend_yield: 1.5,
+            inflation: 3.0,
+            adjustment: InflationAdjustment::Nominal,
+        };
+        let y = &paths(&GrowthModel::Fixed(f), &RETURNS, 1).unwrap()[0].returns[0];
+        assert_eq_decimal_places!(y.stocks.cg, 0.035, 10);
+        assert_eq!(y.tbonds.id, 0.02);
+        assert_eq!(y.tbills.id, 0.01);
+    }
+
+    #[test]
+    fn cholesky_test() {
+        let c = [[1.0, 0.5, 0.0], [0.5, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let l = try_cholesky(&c).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                let x: f64 = (0..3).map(|k| l[i][k] * l[j][k]).sum();
+                assert_eq_decimal_places!(x, c[i][j], 10);
+            }
+        }
+    }
+
+    #[test]
+    fn correlation_not_positive_definite() {
+        let mut p = parametric(1);
+        p.correlation = [[1.0, 2.0, 0.0], [2.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert!(paths(&GrowthModel::Parametric(p), &RETURNS, 10).is_err());
+    }
+}
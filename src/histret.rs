@@ -1,20 +1,50 @@
 #[macro_use]
 mod sp500;
+#[macro_use]
+mod tbill;
 mod tbond;
 #[macro_use]
 mod inflation;
 mod aaabond;
 
 use crate::asset::AssetReturn;
+use crate::config::{AssetClass, OutputFormat};
+use crate::error::Error;
+use crate::report::{write_rows, ReportValue};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HistoricalYear {
     pub year: i32,
     pub stocks: AssetReturn,
     pub tbonds: AssetReturn,
+    pub tbills: AssetReturn,
     pub aaabonds: AssetReturn,
     pub inflation: f64,
+    // Returns of AssetClass::Series, which only come from data files.
+    pub series: BTreeMap<String, AssetReturn>,
+}
+
+impl HistoricalYear {
+    // Fails for a series this year has no returns for, as in generated years, which have none.
+    pub fn asset_return(&self, c: &AssetClass) -> crate::error::Result<&AssetReturn> {
+        match c {
+            AssetClass::UsStocks => Ok(&self.stocks),
+            AssetClass::TBonds => Ok(&self.tbonds),
+            AssetClass::AaaBonds => Ok(&self.aaabonds),
+            AssetClass::TBills => Ok(&self.tbills),
+            AssetClass::Series(name) => {
+                self.series.get(name).ok_or_else(|| Error::MissingReturns {
+                    series: name.clone(),
+                    year: self.year,
+                })
+            }
+        }
+    }
 }
 
 macro_rules! hy {
@@ -23,15 +53,17 @@ macro_rules! hy {
             year: $e,
             stocks: sp500!($e),
             tbonds: bond_return_10y!($e, tbond::DATA),
+            tbills: tbill!($e),
             aaabonds: bond_return_10y!($e, aaabond::DATA),
             inflation: inflation!($e),
+            series: BTreeMap::new(),
         }
     };
 }
 
 #[derive(Debug)]
 pub struct BondRate {
-    year: i32,
+    pub year: i32,
     pub rate: f64,
 }
 
@@ -42,25 +74,28 @@ pub struct BondRate {
 // change on a bond with a coupon rate of 3.17%, when the interest rate goes to 3.45%."
 #[macro_export]
 macro_rules! bond_return_10y {
-    ($e:expr, $data:expr) => {
+    ($e:expr, $data:expr) => {{
+        let data = &$data;
+        let i = ($e - data[0].year) as usize;
+        let (r0, r1) = (data[i - 1].rate, data[i].rate);
         AssetReturn {
-            cg: ($data[$e - 1928].rate
-                + ($data[$e - 1927].rate - $data[$e - 1928].rate)
-                    / ((1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)
-                        * (1.0 + $data[$e - 1927].rate)))
-                / $data[$e - 1927].rate
+            cg: (r0
+                + (r1 - r0)
+                    / ((1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)
+                        * (1.0 + r1)))
+                / r1
                 - 1.0,
-            id: $data[$e - 1928].rate,
+            id: r0,
         }
-    };
+    }};
 }
 
 #[rustfmt::skip]
@@ -87,7 +122,215 @@ pub const RETURNS: [HistoricalYear; 93] = [
     hy!(2020),
 ];
 
+// A row of a data file, mapping column names to values.
+type Row = BTreeMap<String, f64>;
+
+// Columns of the built-in data. Files may use any other column name for a new series.
+const INFLATION: &str = "Inflation";
+const BUILT_IN: [&str; 5] = ["UsStocks", "TBonds", "AaaBonds", "TBills", INFLATION];
+
+// Combines the built-in returns with those in data files, which are either CSV with a header
+// row, or a YAML list of maps. Every row has a "year", and the other columns are in percent:
+// - "Inflation" is the change in CPI over the year.
+// - Any other name is the total return of an asset class, such as "UsStocks" or "IntlStocks".
+// - "<name> yield" is the part of that return paid as interest or dividends. Defaults to 0, so the
+//   whole return is capital gains.
+// Values in later files replace earlier ones, and both replace the built-in data. Years after
+// 2020 must have every built-in column. Only years with returns for every series are kept, and
+// they must be consecutive.
+pub fn load(files: &[PathBuf]) -> Result<Vec<HistoricalYear>, String> {
+    let mut rows = Vec::new();
+    for f in files {
+        let text =
+            fs::read_to_string(f).map_err(|e| format!("Failed to read {}: {}", f.display(), e))?;
+        let parsed = match f.extension().and_then(|e| e.to_str()) {
+            Some("csv") => parse_csv(&text),
+            Some("yaml") | Some("yml") => parse_yaml(&text),
+            _ => Err("expected a .csv, .yaml or .yml file".to_string()),
+        };
+        rows.extend(parsed.map_err(|e| format!("{}: {}", f.display(), e))?);
+    }
+    merge(&rows)
+}
+
+// Writes returns in the same columns load() reads, so the output can be edited and loaded back.
+pub fn write(
+    years: &[HistoricalYear],
+    format: &OutputFormat,
+    w: &mut dyn io::Write,
+) -> io::Result<()> {
+    let series: Vec<&String> = years
+        .first()
+        .map_or(Vec::new(), |y| y.series.keys().collect());
+    let mut keys = vec![String::from("year")];
+    for name in BUILT_IN.iter().filter(|c| **c != INFLATION) {
+        keys.push(name.to_string());
+        keys.push(format!("{} yield", name));
+    }
+    keys.push(INFLATION.to_string());
+    for name in &series {
+        keys.push(name.to_string());
+        keys.push(format!("{} yield", name));
+    }
+    let percent = |r: &AssetReturn| {
+        [
+            ReportValue::Number((r.cg + r.id) * 100.0),
+            ReportValue::Number(r.id * 100.0),
+        ]
+    };
+    let rows: Vec<Vec<ReportValue>> = years
+        .iter()
+        .map(|y| {
+            let mut row = vec![ReportValue::Integer(y.year as i64)];
+            for r in [&y.stocks, &y.tbonds, &y.aaabonds, &y.tbills] {
+                row.extend(percent(r));
+            }
+            row.push(ReportValue::Number(y.inflation * 100.0));
+            for name in &series {
+                row.extend(percent(&y.series[*name]));
+            }
+            row
+        })
+        .collect();
+    write_rows(&keys, &keys, &rows, format, w)
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Row>, String> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let mut row = Row::new();
+        for (h, v) in headers.iter().zip(record.iter()) {
+            // An empty cell means there is no data for that year.
+            if v.trim().is_empty() {
+                continue;
+            }
+            let v = v
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: {} is not a number: {:?}", i + 2, h.trim(), v))?;
+            row.insert(h.trim().to_string(), v);
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn parse_yaml(text: &str) -> Result<Vec<Row>, String> {
+    serde_yaml::from_str(text).map_err(|e| e.to_string())
+}
+
+fn merge(rows: &[Row]) -> Result<Vec<HistoricalYear>, String> {
+    let mut by_year: BTreeMap<i32, Row> = BTreeMap::new();
+    for row in rows {
+        let year = *row.get("year").ok_or("Found a row without a year")?;
+        if year.fract() != 0.0 {
+            return Err(format!("{} is not a year", year));
+        }
+        let values = by_year.entry(year as i32).or_default();
+        for (k, v) in row.iter().filter(|(k, _)| *k != "year") {
+            values.insert(k.clone(), *v);
+        }
+    }
+    let mut series = BTreeSet::new();
+    for (year, row) in &by_year {
+        for k in row.keys() {
+            match k.strip_suffix(" yield") {
+                Some(name) if !row.contains_key(name) => {
+                    return Err(format!("{} has {} but no total return", year, k))
+                }
+                Some(_) => (),
+                None if BUILT_IN.contains(&k.as_str()) => (),
+                None => {
+                    series.insert(k.clone());
+                }
+            }
+        }
+    }
+
+    let mut data: BTreeMap<i32, HistoricalYear> =
+        RETURNS.iter().map(|y| (y.year, y.clone())).collect();
+    for (&year, row) in &by_year {
+        if !data.contains_key(&year) {
+            let missing: Vec<&str> = BUILT_IN
+                .iter()
+                .copied()
+                .filter(|c| !row.contains_key(*c))
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!("{} is missing {}", year, missing.join(", ")));
+            }
+        }
+        let y = data.entry(year).or_insert_with(|| HistoricalYear {
+            year,
+            stocks: AssetReturn { cg: 0.0, id: 0.0 },
+            tbonds: AssetReturn { cg: 0.0, id: 0.0 },
+            tbills: AssetReturn { cg: 0.0, id: 0.0 },
+            aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
+            inflation: 0.0,
+            series: BTreeMap::new(),
+        });
+        for (name, r) in [
+            ("UsStocks", &mut y.stocks),
+            ("TBonds", &mut y.tbonds),
+            ("AaaBonds", &mut y.aaabonds),
+            ("TBills", &mut y.tbills),
+        ] {
+            if let Some(ret) = asset_return(year, row, name)? {
+                *r = ret;
+            }
+        }
+        if let Some(i) = row.get(INFLATION) {
+            y.inflation = i / 100.0;
+        }
+        for name in &series {
+            if let Some(ret) = asset_return(year, row, name)? {
+                y.series.insert(name.clone(), ret);
+            }
+        }
+    }
+
+    let years: Vec<HistoricalYear> = data
+        .into_values()
+        .filter(|y| y.series.len() == series.len())
+        .collect();
+    if years.is_empty() {
+        return Err("No year has returns for every series".to_string());
+    }
+    for w in years.windows(2) {
+        if w[1].year != w[0].year + 1 {
+            return Err(format!(
+                "Returns are missing between {} and {}",
+                w[0].year, w[1].year
+            ));
+        }
+    }
+    Ok(years)
+}
+
+fn asset_return(year: i32, row: &Row, name: &str) -> Result<Option<AssetReturn>, String> {
+    let total = match row.get(name) {
+        Some(t) => *t,
+        None => return Ok(None),
+    };
+    let income = row.get(&format!("{} yield", name)).copied().unwrap_or(0.0);
+    if total <= -100.0 {
+        return Err(format!("{} return for {} is {}%", name, year, total));
+    }
+    if income < 0.0 {
+        return Err(format!("{} yield for {} is negative", name, year));
+    }
+    Ok(Some(AssetReturn {
+        cg: (total - income) / 100.0,
+        id: income / 100.0,
+    }))
+}
+
 mod historical_returns_tests {
+    #[cfg(test)]
+    use crate::assert_eq_decimal_places;
     #[cfg(test)]
     use crate::asset::Asset;
     #[cfg(test)]
@@ -98,16 +341,108 @@ mod historical_returns_tests {
         let mut stonks = Asset::new(100.0);
         let mut tbonds = Asset::new(100.0);
         let mut cbonds = Asset::new(100.0);
-        for i in 0..93 {
-            let is = stonks.grow(&RETURNS[i].stocks, 0.0);
+        let mut tbills = Asset::new(100.0);
+        for r in RETURNS.iter() {
+            let is = stonks.grow(&r.stocks, 0.0);
             stonks.invest(is);
-            let ib = tbonds.grow(&RETURNS[i].tbonds, 0.0);
+            let ib = tbonds.grow(&r.tbonds, 0.0);
             tbonds.invest(ib);
-            let ic = cbonds.grow(&RETURNS[i].aaabonds, 0.0);
+            let ic = cbonds.grow(&r.aaabonds, 0.0);
             cbonds.invest(ic);
+            let it = tbills.grow(&r.tbills, 0.0);
+            tbills.invest(it);
         }
         assert_eq!((100.0 * stonks.value).round() / 100.0, 592_868.15);
         assert_eq!((100.0 * tbonds.value).round() / 100.0, 8_920.90);
         assert_eq!((100.0 * cbonds.value).round() / 100.0, 19_858.51);
+        assert_eq!((100.0 * tbills.value).round() / 100.0, 2_098.25);
+    }
+
+    #[cfg(test)]
+    fn row(values: &[(&str, f64)]) -> Row {
+        values.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn load_built_in() {
+        let data = load(&[]).unwrap();
+        assert_eq!(data.len(), RETURNS.len());
+        assert_eq!(data[0].year, 1928);
+        assert_eq!(data[92].year, 2020);
+    }
+
+    #[test]
+    fn parse_csv_rows() {
+        let rows = parse_csv("year,UsStocks,IntlStocks\n2019,30,\n2020,18,8\n").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], row(&[("year", 2019.0), ("UsStocks", 30.0)]));
+        assert_eq!(
+            rows[1],
+            row(&[("year", 2020.0), ("UsStocks", 18.0), ("IntlStocks", 8.0)])
+        );
+        assert!(parse_csv("year,UsStocks\n2020,abc\n").is_err());
+    }
+
+    #[test]
+    fn extend_and_replace() {
+        let data = merge(&[
+            row(&[
+                ("year", 2021.0),
+                ("UsStocks", 28.0),
+                ("UsStocks yield", 1.5),
+                ("TBonds", -4.0),
+                ("TBonds yield", 1.0),
+                ("AaaBonds", -1.0),
+                ("TBills", 0.05),
+                ("TBills yield", 0.05),
+                ("Inflation", 7.0),
+            ]),
+            row(&[("year", 2020.0), ("UsStocks", 10.0)]),
+        ])
+        .unwrap();
+        assert_eq!(data.len(), RETURNS.len() + 1);
+        let y2020 = &data[data.len() - 2];
+        assert_eq_decimal_places!(y2020.stocks.cg, 0.1, 6);
+        assert_eq!(y2020.stocks.id, 0.0);
+        assert_eq!(y2020.tbonds.cg, RETURNS[92].tbonds.cg);
+        let y2021 = &data[data.len() - 1];
+        assert_eq!(y2021.year, 2021);
+        assert_eq_decimal_places!(y2021.stocks.cg, 0.265, 6);
+        assert_eq_decimal_places!(y2021.stocks.id, 0.015, 6);
+        assert_eq_decimal_places!(y2021.aaabonds.cg, -0.01, 6);
+        assert_eq_decimal_places!(y2021.inflation, 0.07, 6);
+    }
+
+    #[test]
+    fn new_series() {
+        let data = merge(&[
+            row(&[("year", 2019.0), ("Reits", 28.0), ("Reits yield", 4.0)]),
+            row(&[("year", 2020.0), ("Reits", -5.0), ("Reits yield", 4.0)]),
+        ])
+        .unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].year, 2019);
+        let r = data[1]
+            .asset_return(&AssetClass::Series("Reits".to_string()))
+            .unwrap();
+        assert_eq_decimal_places!(r.cg, -0.09, 6);
+        assert_eq_decimal_places!(r.id, 0.04, 6);
+        // Built-in years have no series.
+        assert!(RETURNS[0]
+            .asset_return(&AssetClass::Series("Reits".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_data() {
+        assert!(merge(&[row(&[("UsStocks", 10.0)])]).is_err());
+        assert!(merge(&[row(&[("year", 2021.0), ("UsStocks", 10.0)])]).is_err());
+        assert!(merge(&[row(&[("year", 2020.0), ("Reits yield", 4.0)])]).is_err());
+        assert!(merge(&[row(&[("year", 2020.0), ("UsStocks", -100.0)])]).is_err());
+        assert!(merge(&[
+            row(&[("year", 2000.0), ("Reits", 5.0)]),
+            row(&[("year", 2002.0), ("Reits", 5.0)]),
+        ])
+        .is_err());
     }
 }
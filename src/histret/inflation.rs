@@ -2,15 +2,16 @@ use std::fmt::Debug;
 
 #[derive(Debug)]
 pub struct Inflation {
-    year: i32,
+    pub year: i32,
     pub rate: f64,
 }
 
 #[macro_export]
 macro_rules! inflation {
-    ($e:expr) => {
-        $crate::histret::inflation::DATA[$e - 1928].rate / 100.0
-    };
+    ($e:expr) => {{
+        let data = &$crate::histret::inflation::DATA;
+        data[($e - data[0].year) as usize].rate / 100.0
+    }};
 }
 
 // From http://pages.stern.nyu.edu/~adamodar/New_Home_Page/datafile/histretSPX.html
@@ -4,22 +4,21 @@ use std::fmt::Debug;
 
 #[derive(Debug)]
 pub struct Sp500 {
-    year: i32,
+    pub year: i32,
     pub price: f64,
     pub dividend: f64,
 }
 
 #[macro_export]
 macro_rules! sp500 {
-    ($e:expr) => {
+    ($e:expr) => {{
+        let data = &$crate::histret::sp500::DATA;
+        let i = ($e - data[0].year) as usize;
         AssetReturn {
-            cg: $crate::histret::sp500::DATA[$e - 1927].price
-                / crate::histret::sp500::DATA[$e - 1928].price
-                - 1.0,
-            id: crate::histret::sp500::DATA[$e - 1927].dividend
-                / crate::histret::sp500::DATA[$e - 1928].price,
+            cg: data[i].price / data[i - 1].price - 1.0,
+            id: data[i].dividend / data[i - 1].price,
         }
-    };
+    }};
 }
 
 // From http://pages.stern.nyu.edu/~adamodar/New_Home_Page/datafile/histretSPX.html
@@ -0,0 +1,113 @@
+// Historic returns for 3-month treasury bills.
+
+use crate::histret::BondRate;
+
+#[macro_export]
+macro_rules! tbill {
+    ($e:expr) => {{
+        let data = &$crate::histret::tbill::DATA;
+        AssetReturn {
+            cg: 0.0,
+            id: data[($e - data[0].year) as usize].rate,
+        }
+    }};
+}
+
+// Average yield over each year.
+// From http://pages.stern.nyu.edu/~adamodar/New_Home_Page/datafile/histretSPX.html
+#[rustfmt::skip]
+pub const DATA: [BondRate; 93] = [
+    BondRate { year: 1928, rate: 0.0308 },
+    BondRate { year: 1929, rate: 0.0316 },
+    BondRate { year: 1930, rate: 0.0455 },
+    BondRate { year: 1931, rate: 0.0231 },
+    BondRate { year: 1932, rate: 0.0107 },
+    BondRate { year: 1933, rate: 0.0096 },
+    BondRate { year: 1934, rate: 0.0028 },
+    BondRate { year: 1935, rate: 0.0017 },
+    BondRate { year: 1936, rate: 0.0017 },
+    BondRate { year: 1937, rate: 0.0028 },
+    BondRate { year: 1938, rate: 0.0007 },
+    BondRate { year: 1939, rate: 0.0005 },
+    BondRate { year: 1940, rate: 0.0004 },
+    BondRate { year: 1941, rate: 0.0013 },
+    BondRate { year: 1942, rate: 0.0034 },
+    BondRate { year: 1943, rate: 0.0038 },
+    BondRate { year: 1944, rate: 0.0038 },
+    BondRate { year: 1945, rate: 0.0038 },
+    BondRate { year: 1946, rate: 0.0038 },
+    BondRate { year: 1947, rate: 0.0057 },
+    BondRate { year: 1948, rate: 0.0102 },
+    BondRate { year: 1949, rate: 0.0110 },
+    BondRate { year: 1950, rate: 0.0117 },
+    BondRate { year: 1951, rate: 0.0148 },
+    BondRate { year: 1952, rate: 0.0167 },
+    BondRate { year: 1953, rate: 0.0189 },
+    BondRate { year: 1954, rate: 0.0096 },
+    BondRate { year: 1955, rate: 0.0166 },
+    BondRate { year: 1956, rate: 0.0256 },
+    BondRate { year: 1957, rate: 0.0323 },
+    BondRate { year: 1958, rate: 0.0178 },
+    BondRate { year: 1959, rate: 0.0326 },
+    BondRate { year: 1960, rate: 0.0305 },
+    BondRate { year: 1961, rate: 0.0227 },
+    BondRate { year: 1962, rate: 0.0278 },
+    BondRate { year: 1963, rate: 0.0311 },
+    BondRate { year: 1964, rate: 0.0351 },
+    BondRate { year: 1965, rate: 0.0390 },
+    BondRate { year: 1966, rate: 0.0484 },
+    BondRate { year: 1967, rate: 0.0433 },
+    BondRate { year: 1968, rate: 0.0526 },
+    BondRate { year: 1969, rate: 0.0656 },
+    BondRate { year: 1970, rate: 0.0669 },
+    BondRate { year: 1971, rate: 0.0454 },
+    BondRate { year: 1972, rate: 0.0395 },
+    BondRate { year: 1973, rate: 0.0673 },
+    BondRate { year: 1974, rate: 0.0778 },
+    BondRate { year: 1975, rate: 0.0599 },
+    BondRate { year: 1976, rate: 0.0497 },
+    BondRate { year: 1977, rate: 0.0513 },
+    BondRate { year: 1978, rate: 0.0693 },
+    BondRate { year: 1979, rate: 0.0994 },
+    BondRate { year: 1980, rate: 0.1122 },
+    BondRate { year: 1981, rate: 0.1430 },
+    BondRate { year: 1982, rate: 0.1101 },
+    BondRate { year: 1983, rate: 0.0845 },
+    BondRate { year: 1984, rate: 0.0961 },
+    BondRate { year: 1985, rate: 0.0749 },
+    BondRate { year: 1986, rate: 0.0604 },
+    BondRate { year: 1987, rate: 0.0572 },
+    BondRate { year: 1988, rate: 0.0645 },
+    BondRate { year: 1989, rate: 0.0811 },
+    BondRate { year: 1990, rate: 0.0755 },
+    BondRate { year: 1991, rate: 0.0561 },
+    BondRate { year: 1992, rate: 0.0341 },
+    BondRate { year: 1993, rate: 0.0298 },
+    BondRate { year: 1994, rate: 0.0399 },
+    BondRate { year: 1995, rate: 0.0552 },
+    BondRate { year: 1996, rate: 0.0502 },
+    BondRate { year: 1997, rate: 0.0505 },
+    BondRate { year: 1998, rate: 0.0473 },
+    BondRate { year: 1999, rate: 0.0451 },
+    BondRate { year: 2000, rate: 0.0576 },
+    BondRate { year: 2001, rate: 0.0367 },
+    BondRate { year: 2002, rate: 0.0166 },
+    BondRate { year: 2003, rate: 0.0103 },
+    BondRate { year: 2004, rate: 0.0123 },
+    BondRate { year: 2005, rate: 0.0301 },
+    BondRate { year: 2006, rate: 0.0468 },
+    BondRate { year: 2007, rate: 0.0464 },
+    BondRate { year: 2008, rate: 0.0159 },
+    BondRate { year: 2009, rate: 0.0014 },
+    BondRate { year: 2010, rate: 0.0013 },
+    BondRate { year: 2011, rate: 0.0003 },
+    BondRate { year: 2012, rate: 0.0005 },
+    BondRate { year: 2013, rate: 0.0007 },
+    BondRate { year: 2014, rate: 0.0005 },
+    BondRate { year: 2015, rate: 0.0021 },
+    BondRate { year: 2016, rate: 0.0051 },
+    BondRate { year: 2017, rate: 0.0139 },
+    BondRate { year: 2018, rate: 0.0237 },
+    BondRate { year: 2019, rate: 0.0155 },
+    BondRate { year: 2020, rate: 0.0009 },
+];
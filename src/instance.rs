@@ -1,20 +1,58 @@
-use crate::account::Account;
-use crate::config::{PhaseType, SimpleWithdrawal, TaxAwareWithdrawal, YearlyContribution};
+use crate::account::{Account, Joint};
+use crate::asset::Asset;
+use crate::config::{
+    AssetClass, CashBucket, ConversionAmount, FirstDeath, Person, PhaseType, Portfolio,
+    SimpleWithdrawal, SocialSecurity, SpendingRule, TaxAwareWithdrawal, YearlyContribution,
+};
+use crate::error::Result;
 use crate::histret::HistoricalYear;
+use crate::mix::AssetMix;
+use crate::report::ReportValue;
 use crate::rmd::rmd_fraction;
-use crate::tax::tax;
+use crate::social_security::yearly_benefit;
+use crate::tax::TaxProfile;
 
-use chrono::{Datelike, Utc};
 use std::fmt::Debug;
 
 #[derive(Debug)]
 pub struct Instance {
-    cash: f64,
-    pre_tax: Account,
-    roth: Account,
+    cash: Asset,
+    pre_tax: Joint,
+    roth: Joint,
     after_tax: Account,
     expense_ratio: f64,
+    spouse_birth_year: Option<i32>,
+    first_death: Option<FirstDeath>,
+    // Calendar year in which the simulation starts.
+    start_year: i32,
     inflation: f64,
+    // Growth of tax brackets since the start of the simulation.
+    bracket_index: f64,
+    social_security: Vec<SocialSecurity>,
+    // Total return of stocks, and the change in the value of everything held, in the most recent
+    // year.
+    stock_return: Option<f64>,
+    portfolio_return: Option<f64>,
+    // Last year's real spending with the inflation index it was adjusted by, and the first year's
+    // withdrawal rate, for guardrails. Both start over with each phase.
+    last_spending: Option<(f64, f64)>,
+    initial_rate: Option<f64>,
+    // Years elapsed when the portfolio ran out, if it has.
+    depleted: Option<usize>,
+    // Years in which spending fell short of the target, and the total real shortfall.
+    shortfall_years: usize,
+    shortfall_total: f64,
+    // Highest real value so far, the largest fall from it as a fraction, and the sum of the
+    // squares of each year's fall, over the given number of years.
+    peak: f64,
+    max_drawdown: f64,
+    drawdown_squares: f64,
+    years: usize,
+    // For the perfect withdrawal rate: one over the real growth of $1 since the first withdrawal
+    // year, and its sum over withdrawal years.
+    pwr_discount: f64,
+    pwr_sum: f64,
+    portfolio: Portfolio,
     // TODO: Make private
     pub income: Income,
 }
@@ -25,6 +63,10 @@ pub struct Income {
     pub id: f64,
     pub rmd: f64,
     pub after_tax_bought: f64,
+    pub social_security: f64,
+    pub roth_converted: f64,
+    pub cash_spent: f64,
+    pub cash_refilled: f64,
 
     pub after_tax_sold: f64,
     pub cg: f64,
@@ -32,6 +74,11 @@ pub struct Income {
     pub roth_sold: f64,
 
     pub taxes: f64,
+
+    // Living expenses the spending rule called for this year, and those actually paid, which
+    // are less once the money runs out.
+    pub target_spending: f64,
+    pub spending: f64,
 }
 
 #[derive(Debug, PartialEq)]
@@ -43,34 +90,98 @@ pub struct ValueByAccount {
 
 impl Instance {
     pub fn new(
-        pre_tax: Account,
-        roth: Account,
+        pre_tax: Joint,
+        roth: Joint,
         after_tax: Account,
         expense_ratio: f64,
+        start_year: i32,
     ) -> Instance {
         Instance {
-            cash: 0.0,
-            pre_tax: pre_tax,
-            roth: roth,
-            after_tax: after_tax,
-            expense_ratio: expense_ratio,
+            cash: Asset::new(0.0),
+            pre_tax,
+            roth,
+            after_tax,
+            expense_ratio,
+            spouse_birth_year: None,
+            first_death: None,
+            start_year,
             inflation: 1.0,
+            bracket_index: 1.0,
+            social_security: Vec::new(),
+            stock_return: None,
+            portfolio_return: None,
+            last_spending: None,
+            initial_rate: None,
+            depleted: None,
+            shortfall_years: 0,
+            shortfall_total: 0.0,
+            peak: 0.0,
+            max_drawdown: 0.0,
+            drawdown_squares: 0.0,
+            years: 0,
+            pwr_discount: 1.0,
+            pwr_sum: 0.0,
+            portfolio: Portfolio::default(),
             income: Income {
                 id: 0.0,
                 rmd: 0.0,
                 after_tax_bought: 0.0,
+                social_security: 0.0,
+                roth_converted: 0.0,
+                cash_spent: 0.0,
+                cash_refilled: 0.0,
                 after_tax_sold: 0.0,
                 cg: 0.0,
                 ira_sold: 0.0,
                 roth_sold: 0.0,
                 taxes: 0.0,
+                target_spending: 0.0,
+                spending: 0.0,
             },
         }
     }
+    pub fn with_social_security(mut self, s: &[SocialSecurity]) -> Instance {
+        self.social_security = s.to_vec();
+        self
+    }
+    pub fn with_cash(mut self, c: f64) -> Instance {
+        self.cash = Asset::new(c);
+        self
+    }
+    pub fn with_spouse(
+        mut self,
+        birth_year: i32,
+        pre_tax: Account,
+        roth: Account,
+        first_death: &Option<FirstDeath>,
+    ) -> Instance {
+        self.pre_tax = self.pre_tax.with_spouse(pre_tax);
+        self.roth = self.roth.with_spouse(roth);
+        self.spouse_birth_year = Some(birth_year);
+        self.first_death = first_death.clone();
+        self
+    }
+    // Whether one spouse died before the given calendar year.
+    fn widowed(&self, year: i32) -> bool {
+        self.first_death.as_ref().is_some_and(|d| year > d.year)
+    }
+    // The asset classes that make up stocks and bonds, for stock returns and the perfect
+    // withdrawal rate when no stocks or bonds are held.
+    pub fn with_portfolio(mut self, p: &Portfolio) -> Instance {
+        self.portfolio = p.clone();
+        self
+    }
     pub fn inflation_adjusted(&self, v: f64) -> f64 {
         v / self.inflation
     }
     pub fn value(&self) -> f64 {
+        self.invested_value() + self.cash.value
+    }
+    pub fn cash(&self) -> f64 {
+        self.cash.value
+    }
+    // Value of everything except cash.
+    fn invested_value(&self) -> f64 {
         self.pre_tax.value() + self.roth.value() + self.after_tax.value()
     }
     pub fn value_by_account(&self) -> ValueByAccount {
@@ -80,9 +191,10 @@ impl Instance {
             after_tax: self.after_tax.value(),
         }
     }
+    // Bonds as a fraction of everything except cash.
     pub fn bond_fraction(&self) -> f64 {
-        if self.value() > 0.0 {
-            self.bond_value() / self.value()
+        if self.invested_value() > 0.0 {
+            self.bond_value() / self.invested_value()
         } else {
             0.0
         }
@@ -95,11 +207,83 @@ impl Instance {
         }
     }
     pub fn bond_value(&self) -> f64 {
-        self.pre_tax.bonds.value + self.roth.bonds.value + self.after_tax.bonds.value
+        self.pre_tax.bonds_value() + self.roth.bonds_value() + self.after_tax.bonds.value()
     }
     pub fn capital_gains(&self) -> f64 {
         self.after_tax.capital_gains()
     }
+    // Balances by account and asset class, this year's income, and the inflation index, for
+    // tracing a single path. Balances and income are nominal.
+    pub fn trace(&self) -> Vec<(String, ReportValue)> {
+        let mut t = Vec::new();
+        for (name, stocks, bonds) in [
+            (
+                "PreTax",
+                by_class(&[&self.pre_tax.own.stocks, &self.pre_tax.spouse.stocks]),
+                by_class(&[&self.pre_tax.own.bonds, &self.pre_tax.spouse.bonds]),
+            ),
+            (
+                "Roth",
+                by_class(&[&self.roth.own.stocks, &self.roth.spouse.stocks]),
+                by_class(&[&self.roth.own.bonds, &self.roth.spouse.bonds]),
+            ),
+            (
+                "AfterTax",
+                self.after_tax.stocks.values(),
+                self.after_tax.bonds.values(),
+            ),
+        ] {
+            for (class, v) in stocks {
+                t.push((format!("{} stocks {}", name, class.name()), v));
+            }
+            for (class, v) in bonds {
+                t.push((format!("{} bonds {}", name, class.name()), v));
+            }
+        }
+        t.push((String::from("Cash"), self.cash.value));
+        t.push((String::from("AfterTax cap gains"), self.capital_gains()));
+        // Included in the totals above.
+        t.push((String::from("Spouse PreTax"), self.pre_tax.spouse.value()));
+        t.push((String::from("Spouse Roth"), self.roth.spouse.value()));
+        let i = &self.income;
+        for (name, v) in [
+            ("I&D", i.id),
+            ("RMD", i.rmd),
+            ("AfterTax bought", i.after_tax_bought),
+            ("AfterTax sold", i.after_tax_sold),
+            ("Realized cap gains", i.cg),
+            ("PreTax sold", i.ira_sold),
+            ("Roth sold", i.roth_sold),
+            ("Social Security", i.social_security),
+            ("Roth converted", i.roth_converted),
+            ("Cash spent", i.cash_spent),
+            ("Cash refilled", i.cash_refilled),
+            ("Taxes", i.taxes),
+            ("Target spending", i.target_spending),
+            ("Spending", i.spending),
+        ]
+        .iter()
+        {
+            t.push((String::from(*name), *v));
+        }
+        let mut t: Vec<(String, ReportValue)> = t
+            .into_iter()
+            .map(|(k, v)| (k, ReportValue::Currency(v)))
+            .collect();
+        t.push((
+            String::from("Max drawdown"),
+            ReportValue::Percent(self.max_drawdown),
+        ));
+        t.push((
+            String::from("Perfect withdrawal rate"),
+            ReportValue::Percent(self.perfect_withdrawal_rate().unwrap_or(0.0)),
+        ));
+        t.push((
+            String::from("Inflation index"),
+            ReportValue::Number(self.inflation),
+        ));
+        t
+    }
     pub fn goal_allocations(&self, b: f64) -> ValueByAccount {
         goal_allocations(&self.value_by_account(), b)
     }
@@ -108,10 +292,47 @@ impl Instance {
     }
 
     // Returns taxable interest and dividends.
-    pub fn grow_and_reinvest(&mut self, r: &HistoricalYear, e: f64) -> f64 {
-        self.pre_tax.grow_and_reinvest(&r.stocks, &r.tbonds, e);
-        self.roth.grow_and_reinvest(&r.stocks, &r.tbonds, e);
-        self.after_tax.grow_and_reinvest(&r.stocks, &r.tbonds, e)
+    pub fn grow_and_reinvest(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
+        let before = self.value();
+        let mut stock_return = 0.0;
+        for (class, w) in self.class_weights(false) {
+            let a = r.asset_return(&class)?;
+            stock_return += w * (a.cg + a.id);
+        }
+        self.stock_return = Some(stock_return);
+        self.pre_tax.grow_and_reinvest(r, e)?;
+        self.roth.grow_and_reinvest(r, e)?;
+        let cash_interest = self.cash.grow(&r.tbills, 0.0);
+        self.cash.invest(cash_interest);
+        let id = self.after_tax.grow_and_reinvest(r, e)? + cash_interest;
+        self.portfolio_return = (before > 0.0).then(|| self.value() / before - 1.0);
+        Ok(id)
+    }
+
+    // Decides how much of this year's expenses to pay from the cash bucket, and how much to add
+    // to it. Cash pays all of them after a bad year for stocks, and otherwise whatever the
+    // portfolio can't, even without a bucket. Returns the expenses left to pay from the
+    // portfolio, and the amount to refill.
+    fn cash_bucket(&mut self, b: &Option<CashBucket>, expenses: f64) -> Result<(f64, f64)> {
+        let bad_year = b.as_ref().is_some_and(|b| {
+            self.stock_return
+                .is_some_and(|r| r < b.refill_threshold / 100.0)
+        });
+        let from_cash = if bad_year {
+            expenses
+        } else {
+            (expenses - self.invested_value()).max(0.0)
+        };
+        self.income.cash_spent = from_cash.min(self.cash.value);
+        self.cash.sell_preserving_cg_ratio(self.income.cash_spent)?;
+        let left = expenses - self.income.cash_spent;
+        let refill = match b {
+            Some(b) if !bad_year => (b.years * expenses - self.cash.value)
+                .min(self.invested_value() - left)
+                .max(0.0),
+            _ => 0.0,
+        };
+        Ok((left, refill))
     }
 
     pub fn contribute(&mut self, c: &YearlyContribution) {
@@ -133,9 +354,8 @@ impl Instance {
 
     // Returns taxable (regular income, cap gains). $a is the amount to withdraw, b is the target
     // bond fraction.
-    pub fn withdraw(&mut self, a: f64, b: f64) -> (f64, f64) {
-        assert!(b >= 0.0);
-        assert!(b <= 1.0);
+    pub fn withdraw(&mut self, a: f64, b: f64) -> Result<(f64, f64)> {
+        debug_assert!((0.0..=1.0).contains(&b));
         let w = self.allocate_withdrawals(a);
         let target_allocation = goal_allocations(
             &ValueByAccount {
@@ -146,29 +366,52 @@ impl Instance {
             b,
         );
         self.roth
-            .sell_with_goal_allocation(w.roth, target_allocation.roth);
+            .sell_with_goal_allocation(w.roth, target_allocation.roth)?;
         self.pre_tax
-            .sell_with_goal_allocation(w.pre_tax, target_allocation.pre_tax);
+            .sell_with_goal_allocation(w.pre_tax, target_allocation.pre_tax)?;
         let cg = self
             .after_tax
-            .sell_with_goal_allocation(w.after_tax, target_allocation.after_tax);
-        (w.pre_tax, cg)
+            .sell_with_goal_allocation(w.after_tax, target_allocation.after_tax)?;
+        Ok((w.pre_tax, cg))
     }
 
-    pub fn next(&mut self, y: usize, c: &PhaseType, r: &HistoricalYear) {
+    // Steps one year, the yth of the plan, with years_left years to go including this one.
+    pub fn next(
+        &mut self,
+        y: usize,
+        years_left: usize,
+        c: &PhaseType,
+        r: &HistoricalYear,
+        t: &TaxProfile,
+    ) -> Result<()> {
         self.income = Income {
             id: 0.0,
             rmd: 0.0,
             after_tax_bought: 0.0,
+            social_security: 0.0,
+            roth_converted: 0.0,
+            cash_spent: 0.0,
+            cash_refilled: 0.0,
             after_tax_sold: 0.0,
             cg: 0.0,
             ira_sold: 0.0,
             roth_sold: 0.0,
             taxes: 0.0,
+            target_spending: 0.0,
+            spending: 0.0,
         };
+        self.peak = self.peak.max(self.inflation_adjusted(self.value()));
+        let year = self.start_year + y as i32;
+        if let Some(d) = &self.first_death {
+            if self.widowed(year) {
+                let deceased = d.person;
+                self.pre_tax.inherit(deceased)?;
+                self.roth.inherit(deceased)?;
+            }
+        }
         match c {
             PhaseType::Accumulation(c) => {
-                self.accumulate(c, r);
+                self.accumulate(c, r)?;
             }
             PhaseType::Growth => {
                 self.accumulate(
@@ -179,47 +422,236 @@ impl Instance {
                         target_bond_percent: self.bond_fraction() * 100.0,
                     },
                     r,
-                );
+                )?;
             }
             PhaseType::SimpleWithdrawAndRebalance(w) => {
-                self.simple_withdraw_and_rebalance(w, r);
+                self.simple_withdraw_and_rebalance(w, r, years_left)?;
             }
             PhaseType::WithdrawTaxAware(w) => {
-                self.withdraw_tax_aware(w, r, Utc::now().year() - w.birth_year + y as i32);
+                self.withdraw_tax_aware(w, r, year, years_left, t)?;
             }
         }
+        let withdrawal_bonds = match c {
+            PhaseType::SimpleWithdrawAndRebalance(w) => Some(w.bond_percent),
+            PhaseType::WithdrawTaxAware(w) => Some(w.bond_percent),
+            _ => None,
+        };
+        if let Some(b) = withdrawal_bonds {
+            self.pwr_sum += self.pwr_discount;
+            self.pwr_discount /= self.real_growth(b / 100.0, r)?;
+        }
         self.inflation *= 1.0 + r.inflation;
+        self.bracket_index = t.index_brackets(self.bracket_index, r.inflation);
+        // Ignore rounding error in what was raised.
+        let shortfall = self.income.target_spending - self.income.spending;
+        if shortfall > self.income.target_spending * 1e-9 {
+            self.shortfall_years += 1;
+            self.shortfall_total += self.inflation_adjusted(shortfall);
+        }
+        if self.depleted.is_none() && self.income.target_spending > 0.0 && self.value() <= 0.0 {
+            self.depleted = Some(y + 1);
+        }
+        let real = self.inflation_adjusted(self.value());
+        self.peak = self.peak.max(real);
+        let drawdown = if self.peak > 0.0 {
+            1.0 - real / self.peak
+        } else {
+            0.0
+        };
+        self.max_drawdown = self.max_drawdown.max(drawdown);
+        self.drawdown_squares += drawdown * drawdown;
+        self.years += 1;
+        Ok(())
     }
-    fn accumulate(&mut self, c: &YearlyContribution, r: &HistoricalYear) {
-        self.grow_and_reinvest(r, self.expense_ratio);
+    // Real growth factor this year of $1 invested with bond fraction b.
+    fn real_growth(&self, b: f64, r: &HistoricalYear) -> Result<f64> {
+        let e = self.expense_ratio;
+        let mut growth = 0.0;
+        for (part, bonds) in [(1.0 - b, false), (b, true)] {
+            for (class, w) in self.class_weights(bonds) {
+                let a = r.asset_return(&class)?;
+                // As Asset::grow, with interest and dividends reinvested.
+                growth += part * w * ((1.0 + a.cg) * (1.0 - e) + a.id);
+            }
+        }
+        Ok(growth / (1.0 + r.inflation))
+    }
+    // Fraction of the stocks, or bonds, held in all accounts that is in each asset class. The
+    // portfolio's weights if none are held.
+    fn class_weights(&self, bonds: bool) -> Vec<(AssetClass, f64)> {
+        let accounts = [
+            &self.pre_tax.own,
+            &self.pre_tax.spouse,
+            &self.roth.own,
+            &self.roth.spouse,
+            &self.after_tax,
+        ];
+        let mixes: Vec<&AssetMix> = accounts
+            .iter()
+            .map(|a| if bonds { &a.bonds } else { &a.stocks })
+            .collect();
+        let held = by_class(&mixes);
+        let total: f64 = held.iter().map(|x| x.1).sum();
+        if total > 0.0 {
+            held.into_iter().map(|(c, v)| (c, v / total)).collect()
+        } else {
+            let weights = if bonds {
+                &self.portfolio.bonds
+            } else {
+                &self.portfolio.stocks
+            };
+            weights
+                .iter()
+                .map(|w| (w.asset.clone(), w.percent / 100.0))
+                .collect()
+        }
+    }
+    // Largest fall in real value from its highest point so far, as a fraction.
+    pub fn max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+    // Root mean square of each year's fall in real value from its highest point so far.
+    pub fn ulcer_index(&self) -> f64 {
+        if self.years > 0 {
+            (self.drawdown_squares / self.years as f64).sqrt()
+        } else {
+            0.0
+        }
+    }
+    // Fraction of the value at the start of the first withdrawal year that could be withdrawn,
+    // in real terms, at the start of every withdrawal year so far, leaving exactly nothing. None
+    // before the first withdrawal year.
+    pub fn perfect_withdrawal_rate(&self) -> Option<f64> {
+        if self.pwr_sum > 0.0 {
+            Some(1.0 / self.pwr_sum)
+        } else {
+            None
+        }
+    }
+    // Years elapsed when the portfolio ran out, or None if it has not.
+    pub fn years_until_depletion(&self) -> Option<usize> {
+        self.depleted
+    }
+    pub fn shortfall_years(&self) -> usize {
+        self.shortfall_years
+    }
+    // Total real spending that could not be paid.
+    pub fn shortfall_total(&self) -> f64 {
+        self.shortfall_total
+    }
+    // Called as each phase starts, so that its spending rule starts from the phase's own amount
+    // rather than from the last phase's spending.
+    pub fn start_phase(&mut self) {
+        self.last_spending = None;
+        self.initial_rate = None;
+    }
+    fn accumulate(&mut self, c: &YearlyContribution, r: &HistoricalYear) -> Result<()> {
+        self.grow_and_reinvest(r, self.expense_ratio)?;
         self.contribute(c);
+        Ok(())
     }
-    fn simple_withdraw_and_rebalance(&mut self, w: &SimpleWithdrawal, r: &HistoricalYear) {
-        self.withdraw(w.amount * self.inflation, w.bond_percent / 100.0);
-        self.grow_and_reinvest(r, self.expense_ratio);
+    fn simple_withdraw_and_rebalance(
+        &mut self,
+        w: &SimpleWithdrawal,
+        r: &HistoricalYear,
+        years_left: usize,
+    ) -> Result<()> {
+        let spending = self.spending(&w.spending, w.amount, self.inflation, years_left);
+        self.income.target_spending = spending;
+        let (expenses, refill) = self.cash_bucket(&w.cash_bucket, spending)?;
+        self.income.spending = self.income.cash_spent + expenses.min(self.invested_value());
+        self.withdraw(expenses + refill, w.bond_percent / 100.0)?;
+        self.income.cash_refilled = refill;
+        self.cash.invest(refill);
+        self.grow_and_reinvest(r, self.expense_ratio)?;
         let allocations = self.goal_allocations(w.bond_percent / 100.0);
-        self.pre_tax.rebalance(allocations.pre_tax);
-        self.roth.rebalance(allocations.roth);
+        self.pre_tax.rebalance(allocations.pre_tax)?;
+        self.roth.rebalance(allocations.roth)?;
+        Ok(())
     }
-    fn withdraw_tax_aware(&mut self, w: &TaxAwareWithdrawal, r: &HistoricalYear, age: i32) {
+    fn withdraw_tax_aware(
+        &mut self,
+        w: &TaxAwareWithdrawal,
+        r: &HistoricalYear,
+        year: i32,
+        years_left: usize,
+        t: &TaxProfile,
+    ) -> Result<()> {
         let b = w.bond_percent / 100.0;
         let new_inflation = self.inflation * (1.0 + r.inflation);
-        let real_expenses = w.living_expenses * new_inflation;
+        let spending = self.spending(&w.spending, w.living_expenses, new_inflation, years_left);
+        self.income.target_spending = spending;
+        let (real_expenses, refill) = self.cash_bucket(&w.cash_bucket, spending)?;
+        let bracket_index = t.index_brackets(self.bracket_index, r.inflation);
+
+        let benefits = self
+            .social_security
+            .iter()
+            .map(|s| yearly_benefit(s, year, new_inflation));
+        // A survivor keeps the larger benefit.
+        self.income.social_security = if self.widowed(year) {
+            benefits.fold(0.0, f64::max)
+        } else {
+            benefits.sum()
+        };
 
-        // RMDs are calculated at the beginning of the year.
-        self.income.rmd = self.pre_tax.value() * rmd_fraction(age);
-        self.pre_tax.sell_preserving_allocation(self.income.rmd);
+        // RMDs are calculated at the beginning of the year, for each owner.
+        let own_rmd = self.pre_tax.own.value() * rmd_fraction(year - w.birth_year);
+        self.pre_tax.own.sell_preserving_allocation(own_rmd)?;
+        let spouse_rmd = match self.spouse_birth_year {
+            Some(y) => self.pre_tax.spouse.value() * rmd_fraction(year - y),
+            None => 0.0,
+        };
+        self.pre_tax.spouse.sell_preserving_allocation(spouse_rmd)?;
+        self.income.rmd = own_rmd + spouse_rmd;
 
         // Market growth. After-tax interest and dividends.
-        self.income.id = self.grow_and_reinvest(r, self.expense_ratio);
+        self.income.id = self.grow_and_reinvest(r, self.expense_ratio)?;
 
-        // TODO: Roth conversion.
+        // Roth conversion, on top of the year's other ordinary income, i, and capital gains, cg,
+        // from the $left still in pre-tax accounts. It is decided along with what to sell, since
+        // taxes on it are paid by selling more. After the first death, the age is the survivor's.
+        let birth_year = match (&self.first_death, self.spouse_birth_year) {
+            (Some(d), Some(y)) if self.widowed(year) && d.person == Person::First => y,
+            _ => w.birth_year,
+        };
+        let plan = w
+            .roth_conversion
+            .as_ref()
+            .filter(|c| c.until_age.is_none_or(|a| year - birth_year < a))
+            .map(|c| &c.amount);
+        let ceiling = match plan {
+            Some(ConversionAmount::FillBracket(r)) => t.bracket_ceiling(*r) * bracket_index,
+            _ => 0.0,
+        };
+        let conversion = |s: &Self, i: f64, cg: f64, left: f64| {
+            let amount = match plan {
+                None => 0.0,
+                Some(ConversionAmount::Fixed(a)) => a * new_inflation,
+                Some(ConversionAmount::FillBracket(_)) => s.room_below(t, ceiling, i, cg),
+            };
+            amount.max(0.0).min(left.max(0.0))
+        };
+        self.income.roth_converted = conversion(
+            self,
+            self.income.rmd + self.income.id,
+            0.0,
+            self.pre_tax.value(),
+        );
 
         // Invest any money we have left over. Or, sell more to make up expenses.
-        self.income.taxes =
-            new_inflation * tax((self.income.rmd + self.income.id) / new_inflation, 0.0);
-        let money_left = self.income.rmd - self.income.taxes - real_expenses;
+        self.income.taxes = self.taxes(
+            t,
+            bracket_index,
+            self.income.rmd + self.income.id + self.income.roth_converted,
+            0.0,
+        );
+        let money_left = self.income.rmd + self.income.social_security
+            - self.income.taxes
+            - real_expenses
+            - refill;
         self.income.after_tax_bought = money_left.max(0.0);
+        let mut sold_everything = false;
         if money_left >= 0.0 {
             // We have money left over. Invest it in our after-tax account.
             self.after_tax.invest_with_goal_allocation(
@@ -236,53 +668,205 @@ impl Instance {
             );
         } else {
             // RMDs aren't enough. Sell some assets.
+            // Account values don't change while we search.
+            let values = self.value_by_account();
+            let invested_value = values.pre_tax + values.roth + values.after_tax;
+            let cg_fraction = self.after_tax.capital_gains_fraction();
             let mut raw_guess = -money_left;
-            let mut guess = self.allocate_withdrawals(raw_guess);
-            self.income.taxes = new_inflation
-                * tax(
-                    (self.income.rmd + self.income.id + guess.pre_tax) / new_inflation,
-                    guess.after_tax * self.after_tax.capital_gains_fraction(),
-                );
-            while raw_guess < self.value()
-                && guess.after_tax + guess.pre_tax + guess.roth + self.income.rmd
-                    - self.income.taxes
-                    < real_expenses
+            let mut guess = allocate_withdrawals(&values, raw_guess);
+            let estimate = |s: &Self, guess: &ValueByAccount| {
+                let i = s.income.rmd + s.income.id + guess.pre_tax;
+                let cg = guess.after_tax * cg_fraction;
+                let converted = conversion(s, i, cg, values.pre_tax - guess.pre_tax);
+                (converted, s.taxes(t, bracket_index, i + converted, cg))
+            };
+            let (mut converted, mut taxes) = estimate(self, &guess);
+            while raw_guess < invested_value
+                && guess.after_tax
+                    + guess.pre_tax
+                    + guess.roth
+                    + self.income.rmd
+                    + self.income.social_security
+                    - taxes
+                    < real_expenses + refill
             {
                 raw_guess += 1000.0;
-                guess = self.allocate_withdrawals(raw_guess);
-                self.income.taxes = new_inflation
-                    * tax(
-                        (self.income.rmd + self.income.id + guess.pre_tax) / new_inflation,
-                        guess.after_tax * self.after_tax.capital_gains_fraction() / new_inflation,
-                    );
+                guess = allocate_withdrawals(&values, raw_guess);
+                (converted, taxes) = estimate(self, &guess);
             }
-            let foo = self.withdraw(raw_guess, b);
-            self.income.ira_sold = foo.0;
-            self.income.cg = foo.1;
+            self.income.roth_converted = converted;
+            sold_everything = raw_guess >= invested_value;
+            let (ira_sold, cg) = self.withdraw(raw_guess, b)?;
+            self.income.ira_sold = ira_sold;
+            self.income.cg = cg;
             self.income.after_tax_sold = guess.after_tax;
             self.income.roth_sold = guess.roth;
-            self.income.taxes = new_inflation
-                * tax(
-                    (self.income.rmd + self.income.id + self.income.ira_sold) / new_inflation,
-                    self.income.cg / new_inflation,
-                );
+            self.income.roth_converted = conversion(
+                self,
+                self.income.rmd + self.income.id + ira_sold,
+                cg,
+                self.pre_tax.value(),
+            );
+            self.income.taxes = self.taxes(
+                t,
+                bracket_index,
+                self.income.rmd
+                    + self.income.id
+                    + self.income.roth_converted
+                    + self.income.ira_sold,
+                self.income.cg,
+            );
         }
 
+        self.pre_tax
+            .convert(self.income.roth_converted, &mut self.roth)?;
+
+        // Refill cash with whatever we raised beyond expenses, up to the planned amount.
+        let raised = self.income.rmd
+            + self.income.social_security
+            + self.income.after_tax_sold
+            + self.income.ira_sold
+            + self.income.roth_sold
+            - self.income.taxes
+            - self.income.after_tax_bought
+            - real_expenses;
+        self.income.cash_refilled = refill.min(raised).max(0.0);
+        // Cash pays whatever selling everything didn't.
+        let from_cash = if sold_everything {
+            (-raised).max(0.0).min(self.cash.value)
+        } else {
+            0.0
+        };
+        self.cash.sell_preserving_cg_ratio(from_cash)?;
+        self.income.cash_spent += from_cash;
+        // Taxes on what was sold can come out a little higher than estimated, so only count a
+        // shortfall once there is nothing left to sell.
+        self.income.spending = if sold_everything {
+            self.income.cash_spent - from_cash
+                + (real_expenses + raised + from_cash)
+                    .max(0.0)
+                    .min(real_expenses)
+        } else {
+            self.income.target_spending
+        };
+        self.cash.invest(self.income.cash_refilled);
+
         // Rebalance tax-advantaged accounts.
         let target_allocations = self.goal_allocations(b);
-        self.pre_tax.rebalance(target_allocations.pre_tax);
-        self.roth.rebalance(target_allocations.roth);
+        self.pre_tax.rebalance(target_allocations.pre_tax)?;
+        self.roth.rebalance(target_allocations.roth)?;
+        Ok(())
+    }
+    // Nominal spending this year under rule r, which starts from amount in today's dollars.
+    // inflation is the index to adjust it by.
+    fn spending(
+        &mut self,
+        r: &SpendingRule,
+        amount: f64,
+        inflation: f64,
+        years_left: usize,
+    ) -> f64 {
+        let value = self.value();
+        match r {
+            SpendingRule::Constant => amount * inflation,
+            SpendingRule::PercentOfPortfolio(p) => value * p / 100.0,
+            SpendingRule::VariablePercentage(p) => value * vpw_fraction(p / 100.0, years_left),
+            SpendingRule::FloorAndCeiling(f) => (value * f.percent / 100.0)
+                .max(amount * inflation * f.floor / 100.0)
+                .min(amount * inflation * f.ceiling / 100.0),
+            SpendingRule::Guardrails(g) => {
+                let real = match (self.last_spending, self.initial_rate) {
+                    (Some((last, last_inflation)), Some(initial)) if value > 0.0 => {
+                        // No raise for inflation after a year in which the portfolio lost value.
+                        let last = if self.portfolio_return.is_some_and(|x| x < 0.0) {
+                            (last * last_inflation / inflation).min(last)
+                        } else {
+                            last
+                        };
+                        let rate = last * inflation / value;
+                        if rate > initial * (1.0 + g.upper / 100.0) {
+                            last * (1.0 - g.adjustment / 100.0)
+                        } else if rate < initial * (1.0 - g.lower / 100.0) {
+                            last * (1.0 + g.adjustment / 100.0)
+                        } else {
+                            last
+                        }
+                    }
+                    (Some((last, _)), _) => last,
+                    (None, _) => {
+                        if value > 0.0 {
+                            self.initial_rate = Some(amount * inflation / value);
+                        }
+                        amount
+                    }
+                };
+                self.last_spending = Some((real, inflation));
+                real * inflation
+            }
+        }
+    }
+
+    // The most ordinary income that can be added to $i, with $cg of capital gains, keeping
+    // taxable income, including Social Security, within the nominal ceiling. Taxable Social
+    // Security grows with income, so this searches to the nearest dollar.
+    fn room_below(&self, t: &TaxProfile, ceiling: f64, i: f64, cg: f64) -> f64 {
+        let ss = self.income.social_security;
+        let taxable = |x: f64| i + x + t.taxable_social_security(i + x + cg, ss);
+        if ceiling.is_infinite() {
+            return f64::INFINITY;
+        }
+        if taxable(0.0) >= ceiling {
+            return 0.0;
+        }
+        let (mut low, mut high) = (0.0, ceiling - i);
+        while high - low > 1.0 {
+            let x = (low + high) / 2.0;
+            if taxable(x) > ceiling {
+                high = x;
+            } else {
+                low = x;
+            }
+        }
+        low
+    }
+    // Nominal taxes on $i of ordinary income and $cg of capital gains, plus the taxable part of
+    // this year's Social Security benefits.
+    fn taxes(&self, t: &TaxProfile, bracket_index: f64, i: f64, cg: f64) -> f64 {
+        let ss = t.taxable_social_security(i + cg, self.income.social_security);
+        t.indexed_tax(bracket_index, i + ss, cg)
+    }
+}
+
+// Value of each asset class, summed over the mixes, in the order the classes first appear.
+fn by_class(mixes: &[&AssetMix]) -> Vec<(AssetClass, f64)> {
+    let mut t: Vec<(AssetClass, f64)> = Vec::new();
+    for (class, v) in mixes.iter().flat_map(|m| m.values()) {
+        match t.iter_mut().find(|x| x.0 == class) {
+            Some(x) => x.1 += v,
+            None => t.push((class, v)),
+        }
+    }
+    t
+}
+
+// Fraction of the portfolio to spend at the start of the year, so that equal real payments at
+// real return r use it up in n years.
+fn vpw_fraction(r: f64, n: usize) -> f64 {
+    let n = n.max(1) as f64;
+    if r == 0.0 {
+        1.0 / n
+    } else {
+        r / (1.0 - (1.0 + r).powf(-n)) / (1.0 + r)
     }
 }
 
 // Calculates what our asset allocation ought to be in each account, so that our overall
 // bond fraction is b.
 fn goal_allocations(v: &ValueByAccount, b: f64) -> ValueByAccount {
-    assert!(b >= 0.0);
-    assert!(b <= 1.0);
-    assert!(v.pre_tax >= 0.0);
-    assert!(v.roth >= 0.0);
-    assert!(v.after_tax >= 0.0);
+    debug_assert!((0.0..=1.0).contains(&b));
+    debug_assert!(v.pre_tax >= 0.0);
+    debug_assert!(v.roth >= 0.0);
+    debug_assert!(v.after_tax >= 0.0);
     let total = v.after_tax + v.pre_tax + v.roth;
     let bond_goal = total * b;
     if bond_goal <= v.pre_tax {
@@ -320,10 +904,10 @@ fn goal_allocations(v: &ValueByAccount, b: f64) -> ValueByAccount {
 
 // Calculates how to withdraw a total of $a from our accounts.
 fn allocate_withdrawals(v: &ValueByAccount, a: f64) -> ValueByAccount {
-    assert!(a >= 0.0);
-    assert!(v.pre_tax >= 0.0);
-    assert!(v.roth >= 0.0);
-    assert!(v.after_tax >= 0.0);
+    debug_assert!(a >= 0.0);
+    debug_assert!(v.pre_tax >= 0.0);
+    debug_assert!(v.roth >= 0.0);
+    debug_assert!(v.after_tax >= 0.0);
     if a <= v.after_tax {
         ValueByAccount {
             pre_tax: 0.0,
@@ -349,17 +933,39 @@ fn allocate_withdrawals(v: &ValueByAccount, a: f64) -> ValueByAccount {
 mod instance_tests {
     #[cfg(test)]
     use crate::asset::AssetReturn;
+    use crate::config::{FloorAndCeiling, Guardrails, RothConversion, TaxConfig};
     use crate::instance::*;
     #[cfg(test)]
     use crate::{assert_eq_cents, assert_eq_decimal_places};
+    #[cfg(test)]
+    use std::collections::BTreeMap;
+
+    #[cfg(test)]
+    fn year(stocks: AssetReturn, bonds: AssetReturn, inflation: f64) -> HistoricalYear {
+        HistoricalYear {
+            year: 0,
+            stocks,
+            tbonds: bonds,
+            tbills: AssetReturn { cg: 0.0, id: 0.0 },
+            aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
+            inflation,
+            series: BTreeMap::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn joint(a: Account) -> Joint {
+        Joint::new(a, &Portfolio::default()).unwrap()
+    }
 
     #[test]
     fn goal_allocations() {
         let instance = Instance::new(
-            Account::new(50.0, 50.0),
-            Account::new(50.0, 50.0),
-            Account::new(50.0, 50.0),
+            joint(Account::new(50.0, 50.0).unwrap()),
+            joint(Account::new(50.0, 50.0).unwrap()),
+            Account::new(50.0, 50.0).unwrap(),
             0.0,
+            2026,
         );
         let alloc = instance.goal_allocations(0.0);
         assert_eq!(alloc.pre_tax, 0.0);
@@ -380,21 +986,22 @@ mod instance_tests {
     #[test]
     fn grow_and_reinvest() {
         let mut instance = Instance::new(
-            Account::new(50.0, 50.0),
-            Account::new(100.0, 0.0),
-            Account::new(90.0, 10.0),
-            0.0,
-        );
-        let id = instance.grow_and_reinvest(
-            &HistoricalYear {
-                year: 0,
-                stocks: AssetReturn { cg: 0.08, id: 0.02 },
-                tbonds: AssetReturn { cg: 0.0, id: 0.04 },
-                aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
-                inflation: 0.0,
-            },
+            joint(Account::new(50.0, 50.0).unwrap()),
+            joint(Account::new(100.0, 0.0).unwrap()),
+            Account::new(90.0, 10.0).unwrap(),
             0.0,
+            2026,
         );
+        let id = instance
+            .grow_and_reinvest(
+                &year(
+                    AssetReturn { cg: 0.08, id: 0.02 },
+                    AssetReturn { cg: 0.0, id: 0.04 },
+                    0.0,
+                ),
+                0.0,
+            )
+            .unwrap();
         assert_eq!(id, 2.2);
         assert_eq!(
             instance.value_by_account(),
@@ -409,10 +1016,11 @@ mod instance_tests {
     #[test]
     fn contribute() {
         let mut instance = Instance::new(
-            Account::new(0.0, 0.0),
-            Account::new(0.0, 0.0),
-            Account::new(0.0, 0.0),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
             0.0,
+            2026,
         );
         instance.contribute(&YearlyContribution {
             pre_tax: 10000.0,
@@ -442,13 +1050,14 @@ mod instance_tests {
     #[test]
     fn withdraw_after_tax() {
         let mut instance = Instance::new(
-            Account::new(50.0, 50.0),
-            Account::new(0.0, 0.0),
-            Account::new_with_basis(100.0, 50.0, 0.0, 0.0),
+            joint(Account::new(50.0, 50.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new_with_basis(100.0, 50.0, 0.0, 0.0).unwrap(),
             0.0,
+            2026,
         );
         assert_eq!(instance.value(), 200.0);
-        let income = instance.withdraw(50.0, 0.25);
+        let income = instance.withdraw(50.0, 0.25).unwrap();
         assert_eq!(instance.value(), 150.0);
         assert_eq!(income.0, 0.0);
         assert_eq!(income.1, 25.0);
@@ -457,13 +1066,14 @@ mod instance_tests {
     #[test]
     fn withdraw_pre_tax() {
         let mut instance = Instance::new(
-            Account::new(50.0, 50.0),
-            Account::new(0.0, 0.0),
-            Account::new_with_basis(100.0, 50.0, 0.0, 0.0),
+            joint(Account::new(50.0, 50.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new_with_basis(100.0, 50.0, 0.0, 0.0).unwrap(),
             0.0,
+            2026,
         );
         assert_eq!(instance.value(), 200.0);
-        let income = instance.withdraw(150.0, 0.25);
+        let income = instance.withdraw(150.0, 0.25).unwrap();
         assert_eq!(instance.value(), 50.0);
         assert_eq!(income.0, 50.0);
         assert_eq!(income.1, 50.0);
@@ -472,26 +1082,27 @@ mod instance_tests {
     #[test]
     fn accumulation() {
         let mut instance = Instance::new(
-            Account::new(0.0, 0.0),
-            Account::new(0.0, 0.0),
-            Account::new(0.0, 0.0),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
             0.0,
+            2026,
         );
-        instance.accumulate(
-            &YearlyContribution {
-                pre_tax: 100.0,
-                roth: 200.0,
-                after_tax: 300.0,
-                target_bond_percent: 10.0,
-            },
-            &HistoricalYear {
-                year: 0,
-                stocks: AssetReturn { cg: 0.05, id: 0.02 },
-                tbonds: AssetReturn { cg: 0.0, id: 0.04 },
-                aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
-                inflation: 0.00,
-            },
-        );
+        instance
+            .accumulate(
+                &YearlyContribution {
+                    pre_tax: 100.0,
+                    roth: 200.0,
+                    after_tax: 300.0,
+                    target_bond_percent: 10.0,
+                },
+                &year(
+                    AssetReturn { cg: 0.05, id: 0.02 },
+                    AssetReturn { cg: 0.0, id: 0.04 },
+                    0.0,
+                ),
+            )
+            .unwrap();
         assert_eq!(instance.value(), 600.0);
         assert_eq!(
             instance.value_by_account(),
@@ -510,21 +1121,21 @@ mod instance_tests {
                 after_tax: 0.0
             }
         );
-        instance.accumulate(
-            &YearlyContribution {
-                pre_tax: 100.0,
-                roth: 200.0,
-                after_tax: 300.0,
-                target_bond_percent: 10.0,
-            },
-            &HistoricalYear {
-                year: 0,
-                stocks: AssetReturn { cg: 0.05, id: 0.02 },
-                tbonds: AssetReturn { cg: 0.0, id: 0.04 },
-                aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
-                inflation: 0.00,
-            },
-        );
+        instance
+            .accumulate(
+                &YearlyContribution {
+                    pre_tax: 100.0,
+                    roth: 200.0,
+                    after_tax: 300.0,
+                    target_bond_percent: 10.0,
+                },
+                &year(
+                    AssetReturn { cg: 0.05, id: 0.02 },
+                    AssetReturn { cg: 0.0, id: 0.04 },
+                    0.0,
+                ),
+            )
+            .unwrap();
         assert_eq!(instance.value(), 1240.2);
         assert_eq!(
             instance.value_by_account(),
@@ -544,24 +1155,28 @@ mod instance_tests {
     #[test]
     fn simple_withdraw_and_rebalance() {
         let mut instance = Instance::new(
-            Account::new(50.0, 50.0),
-            Account::new(100.0, 0.0),
-            Account::new(100.0, 0.0),
+            joint(Account::new(50.0, 50.0).unwrap()),
+            joint(Account::new(100.0, 0.0).unwrap()),
+            Account::new(100.0, 0.0).unwrap(),
             0.0,
+            2026,
         );
-        instance.simple_withdraw_and_rebalance(
-            &SimpleWithdrawal {
-                amount: 150.0,
-                bond_percent: 20.0,
-            },
-            &HistoricalYear {
-                year: 0,
-                stocks: AssetReturn { cg: 0.05, id: 0.02 },
-                tbonds: AssetReturn { cg: 0.0, id: 0.04 },
-                aaabonds: AssetReturn { cg: 0.0, id: 0.0 },
-                inflation: 0.00,
-            },
-        );
+        instance
+            .simple_withdraw_and_rebalance(
+                &SimpleWithdrawal {
+                    amount: 150.0,
+                    bond_percent: 20.0,
+                    cash_bucket: None,
+                    spending: SpendingRule::Constant,
+                },
+                &year(
+                    AssetReturn { cg: 0.05, id: 0.02 },
+                    AssetReturn { cg: 0.0, id: 0.04 },
+                    0.0,
+                ),
+                30,
+            )
+            .unwrap();
         assert_eq_cents!(instance.value(), 159.6);
         let v = instance.value_by_account();
         assert_eq_cents!(v.pre_tax, 30.0 * 1.04 + 20.0 * 1.07);
@@ -569,4 +1184,240 @@ mod instance_tests {
         assert_eq!(v.after_tax, 0.0);
         assert_eq!(instance.bond_fraction(), 0.2);
     }
+
+    #[test]
+    fn cash_bucket() {
+        let bucket = Some(CashBucket {
+            years: 2.0,
+            refill_threshold: 0.0,
+        });
+        let mut instance = Instance::new(
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(100.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        )
+        .with_cash(5.0);
+        assert_eq!(instance.value(), 105.0);
+
+        // Refill at the start, before there is any return history.
+        assert_eq!(instance.cash_bucket(&bucket, 10.0).unwrap(), (10.0, 15.0));
+
+        // Refill after a good year.
+        instance.stock_return = Some(0.1);
+        assert_eq!(instance.cash_bucket(&bucket, 10.0).unwrap(), (10.0, 15.0));
+
+        // Spend cash after a bad year.
+        instance.stock_return = Some(-0.1);
+        assert_eq!(instance.cash_bucket(&bucket, 10.0).unwrap(), (5.0, 0.0));
+        assert_eq!(instance.income.cash_spent, 5.0);
+        assert_eq!(instance.cash(), 0.0);
+
+        // No bucket.
+        assert_eq!(instance.cash_bucket(&None, 10.0).unwrap(), (10.0, 0.0));
+
+        // Cash pays what the portfolio can't, even after a good year, and isn't refilled.
+        let mut instance = Instance::new(
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(100.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        )
+        .with_cash(50.0);
+        instance.stock_return = Some(0.1);
+        assert_eq!(instance.cash_bucket(&bucket, 120.0).unwrap(), (100.0, 0.0));
+        assert_eq!(instance.income.cash_spent, 20.0);
+        assert_eq!(instance.cash(), 30.0);
+        assert_eq!(instance.cash_bucket(&None, 120.0).unwrap(), (100.0, 0.0));
+        assert_eq!(instance.cash(), 10.0);
+    }
+
+    #[test]
+    fn spending_rules() {
+        let mut instance = Instance::new(
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(1000.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        );
+        let s = |i: &mut Instance, r: &SpendingRule| i.spending(r, 40.0, 1.5, 30);
+        assert_eq!(s(&mut instance, &SpendingRule::Constant), 60.0);
+        assert_eq!(
+            s(&mut instance, &SpendingRule::PercentOfPortfolio(5.0)),
+            50.0
+        );
+        assert_eq_cents!(
+            s(&mut instance, &SpendingRule::VariablePercentage(0.0)),
+            1000.0 / 30.0
+        );
+        let f = |floor, ceiling| {
+            SpendingRule::FloorAndCeiling(FloorAndCeiling {
+                percent: 5.0,
+                floor,
+                ceiling,
+            })
+        };
+        assert_eq!(s(&mut instance, &f(90.0, 110.0)), 54.0);
+        assert_eq!(s(&mut instance, &f(50.0, 75.0)), 45.0);
+        assert_eq!(s(&mut instance, &f(50.0, 100.0)), 50.0);
+
+        // Starts at the amount, so a 6% withdrawal rate.
+        let g = SpendingRule::Guardrails(Guardrails {
+            upper: 20.0,
+            lower: 20.0,
+            adjustment: 10.0,
+        });
+        assert_eq!(s(&mut instance, &g), 60.0);
+        // 7% is not above the upper guardrail of 7.2%.
+        instance.roth.own = Account::new(60.0 / 0.07, 0.0).unwrap();
+        assert_eq_cents!(s(&mut instance, &g), 60.0);
+        // 8% is, so spending is cut.
+        instance.roth.own = Account::new(750.0, 0.0).unwrap();
+        assert_eq_cents!(s(&mut instance, &g), 54.0);
+        // 3.6% is below the lower guardrail, so spending is raised from there.
+        instance.roth.own = Account::new(1500.0, 0.0).unwrap();
+        assert_eq_cents!(s(&mut instance, &g), 59.4);
+        // After a year in which the portfolio lost value, spending isn't raised for inflation.
+        instance.roth.own = Account::new(1000.0, 0.0).unwrap();
+        instance.portfolio_return = Some(-0.1);
+        assert_eq_cents!(instance.spending(&g, 40.0, 1.65, 30), 59.4);
+        // A new phase starts over from its amount.
+        instance.start_phase();
+        assert_eq_cents!(s(&mut instance, &g), 60.0);
+    }
+
+    #[test]
+    fn risk_metrics() {
+        let mut instance = Instance::new(
+            joint(Account::new(0.0, 0.0).unwrap()),
+            joint(Account::new(1000.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        );
+        assert_eq!(instance.perfect_withdrawal_rate(), None);
+        let phase = PhaseType::SimpleWithdrawAndRebalance(SimpleWithdrawal {
+            amount: 100.0,
+            bond_percent: 0.0,
+            cash_bucket: None,
+            spending: SpendingRule::Constant,
+        });
+        let flat = year(
+            AssetReturn { cg: 0.0, id: 0.0 },
+            AssetReturn { cg: 0.0, id: 0.0 },
+            0.0,
+        );
+        let tax = TaxProfile::new(&TaxConfig::default());
+        instance.next(0, 2, &phase, &flat, &tax).unwrap();
+        instance.next(1, 1, &phase, &flat, &tax).unwrap();
+        assert_eq_cents!(instance.value(), 800.0);
+        assert_eq_cents!(instance.max_drawdown(), 0.2);
+        assert_eq_decimal_places!(instance.ulcer_index(), ((0.01 + 0.04) / 2.0_f64).sqrt(), 6);
+        // With no growth, spending half in each of two years leaves nothing.
+        assert_eq!(instance.perfect_withdrawal_rate(), Some(0.5));
+    }
+
+    #[test]
+    fn roth_conversion_fills_bracket() {
+        let mut instance = Instance::new(
+            joint(Account::new(1_000_000.0, 0.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        );
+        let phase = |living_expenses| {
+            PhaseType::WithdrawTaxAware(TaxAwareWithdrawal {
+                living_expenses,
+                birth_year: 1970,
+                bond_percent: 0.0,
+                roth_conversion: Some(RothConversion {
+                    amount: ConversionAmount::FillBracket(12.0),
+                    until_age: None,
+                }),
+                cash_bucket: None,
+                spending: SpendingRule::Constant,
+            })
+        };
+        let flat = year(
+            AssetReturn { cg: 0.0, id: 0.0 },
+            AssetReturn { cg: 0.0, id: 0.0 },
+            0.0,
+        );
+        let tax = TaxProfile::new(&TaxConfig::default());
+        // The 12% bracket ends at $105,050, including the standard deduction. The conversion
+        // tops up what was sold for expenses and its taxes.
+        instance.next(0, 2, &phase(40_000.0), &flat, &tax).unwrap();
+        let income = instance.income.ira_sold + instance.income.roth_converted;
+        assert!(instance.income.ira_sold > 40_000.0);
+        assert!(income <= 105_050.0 && income > 104_900.0, "{}", income);
+        assert_eq_cents!(
+            instance.value_by_account().roth,
+            instance.income.roth_converted
+        );
+
+        // Selling for expenses fills the bracket by itself, so nothing is converted.
+        instance.next(1, 1, &phase(120_000.0), &flat, &tax).unwrap();
+        assert!(instance.income.ira_sold > 105_050.0);
+        assert_eq!(instance.income.roth_converted, 0.0);
+    }
+
+    #[test]
+    fn roth_conversion_survivor_age() {
+        let mut instance = Instance::new(
+            joint(Account::new(1_000_000.0, 0.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2030,
+        )
+        .with_spouse(
+            1970,
+            Account::new(0.0, 0.0).unwrap(),
+            Account::new(0.0, 0.0).unwrap(),
+            &Some(FirstDeath {
+                person: Person::First,
+                year: 2030,
+            }),
+        );
+        let phase = PhaseType::WithdrawTaxAware(TaxAwareWithdrawal {
+            living_expenses: 0.0,
+            birth_year: 1960,
+            bond_percent: 0.0,
+            roth_conversion: Some(RothConversion {
+                amount: ConversionAmount::Fixed(10_000.0),
+                until_age: Some(65),
+            }),
+            cash_bucket: None,
+            spending: SpendingRule::Constant,
+        });
+        let flat = year(
+            AssetReturn { cg: 0.0, id: 0.0 },
+            AssetReturn { cg: 0.0, id: 0.0 },
+            0.0,
+        );
+        let tax = TaxProfile::new(&TaxConfig::default());
+        // The first person is 70, past the age to stop converting.
+        instance.next(0, 2, &phase, &flat, &tax).unwrap();
+        assert_eq!(instance.income.roth_converted, 0.0);
+        // They died in 2030, and the survivor is 61.
+        instance.next(1, 1, &phase, &flat, &tax).unwrap();
+        assert_eq!(instance.income.roth_converted, 10_000.0);
+    }
+
+    #[test]
+    fn vpw_fraction() {
+        assert_eq_cents!(super::vpw_fraction(0.05, 1), 1.0);
+        assert_eq!(super::vpw_fraction(0.0, 4), 0.25);
+        // Spending this fraction every year at a 5% real return uses up the portfolio.
+        let f = super::vpw_fraction(0.05, 20);
+        let mut v = 1.0;
+        for _ in 0..20 {
+            v = (v - f) * 1.05;
+        }
+        assert_eq_cents!(v, 0.0);
+    }
 }
@@ -1,12 +1,22 @@
 mod account;
 mod asset;
 pub mod config;
-mod histret;
+mod error;
+mod growth;
+pub mod histret;
 pub mod instance;
-mod report;
+mod mix;
+pub mod report;
 mod rmd;
 pub mod scenario;
+mod social_security;
+pub mod solve;
+pub mod sweep;
 mod tax;
+mod trace;
+pub mod validate;
+
+pub use error::{Error, Result};
 
 #[macro_export]
 macro_rules! assert_eq_decimal_places {
@@ -1,20 +1,269 @@
-use isim::scenario;
+use isim::config::{InitialState, OutputFormat, ReportField, Solve, SolveFor, Sweep, SweepAxis};
+use isim::histret;
+use isim::scenario::Scenario;
+use isim::solve;
+use isim::sweep;
+use isim::validate::{self, ConfigError};
 
-use serde_yaml;
-use std::env;
+use clap::{Parser, Subcommand};
+use serde_yaml::Value;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Simulates an investment plan over historical or generated market returns
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Number of threads to run paths and sweep points on. Defaults to one per core
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a config and print its report
+    Run {
+        /// YAML config file
+        config: PathBuf,
+        /// Output format: table, csv or json. Defaults to the config's output_format
+        #[arg(long)]
+        format: Option<OutputFormat>,
+        /// Replace the seed of a Bootstrap, BlockBootstrap or Parametric growth model
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Lengthen or shorten the last phase so the plan lasts this many years
+        #[arg(long)]
+        years: Option<usize>,
+        /// Write the state of every path in every year to this file
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Only trace the path with this id, which is its starting year for historical paths
+        #[arg(long, value_name = "ID", requires = "trace")]
+        trace_path: Option<i32>,
+    },
+    /// Check a config and list every problem with its YAML path
+    Validate {
+        /// YAML config file
+        config: PathBuf,
+    },
+    /// Run a config for each value of one or two settings and print a metric for each run
+    Sweep {
+        /// YAML config file. Its sweep section gives the axes and metric, unless overridden
+        config: PathBuf,
+        /// A YAML path and the values to try, such as
+        /// phases[1].config.SimpleWithdrawAndRebalance.amount=30,35,40. Give two for a grid
+        #[arg(long, value_name = "PATH=V1,V2,...")]
+        axis: Vec<SweepAxis>,
+        /// Report field to print at the end of each run, in YAML, such as SuccessRate or
+        /// "{Value: [Median, Total, Real]}". Defaults to SuccessRate
+        #[arg(long, value_parser = parse_metric)]
+        metric: Option<ReportField>,
+        /// Output format: table, csv or json
+        #[arg(long, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Find the highest withdrawal, fewest working years, or lowest contribution that meets a
+    /// target success rate
+    Solve {
+        /// YAML config file. Its solve section gives the target, unless overridden
+        config: PathBuf,
+        /// What to solve for: MaxWithdrawal, MinWorkingYears or MinContribution. Defaults to
+        /// MaxWithdrawal
+        #[arg(long, value_parser = parse_find)]
+        find: Option<SolveFor>,
+        /// Percent of paths that must succeed
+        #[arg(long)]
+        success_rate: Option<f64>,
+        /// Real value each successful path must end with. Defaults to 0
+        #[arg(long)]
+        min_ending_balance: Option<f64>,
+        /// Output format: table, csv or json
+        #[arg(long, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Show the year-by-year state of the path with one starting year
+    Explain {
+        /// YAML config file
+        config: PathBuf,
+        /// Id of the path, which is its starting year for historical paths
+        path: i32,
+        /// Output format: table, csv or json
+        #[arg(long, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Print the historical returns and inflation, in percent, that simulations draw from
+    Data {
+        /// CSV or YAML files that extend or replace the built-in data, as in return_data
+        files: Vec<PathBuf>,
+        /// Output format: table, csv or json
+        #[arg(long, default_value = "table")]
+        format: OutputFormat,
+    },
+}
 
 fn main() {
-    let config = env::args().nth(1).expect("Config file not specified");
-    let config = serde_yaml::from_str(
-        fs::read_to_string(config)
-            .expect("Failed to read config")
-            .as_str(),
-    )
-    .unwrap();
-    //println!("{:#?}", config);
-    //println!("{}", serde_yaml::to_string(&config).unwrap());
-    let mut scenario = scenario::Scenario::new(&config);
-    scenario.run();
-    scenario.report();
+    let cli = Cli::parse();
+    if let Some(n) = cli.threads {
+        or_exit(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build_global(),
+        );
+    }
+    match cli.command {
+        Command::Run {
+            config,
+            format,
+            seed,
+            years,
+            trace,
+            trace_path,
+        } => {
+            let mut config = load(&config);
+            if let Some(s) = seed {
+                or_exit(config.set_seed(s));
+            }
+            if let Some(y) = years {
+                or_exit(config.set_years(y));
+            }
+            let mut scenario = or_exit(Scenario::new(&config));
+            if trace.is_some() {
+                scenario.trace(trace_path);
+            }
+            or_exit(scenario.run());
+            match &format {
+                Some(f) => or_exit(scenario.write_report(f, &mut io::stdout())),
+                None => or_exit(scenario.report()),
+            }
+            if let Some(t) = trace {
+                let mut file = or_exit(fs::File::create(t));
+                or_exit(scenario.write_trace(&format.unwrap_or(OutputFormat::Csv), &mut file));
+            }
+        }
+        Command::Validate { config } => {
+            load(&config);
+            println!("{}: OK", config.display());
+        }
+        Command::Sweep {
+            config,
+            axis,
+            metric,
+            format,
+        } => {
+            let text = or_exit(fs::read_to_string(&config).map_err(ConfigError::Read));
+            let value: Value = or_exit(serde_yaml::from_str(&text).map_err(ConfigError::Parse));
+            let dir = config.parent().unwrap_or_else(|| Path::new(""));
+            let is = or_exit(validate::from_value(value.clone(), dir));
+            warn(&is);
+            let mut s = is.sweep.unwrap_or(Sweep {
+                metric: ReportField::SuccessRate,
+                axes: Vec::new(),
+            });
+            if !axis.is_empty() {
+                s.axes = axis;
+            }
+            if let Some(m) = metric {
+                s.metric = m;
+            }
+            let grid = or_exit(sweep::sweep(&value, dir, &s));
+            or_exit(grid.write(&format, &mut io::stdout()));
+        }
+        Command::Solve {
+            config,
+            find,
+            success_rate,
+            min_ending_balance,
+            format,
+        } => {
+            let mut config = load(&config);
+            let mut s = match (config.solve.take(), success_rate) {
+                (Some(s), _) => s,
+                (None, Some(r)) => Solve {
+                    find: SolveFor::default(),
+                    success_rate: r,
+                    min_ending_balance: 0.0,
+                },
+                (None, None) => fail("Give a target with --success-rate or a solve section"),
+            };
+            if let Some(f) = find {
+                s.find = f;
+            }
+            if let Some(r) = success_rate {
+                s.success_rate = r;
+            }
+            if let Some(b) = min_ending_balance {
+                s.min_ending_balance = b;
+            }
+            let find = s.find;
+            config.solve = Some(s);
+            // The command line may have changed what to solve for.
+            or_exit(validate::validate(&config));
+            let mut out = io::stdout();
+            or_exit(match find {
+                SolveFor::MaxWithdrawal => {
+                    or_exit(solve::max_withdrawal(config)).write(&format, &mut out)
+                }
+                SolveFor::MinWorkingYears => {
+                    or_exit(solve::min_working_years(config)).write(&format, &mut out)
+                }
+                SolveFor::MinContribution => {
+                    or_exit(solve::min_contribution(config)).write(&format, &mut out)
+                }
+            });
+        }
+        Command::Explain {
+            config,
+            path,
+            format,
+        } => {
+            let config = load(&config);
+            let mut scenario = or_exit(Scenario::new(&config));
+            if !scenario.has_path(path) {
+                fail(format!("No path has id {}", path));
+            }
+            scenario.trace(Some(path));
+            or_exit(scenario.run());
+            or_exit(scenario.write_trace(&format, &mut io::stdout()));
+        }
+        Command::Data { files, format } => {
+            let data = or_exit(histret::load(&files));
+            or_exit(histret::write(&data, &format, &mut io::stdout()));
+        }
+    }
+}
+
+fn parse_find(s: &str) -> Result<SolveFor, String> {
+    serde_yaml::from_str(s).map_err(|e| e.to_string())
+}
+
+fn parse_metric(s: &str) -> Result<ReportField, String> {
+    serde_yaml::from_str(s).map_err(|e| e.to_string())
+}
+
+fn load(path: &Path) -> InitialState {
+    let is = or_exit(validate::load(path));
+    warn(&is);
+    is
+}
+
+// Prints anything in the config that is valid but probably a mistake.
+fn warn(is: &InitialState) {
+    for w in validate::warnings(is) {
+        eprintln!("Warning: {}: {}", w.path, w.message);
+    }
+}
+
+// Prints the error and exits with a non-zero status.
+fn or_exit<T, E: fmt::Display>(r: Result<T, E>) -> T {
+    r.unwrap_or_else(|e| fail(e))
+}
+
+fn fail<E: fmt::Display>(e: E) -> ! {
+    eprintln!("{}", e);
+    process::exit(1)
 }
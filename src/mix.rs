@@ -0,0 +1,271 @@
+use crate::asset::Asset;
+use crate::config::{AssetClass, Weight};
+use crate::error::{Error, Result};
+use crate::histret::HistoricalYear;
+
+use std::fmt::Debug;
+
+// Asset classes held in target proportions, which together behave like a single asset. New money
+// is split by target weight, and sales are in proportion to current value.
+#[derive(Debug, Clone)]
+pub struct AssetMix {
+    holdings: Vec<Holding>,
+}
+
+#[derive(Debug, Clone)]
+struct Holding {
+    class: AssetClass,
+    // Target fraction of the mix.
+    weight: f64,
+    asset: Asset,
+}
+
+impl AssetMix {
+    // Splits an asset between classes by weight, in percent. Each class gets the same fraction
+    // of capital gains.
+    pub fn new(a: Asset, weights: &[Weight]) -> Result<AssetMix> {
+        if weights.is_empty() {
+            return Err(Error::InvalidAllocation(String::from(
+                "No asset classes given",
+            )));
+        }
+        let total: f64 = weights.iter().map(|w| w.percent).sum();
+        if (total - 100.0).abs() >= 1e-6 {
+            return Err(Error::InvalidAllocation(format!(
+                "Asset class weights add up to {}%, not 100%",
+                total
+            )));
+        }
+        let value = a.value;
+        let mut rest = a;
+        let mut holdings = Vec::with_capacity(weights.len());
+        for (j, w) in weights.iter().enumerate() {
+            if w.percent < 0.0 {
+                return Err(Error::InvalidAllocation(format!(
+                    "Negative weight for {:?}",
+                    w.asset
+                )));
+            }
+            let asset = if j + 1 == weights.len() {
+                rest.clone()
+            } else {
+                rest.split_off((value * w.percent / 100.0).min(rest.value))?
+            };
+            holdings.push(Holding {
+                class: w.asset.clone(),
+                weight: w.percent / 100.0,
+                asset,
+            });
+        }
+        Ok(AssetMix { holdings })
+    }
+
+    pub fn value(&self) -> f64 {
+        self.holdings.iter().map(|h| h.asset.value).sum()
+    }
+    pub fn capital_gains(&self) -> f64 {
+        self.holdings.iter().map(|h| h.asset.capital_gains()).sum()
+    }
+    // Value of each asset class.
+    pub fn values(&self) -> Vec<(AssetClass, f64)> {
+        self.holdings
+            .iter()
+            .map(|h| (h.class.clone(), h.asset.value))
+            .collect()
+    }
+
+    // Market growth methods. All return interest and dividends.
+
+    pub fn grow(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
+        let mut id = 0.0;
+        for h in &mut self.holdings {
+            id += h.asset.grow(r.asset_return(&h.class)?, e);
+        }
+        Ok(id)
+    }
+    // Each class reinvests its own interest and dividends.
+    pub fn grow_and_reinvest(&mut self, r: &HistoricalYear, e: f64) -> Result<f64> {
+        let mut id = 0.0;
+        for h in &mut self.holdings {
+            let hid = h.asset.grow(r.asset_return(&h.class)?, e);
+            h.asset.invest(hid);
+            id += hid;
+        }
+        Ok(id)
+    }
+
+    // Invests $a, split by target weight.
+    pub fn invest(&mut self, a: f64) {
+        debug_assert!(a >= 0.0);
+        let mut invested = 0.0;
+        let n = self.holdings.len();
+        for (j, h) in self.holdings.iter_mut().enumerate() {
+            let amt = if j + 1 == n {
+                a - invested
+            } else {
+                a * h.weight
+            };
+            h.asset.invest(amt.max(0.0));
+            invested += amt;
+        }
+    }
+    // Sells $a in proportion to the current value of each class. Returns realized capital gains.
+    pub fn sell_preserving_cg_ratio(&mut self, a: f64) -> Result<f64> {
+        debug_assert!(a >= 0.0);
+        let total = self.value();
+        if a > total {
+            return Err(Error::InsufficientFunds {
+                requested: a,
+                available: total,
+            });
+        }
+        if a == 0.0 {
+            return Ok(0.0);
+        }
+        let mut sold = 0.0;
+        let mut cg = 0.0;
+        let n = self.holdings.len();
+        for (j, h) in self.holdings.iter_mut().enumerate() {
+            let amt = if j + 1 == n {
+                a - sold
+            } else {
+                a * h.asset.value / total
+            };
+            let amt = amt.min(h.asset.value).max(0.0);
+            cg += h.asset.sell_preserving_cg_ratio(amt)?;
+            sold += amt;
+        }
+        Ok(cg)
+    }
+    // How far each class is above its target share of goal, the value the whole mix should have.
+    // Negative for classes below their share.
+    pub fn excess(&self, goal: f64) -> Vec<f64> {
+        self.holdings
+            .iter()
+            .map(|h| h.asset.value - goal * h.weight)
+            .collect()
+    }
+    // Sells the given amount of each class. Returns realized capital gains.
+    pub fn sell_each(&mut self, amounts: &[f64]) -> Result<f64> {
+        let mut cg = 0.0;
+        for (h, a) in self.holdings.iter_mut().zip(amounts) {
+            cg += h
+                .asset
+                .sell_preserving_cg_ratio(a.min(h.asset.value).max(0.0))?;
+        }
+        Ok(cg)
+    }
+    // Invests the given amount in each class.
+    pub fn invest_each(&mut self, amounts: &[f64]) {
+        for (h, a) in self.holdings.iter_mut().zip(amounts) {
+            h.asset.invest(a.max(0.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod mix_tests {
+    #[cfg(test)]
+    use crate::assert_eq_cents;
+    #[cfg(test)]
+    use crate::asset::AssetReturn;
+    use crate::mix::*;
+    #[cfg(test)]
+    use std::collections::BTreeMap;
+
+    #[cfg(test)]
+    fn weights() -> Vec<Weight> {
+        vec![
+            Weight {
+                asset: AssetClass::TBonds,
+                percent: 60.0,
+            },
+            Weight {
+                asset: AssetClass::AaaBonds,
+                percent: 40.0,
+            },
+        ]
+    }
+
+    #[cfg(test)]
+    fn year() -> HistoricalYear {
+        HistoricalYear {
+            year: 0,
+            stocks: AssetReturn { cg: 0.0, id: 0.0 },
+            tbonds: AssetReturn { cg: 0.1, id: 0.02 },
+            tbills: AssetReturn { cg: 0.0, id: 0.0 },
+            aaabonds: AssetReturn { cg: 0.0, id: 0.05 },
+            inflation: 0.0,
+            series: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn new() {
+        let m = AssetMix::new(Asset::new_with_basis(100.0, 50.0).unwrap(), &weights()).unwrap();
+        assert_eq!(
+            m.values(),
+            vec![(AssetClass::TBonds, 60.0), (AssetClass::AaaBonds, 40.0)]
+        );
+        assert_eq!(m.value(), 100.0);
+        assert_eq!(m.capital_gains(), 50.0);
+    }
+
+    #[test]
+    fn invalid_weights() {
+        let mut w = weights();
+        w[1].percent = 30.0;
+        assert!(matches!(
+            AssetMix::new(Asset::new(100.0), &w),
+            Err(Error::InvalidAllocation(_))
+        ));
+    }
+
+    #[test]
+    fn grow() {
+        let mut m = AssetMix::new(Asset::new(100.0), &weights()).unwrap();
+        assert_eq_cents!(m.grow(&year(), 0.0).unwrap(), 1.2 + 2.0);
+        assert_eq_cents!(m.value(), 106.0);
+        let mut m = AssetMix::new(Asset::new(100.0), &weights()).unwrap();
+        assert_eq_cents!(m.grow_and_reinvest(&year(), 0.0).unwrap(), 3.2);
+        assert_eq_cents!(m.value(), 109.2);
+        assert_eq_cents!(m.capital_gains(), 6.0);
+    }
+
+    #[test]
+    fn invest() {
+        let mut m = AssetMix::new(Asset::new(0.0), &weights()).unwrap();
+        m.invest(10.0);
+        assert_eq!(
+            m.values(),
+            vec![(AssetClass::TBonds, 6.0), (AssetClass::AaaBonds, 4.0)]
+        );
+    }
+
+    #[test]
+    fn sell_preserving_cg_ratio() {
+        let mut m = AssetMix::new(Asset::new_with_basis(100.0, 50.0).unwrap(), &weights()).unwrap();
+        m.grow(&year(), 0.0).unwrap();
+        assert_eq_cents!(
+            m.sell_preserving_cg_ratio(53.0).unwrap(),
+            53.0 * 56.0 / 106.0
+        );
+        assert_eq_cents!(m.value(), 53.0);
+        assert_eq_cents!(m.values()[0].1, 33.0);
+        assert_eq_cents!(m.values()[1].1, 20.0);
+    }
+
+    #[test]
+    fn excess() {
+        let mut m = AssetMix::new(Asset::new(100.0), &weights()).unwrap();
+        m.grow(&year(), 0.0).unwrap();
+        let excess = m.excess(106.0);
+        assert_eq_cents!(excess[0], 2.4);
+        assert_eq_cents!(excess[1], -2.4);
+        assert_eq_cents!(m.sell_each(&[2.4, 0.0]).unwrap(), 6.0 * 2.4 / 66.0);
+        m.invest_each(&[0.0, 2.4]);
+        assert_eq_cents!(m.value(), 106.0);
+        assert_eq_cents!(m.values()[0].1, 63.6);
+        assert_eq_cents!(m.values()[1].1, 42.4);
+    }
+}
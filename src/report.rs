@@ -1,10 +1,52 @@
-use cli_table::format::{Align, Border, HorizontalLine, Separator, VerticalLine};
-use cli_table::{print_stdout, Cell, Row, Table, TableStruct};
+use crate::config::{OutputFormat, ReportField};
+
+use cli_table::format::{Align, Border, HorizontalLine, Justify, Separator, VerticalLine};
+use cli_table::{Cell, CellStruct, Row, Table, TableStruct};
+use itertools::join;
+use num_format::{Locale, ToFormattedString};
+use serde::Serialize;
 use std::fmt::Debug;
+use std::io::{self, Write};
+
+// A single value in the report, before formatting.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ReportValue {
+    Integer(i64),
+    Currency(f64),
+    // A fraction, so 0.5 is 50%.
+    Percent(f64),
+    Years(Vec<i32>),
+    Number(f64),
+    Text(String),
+}
+
+impl ReportValue {
+    fn cell(&self) -> CellStruct {
+        match self {
+            ReportValue::Integer(x) => x.cell(),
+            ReportValue::Currency(x) => cfmt(*x).cell().justify(Justify::Right),
+            ReportValue::Percent(x) => pfmt(*x).cell().justify(Justify::Right),
+            ReportValue::Years(x) => join(x, ", ").cell(),
+            ReportValue::Number(x) => format!("{:.4}", x).cell().justify(Justify::Right),
+            ReportValue::Text(x) => x.cell(),
+        }
+    }
+    fn csv(&self) -> String {
+        match self {
+            ReportValue::Integer(x) => x.to_string(),
+            ReportValue::Currency(x) | ReportValue::Percent(x) | ReportValue::Number(x) => {
+                x.to_string()
+            }
+            ReportValue::Years(x) => join(x, " "),
+            ReportValue::Text(x) => x.clone(),
+        }
+    }
+}
 
 pub struct Report<'a> {
-    pub config: &'a Vec<crate::config::ReportField>,
-    rows: Vec<cli_table::RowStruct>,
+    pub config: &'a Vec<ReportField>,
+    rows: Vec<Vec<ReportValue>>,
 }
 
 impl Debug for Report<'_> {
@@ -14,33 +56,182 @@ impl Debug for Report<'_> {
 }
 
 impl Report<'_> {
-    pub fn new(c: &Vec<crate::config::ReportField>) -> Report {
+    pub fn new(c: &Vec<ReportField>) -> Report<'_> {
         Report {
             config: c,
             rows: Vec::new(),
         }
     }
-    pub fn row(&mut self, r: Vec<cli_table::CellStruct>) {
-        self.rows.push(r.row());
+    pub fn row(&mut self, r: Vec<ReportValue>) {
+        self.rows.push(r);
     }
-    pub fn table(&mut self) -> TableStruct {
-        let mut title: Vec<cli_table::CellStruct> = Vec::new();
-        for f in self.config {
-            title.push(f.title().cell().align(Align::Bottom));
-        }
-        std::mem::take(&mut self.rows)
-            .table()
-            .border(Border::builder().build())
-            .separator(
-                Separator::builder()
-                    .column(Some(VerticalLine::default()))
-                    .title(Some(HorizontalLine::default()))
-                    .row(None)
-                    .build(),
-            )
-            .title(title)
-    }
-    pub fn print(&mut self) {
-        print_stdout(self.table()).expect("Failed to print report")
+    pub fn write(&self, format: &OutputFormat, w: &mut dyn Write) -> io::Result<()> {
+        write_rows(
+            &self.config.iter().map(|f| f.title()).collect::<Vec<_>>(),
+            &self.config.iter().map(|f| f.key()).collect::<Vec<_>>(),
+            &self.rows,
+            format,
+            w,
+        )
+    }
+    pub fn print(&self, format: &OutputFormat) -> io::Result<()> {
+        self.write(format, &mut io::stdout())
+    }
+}
+
+// Writes rows of values in the given format. Titles are used as table headings, and may span
+// several lines. Keys are single-line names used by machine-readable formats.
+pub fn write_rows(
+    titles: &[String],
+    keys: &[String],
+    rows: &[Vec<ReportValue>],
+    format: &OutputFormat,
+    w: &mut dyn Write,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Table => writeln!(w, "{}", table(titles, rows).display()?),
+        OutputFormat::Csv => write_csv(keys, rows, w),
+        OutputFormat::Json => write_json(keys, rows, w),
+    }
+}
+
+fn table(titles: &[String], rows: &[Vec<ReportValue>]) -> TableStruct {
+    rows.iter()
+        .map(|r| {
+            r.iter()
+                .map(|v| v.cell())
+                .collect::<Vec<CellStruct>>()
+                .row()
+        })
+        .collect::<Vec<_>>()
+        .table()
+        .border(Border::builder().build())
+        .separator(
+            Separator::builder()
+                .column(Some(VerticalLine::default()))
+                .title(Some(HorizontalLine::default()))
+                .row(None)
+                .build(),
+        )
+        .title(titles.iter().map(|t| t.cell().align(Align::Bottom)))
+}
+
+fn write_csv(keys: &[String], rows: &[Vec<ReportValue>], w: &mut dyn Write) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(w);
+    writer.write_record(keys)?;
+    for r in rows {
+        writer.write_record(r.iter().map(|v| v.csv()))?;
+    }
+    writer.flush()
+}
+
+// Writes an array with one array per row, of {"field": key, "value": value} objects in column
+// order. A list rather than an object keyed by field, so a field listed twice keeps both values.
+fn write_json(keys: &[String], rows: &[Vec<ReportValue>], w: &mut dyn Write) -> io::Result<()> {
+    let rows: Vec<Vec<serde_json::Value>> = rows
+        .iter()
+        .map(|r| {
+            keys.iter()
+                .zip(r.iter())
+                .map(|(k, v)| serde_json::json!({ "field": k, "value": v }))
+                .collect()
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *w, &rows)?;
+    writeln!(w)
+}
+
+fn cfmt(x: f64) -> String {
+    format!("${}", (x.round() as i64).to_formatted_string(&Locale::en))
+}
+
+fn pfmt(x: f64) -> String {
+    format!("{:.1}%", 100.0 * x)
+}
+
+#[cfg(test)]
+mod report_tests {
+    use crate::config::*;
+    use crate::report::*;
+
+    fn report(c: &Vec<ReportField>) -> Report<'_> {
+        let mut r = Report::new(c);
+        r.row(vec![
+            ReportValue::Integer(0),
+            ReportValue::Currency(1234.5),
+            ReportValue::Percent(0.25),
+            ReportValue::Years(vec![1966, 1969]),
+        ]);
+        r
+    }
+
+    fn config() -> Vec<ReportField> {
+        vec![
+            ReportField::YearsElapsed,
+            ReportField::Value(
+                Measure::Median,
+                AccountType::Total,
+                InflationAdjustment::Real,
+            ),
+            ReportField::SuccessRate,
+            ReportField::WorstYears,
+        ]
+    }
+
+    fn output(r: &Report, f: &OutputFormat) -> String {
+        let mut out = Vec::new();
+        r.write(f, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        let c = config();
+        assert_eq!(
+            output(&report(&c), &OutputFormat::Csv),
+            "Year,\"Value(Median, Total, Real)\",Success rate,Worst Years\n\
+             0,1234.5,0.25,1966 1969\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let c = config();
+        let v: serde_json::Value =
+            serde_json::from_str(&output(&report(&c), &OutputFormat::Json)).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!([[
+                { "field": "Year", "value": 0 },
+                { "field": "Value(Median, Total, Real)", "value": 1234.5 },
+                { "field": "Success rate", "value": 0.25 },
+                { "field": "Worst Years", "value": [1966, 1969] },
+            ]])
+        );
+    }
+
+    #[test]
+    fn json_keeps_duplicate_fields() {
+        let c = vec![ReportField::SuccessRate, ReportField::SuccessRate];
+        let mut r = Report::new(&c);
+        r.row(vec![ReportValue::Percent(0.25), ReportValue::Percent(0.5)]);
+        let v: serde_json::Value = serde_json::from_str(&output(&r, &OutputFormat::Json)).unwrap();
+        assert_eq!(v[0][0]["value"], 0.25);
+        assert_eq!(v[0][1]["value"], 0.5);
+    }
+
+    #[test]
+    fn table() {
+        let c = config();
+        let t = output(&report(&c), &OutputFormat::Table);
+        assert!(t.contains("$1,235"));
+        assert!(t.contains("25.0%"));
+        assert!(t.contains("1966, 1969"));
+    }
+
+    #[test]
+    fn fmt() {
+        assert_eq!(cfmt(1234567.8), "$1,234,568");
+        assert_eq!(pfmt(0.1234), "12.3%");
     }
 }
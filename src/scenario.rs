@@ -1,210 +1,455 @@
-use crate::account::Account;
-use crate::config::{AccountType, InflationAdjustment, InitialState, Measure, Phase, ReportField};
-use crate::histret::RETURNS;
+use crate::account::{Account, Joint};
+use crate::config::{
+    AccountType, FilingStatus, InflationAdjustment, InitialState, Measure, OutputFormat, Phase,
+    ReportField, TaxConfig,
+};
+use crate::error::Result;
+use crate::growth::{paths, Path};
+use crate::histret::{load, HistoricalYear};
 use crate::instance::Instance;
-use crate::report::Report;
+use crate::report::{Report, ReportValue};
+use crate::tax::TaxSchedule;
+use crate::trace::Trace;
+use crate::validate::{ConfigError, Problem};
 
-use cli_table::format::Justify;
-use cli_table::Cell;
-use itertools::join;
-use num_format::{Locale, ToFormattedString};
+use rayon::prelude::*;
 use std::fmt::Debug;
+use std::io;
 
-// TODO: pub enum GrowthModel { Fixed, HistoricalPath, RandomYear }
 // TODO: pub enum TaxStrategy { Taxed(Account), Untaxed(Account) }
 
 #[derive(Debug)]
 pub struct Scenario<'a> {
     year: usize,
+    start_year: i32,
+    paths: Vec<Path>,
+    // Each instance is paired with the index of the path it follows.
     instances: Vec<(usize, Instance)>,
     phases: &'a Vec<Phase>,
     report: Report<'a>,
+    output_format: &'a OutputFormat,
+    trace: Option<Trace>,
+    tax: TaxSchedule,
+    // Taxes for the survivor, from the first year after one spouse dies.
+    survivor_tax: Option<(usize, TaxSchedule)>,
 }
 
 impl Scenario<'_> {
-    pub fn new(is: &InitialState) -> Scenario {
+    // Reads the config's data files, which must already have been checked with
+    // validate::from_value or validate::validate.
+    pub fn new(is: &InitialState) -> Result<Scenario<'_>> {
+        Scenario::with_data(is, &load_data(is)?)
+    }
+    // Like new, but with data already read from the config's data files, so that many scenarios
+    // can be built from one read.
+    pub fn with_data<'a>(is: &'a InitialState, data: &[HistoricalYear]) -> Result<Scenario<'a>> {
+        let years = is.phases.iter().map(|x| x.years).sum();
+        let paths = paths(&is.growth_model, data, years).map_err(|message| {
+            ConfigError::Invalid(vec![Problem {
+                path: String::from("growth_model"),
+                message,
+            }])
+        })?;
+        // Paths that run off the end of the data are dropped when they do, which would leave
+        // nothing to report.
+        if !paths.iter().any(|p| p.returns.len() >= years) {
+            return Err(ConfigError::Invalid(vec![Problem {
+                path: String::from("phases"),
+                message: format!(
+                    "The plan lasts {} years, longer than the {} years of historical data",
+                    years,
+                    data.len()
+                ),
+            }])
+            .into());
+        }
+        let start_year = is.start_year;
         let mut s = Scenario {
             year: 0,
-            instances: Vec::with_capacity(RETURNS.len()),
+            start_year,
+            instances: Vec::with_capacity(paths.len()),
+            paths,
             phases: &is.phases,
             report: Report::new(&is.report),
+            output_format: &is.output_format,
+            trace: None,
+            tax: TaxSchedule::new(&is.tax, start_year, data),
+            survivor_tax: is
+                .spouse
+                .as_ref()
+                .and_then(|s| s.first_death.as_ref())
+                .map(|d| {
+                    let tax = TaxConfig {
+                        filing_status: FilingStatus::Single,
+                        ..is.tax.clone()
+                    };
+                    (
+                        (d.year + 1 - start_year).max(0) as usize,
+                        TaxSchedule::new(&tax, start_year, data),
+                    )
+                }),
         };
-        let pre_tax = Account::from_allocation(&is.initial_balance.pre_tax);
-        let roth = Account::from_allocation(&is.initial_balance.roth);
+        let ib = &is.initial_balance;
+        let pre_tax = Account::from_allocation(&ib.pre_tax, is.portfolio_of(&ib.pre_tax))?;
+        let roth = Account::from_allocation(&ib.roth, is.portfolio_of(&ib.roth))?;
         let after_tax = Account::from_allocation_and_basis(
-            &is.initial_balance.after_tax,
-            is.initial_balance.after_tax_cost_basis.unwrap_or(0.0),
-        );
-        for i in 0..RETURNS.len() {
-            s.instances.push((
-                i,
-                Instance::new(
+            &ib.after_tax,
+            ib.after_tax_cost_basis.unwrap_or(0.0),
+            is.portfolio_of(&ib.after_tax),
+        )?;
+        // Without a spouse, their empty accounts hold the same classes as the first person's.
+        let pre_tax = Joint::new(pre_tax, is.portfolio_of(&ib.pre_tax))?;
+        let roth = Joint::new(roth, is.portfolio_of(&ib.roth))?;
+        let spouse = match &is.spouse {
+            Some(sp) => Some((
+                sp,
+                Account::from_allocation(&sp.pre_tax, is.portfolio_of(&sp.pre_tax))?,
+                Account::from_allocation(&sp.roth, is.portfolio_of(&sp.roth))?,
+            )),
+            None => None,
+        };
+        for i in 0..s.paths.len() {
+            let mut instance = Instance::new(
+                pre_tax.clone(),
+                roth.clone(),
+                after_tax.clone(),
+                is.expense_ratio / 100.0,
+                start_year,
+            )
+            .with_social_security(&is.social_security)
+            .with_cash(ib.cash)
+            .with_portfolio(&is.portfolio);
+            if let Some((sp, pre_tax, roth)) = &spouse {
+                instance = instance.with_spouse(
+                    sp.birth_year,
                     pre_tax.clone(),
                     roth.clone(),
-                    after_tax.clone(),
-                    is.expense_ratio / 100.0,
-                ),
-            ));
+                    &sp.first_death,
+                );
+            }
+            s.instances.push((i, instance));
         }
-        s
+        Ok(s)
     }
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Result<()> {
         self.report.row(self.row());
+        self.record_trace();
         for i in 0..self.phases.len() {
+            for x in &mut self.instances {
+                x.1.start_phase();
+            }
             for _ in 0..self.phases[i].years {
-                self.next(i);
+                self.next(i)?;
                 self.report.row(self.row());
+                self.record_trace();
+            }
+        }
+        Ok(())
+    }
+    // Records the state of every instance in every year, or only of the instance following the
+    // path with the given id. Must be called before run().
+    pub fn trace(&mut self, path: Option<i32>) {
+        self.trace = Some(Trace::new(path, self.start_year));
+    }
+    // Whether any path has the given id.
+    pub fn has_path(&self, id: i32) -> bool {
+        self.paths.iter().any(|p| p.id == id)
+    }
+    // Fails if trace() was not called.
+    pub fn write_trace(&mut self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        match &mut self.trace {
+            Some(t) => t.write(format, w),
+            None => Err(io::Error::other("Tracing was not enabled")),
+        }
+    }
+    fn record_trace(&mut self) {
+        if let Some(t) = &mut self.trace {
+            for i in &self.instances {
+                t.record(self.paths[i.0].id, self.year, &i.1);
             }
         }
     }
-    pub fn report(&mut self) {
-        self.report.print();
+    pub fn report(&self) -> io::Result<()> {
+        self.report.print(self.output_format)
+    }
+    pub fn write_report(&self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        self.report.write(format, w)
     }
-    fn next(&mut self, i: usize) -> usize {
+    fn next(&mut self, i: usize) -> Result<usize> {
         let c = &self.phases[i].config;
         let y = self.year;
-        self.instances.retain(|x| x.0 + y < RETURNS.len());
-        for i in &mut self.instances {
-            i.1.next(y, c, &RETURNS[self.year + i.0]);
-        }
+        let paths = &self.paths;
+        let tax = match &self.survivor_tax {
+            Some((from, t)) if y >= *from => t.profile(y),
+            _ => self.tax.profile(y),
+        };
+        let years_left = self.length_years() - y;
+        self.instances.retain(|x| y < paths[x.0].returns.len());
+        // Instances are independent, so step them in parallel. Errors are collected in order so
+        // the first one is reported, as in a serial run.
+        self.instances
+            .par_iter_mut()
+            .map(|i| i.1.next(y, years_left, c, &paths[i.0].returns[y], tax))
+            .collect::<Vec<Result<()>>>()
+            .into_iter()
+            .collect::<Result<()>>()?;
         self.year += 1;
         self.instances.sort_by(|a, b| {
-            (a.1.inflation_adjusted(a.1.value()))
-                .partial_cmp(&b.1.inflation_adjusted(b.1.value()))
-                .unwrap()
+            (a.1.inflation_adjusted(a.1.value())).total_cmp(&b.1.inflation_adjusted(b.1.value()))
         });
-        self.instances.len()
-    }
-    fn row(&self) -> Vec<cli_table::CellStruct> {
-        let mut r: Vec<cli_table::CellStruct> = Vec::new();
-        for f in self.report.config {
-            r.push(match f {
-                ReportField::YearsElapsed => self.years_elapsed().cell(),
-                ReportField::WorstYears => join(&self.worst_starting_years(), ", ").cell(),
-                ReportField::Value(m, a, inf) => {
-                    let i = match m {
-                        Measure::Median => self.median_instance(),
-                        Measure::Worst => self.worst_instance(),
-                    };
-                    let v = match a {
-                        AccountType::Total => i.value(),
-                        AccountType::PreTax => i.value_by_account().pre_tax,
-                        AccountType::Roth => i.value_by_account().roth,
-                        AccountType::AfterTax => i.value_by_account().after_tax,
-                    };
-                    cfmt(match inf {
-                        InflationAdjustment::Real => i.inflation_adjusted(v),
-                        InflationAdjustment::Nominal => v,
-                    })
-                    .cell()
-                    .justify(Justify::Right)
-                }
-                ReportField::BondPercent(a) => pfmt(match a {
-                    AccountType::Total => self.median_instance().bond_fraction(),
-                    AccountType::PreTax => {
-                        self.median_instance().bond_fraction_by_account().pre_tax
-                    }
-                    AccountType::Roth => self.median_instance().bond_fraction_by_account().roth,
-                    AccountType::AfterTax => {
-                        self.median_instance().bond_fraction_by_account().after_tax
-                    }
+        Ok(self.instances.len())
+    }
+    fn row(&self) -> Vec<ReportValue> {
+        self.report.config.iter().map(|f| self.value(f)).collect()
+    }
+    // The value of a report field in the current year.
+    pub fn value(&self, f: &ReportField) -> ReportValue {
+        match f {
+            ReportField::YearsElapsed => ReportValue::Integer(self.years_elapsed() as i64),
+            ReportField::CalendarYear => {
+                ReportValue::Integer(self.start_year as i64 + self.years_elapsed() as i64)
+            }
+            ReportField::WorstYears => ReportValue::Years(self.worst_starting_years()),
+            ReportField::Value(m, a, inf) => {
+                ReportValue::Currency(self.measure(m, |i| account_value(i, a, inf)))
+            }
+            ReportField::BondPercent(m, a) => {
+                let i = self.instance(m);
+                ReportValue::Percent(match a {
+                    AccountType::Total => i.bond_fraction(),
+                    AccountType::PreTax => i.bond_fraction_by_account().pre_tax,
+                    AccountType::Roth => i.bond_fraction_by_account().roth,
+                    AccountType::AfterTax => i.bond_fraction_by_account().after_tax,
                 })
-                .cell()
-                .justify(Justify::Right),
-                ReportField::CapGainsPercent => {
-                    let i = self.median_instance();
-                    pfmt(i.capital_gains() / i.value())
-                        .cell()
-                        .justify(Justify::Right)
-                }
-                ReportField::StartingYear(m) => match m {
-                    Measure::Median => RETURNS[self.instances[self.instances.len() / 2].0]
-                        .year
-                        .cell(),
-                    Measure::Worst => RETURNS[self.instances[0].0].year.cell(),
-                },
-                ReportField::InterestAndDividends => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.id),
-                )
-                .cell(),
-                ReportField::StocksSold => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.after_tax_sold),
-                )
-                .cell(),
-                ReportField::StocksBought => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.after_tax_bought),
-                )
-                .cell(),
-                ReportField::CapitalGains => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.cg),
-                )
-                .cell(),
-                ReportField::SuccessRate => pfmt(self.success_ratio()).cell(),
-                ReportField::RequiredMinimumDistribution => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.rmd),
-                )
-                .cell(),
-                ReportField::Taxes => cfmt(
-                    self.median_instance()
-                        .inflation_adjusted(self.median_instance().income.taxes),
+            }
+            ReportField::CapGainsPercent(m) => {
+                let i = self.instance(m);
+                ReportValue::Percent(i.capital_gains() / i.value())
+            }
+            ReportField::StartingYear(m) => {
+                ReportValue::Integer(self.paths[self.instances[self.instance_index(m)].0].id as i64)
+            }
+            ReportField::InterestAndDividends(m) => {
+                ReportValue::Currency(self.measure(m, |i| i.inflation_adjusted(i.income.id)))
+            }
+            ReportField::StocksSold(m) => ReportValue::Currency(
+                self.measure(m, |i| i.inflation_adjusted(i.income.after_tax_sold)),
+            ),
+            ReportField::StocksBought(m) => ReportValue::Currency(
+                self.measure(m, |i| i.inflation_adjusted(i.income.after_tax_bought)),
+            ),
+            ReportField::CapitalGains(m) => {
+                ReportValue::Currency(self.measure(m, |i| i.inflation_adjusted(i.income.cg)))
+            }
+            ReportField::SuccessRate => ReportValue::Percent(self.success_ratio()),
+            ReportField::RequiredMinimumDistribution(m) => {
+                ReportValue::Currency(self.measure(m, |i| i.inflation_adjusted(i.income.rmd)))
+            }
+            ReportField::Taxes(m) => {
+                ReportValue::Currency(self.measure(m, |i| i.inflation_adjusted(i.income.taxes)))
+            }
+            ReportField::SocialSecurity(m) => ReportValue::Currency(
+                self.measure(m, |i| i.inflation_adjusted(i.income.social_security)),
+            ),
+            ReportField::RothConversion(m) => ReportValue::Currency(
+                self.measure(m, |i| i.inflation_adjusted(i.income.roth_converted)),
+            ),
+            ReportField::TaxRate(m) => ReportValue::Percent(self.measure(m, |i| {
+                i.income.taxes
+                    / (i.income.id
+                        + i.income.cg
+                        + i.income.rmd
+                        + i.income.ira_sold
+                        + i.income.roth_converted
+                        + i.income.social_security)
+            })),
+            ReportField::Cash(m) => {
+                ReportValue::Currency(self.measure(m, |i| i.inflation_adjusted(i.cash())))
+            }
+            ReportField::ExpensesDoubleCheck(m) => ReportValue::Currency(self.measure(m, |i| {
+                i.inflation_adjusted(
+                    i.income.rmd + i.income.social_security + i.income.cash_spent
+                        - i.income.cash_refilled
+                        + i.income.after_tax_sold
+                        + i.income.ira_sold
+                        + i.income.roth_sold
+                        - i.income.taxes
+                        - i.income.after_tax_bought,
                 )
-                .cell(),
-                ReportField::TaxRate => {
-                    let income = &self.median_instance().income;
-                    pfmt(income.taxes / (income.id + income.cg + income.rmd + income.ira_sold))
-                        .cell()
-                }
-                ReportField::Cash => unimplemented!(),
-                ReportField::ExpensesDoubleCheck => {
-                    let i = self.median_instance();
-                    cfmt(i.inflation_adjusted(
-                        i.income.rmd
-                            + i.income.after_tax_sold
-                            + i.income.ira_sold
-                            + i.income.roth_sold
-                            - i.income.taxes
-                            - i.income.after_tax_bought,
-                    ))
-                    .cell()
-                }
-            });
+            })),
+            ReportField::Spending(m) => {
+                ReportValue::Currency(self.ranked(m, |i| i.inflation_adjusted(i.income.spending)))
+            }
+            ReportField::YearsUntilDepletion(m) => ReportValue::Integer(
+                self.ranked(m, |i| i.years_until_depletion().unwrap_or(self.year) as f64)
+                    .round() as i64,
+            ),
+            ReportField::ShortfallTotal(m) => {
+                ReportValue::Currency(self.ranked_largest_worst(m, |i| i.shortfall_total()))
+            }
+            ReportField::ShortfallYears(m) => ReportValue::Integer(
+                self.ranked_largest_worst(m, |i| i.shortfall_years() as f64)
+                    .round() as i64,
+            ),
+            ReportField::MaxDrawdown(m) => {
+                ReportValue::Percent(self.ranked_largest_worst(m, |i| i.max_drawdown()))
+            }
+            ReportField::UlcerIndex(m) => {
+                ReportValue::Percent(self.ranked_largest_worst(m, |i| i.ulcer_index()))
+            }
+            ReportField::PerfectWithdrawalRate(m) => {
+                ReportValue::Percent(self.ranked(m, |i| i.perfect_withdrawal_rate().unwrap_or(0.0)))
+            }
+            ReportField::PerfectWithdrawalRateStartingYear(m) => ReportValue::Integer(
+                self.ranked_path(m, |i| i.perfect_withdrawal_rate().unwrap_or(0.0)) as i64,
+            ),
+            ReportField::ValueStdDev(a, inf) => {
+                let mean = self.measure(&Measure::Mean, |i| account_value(i, a, inf));
+                let variance = self.measure(&Measure::Mean, |i| {
+                    (account_value(i, a, inf) - mean).powi(2)
+                });
+                ReportValue::Currency(variance.sqrt())
+            }
         }
-        r
     }
-    pub fn worst_starting_years(&self) -> [i32; 3] {
-        [
-            RETURNS[self.instances[0].0].year,
-            RETURNS[self.instances[1].0].year,
-            RETURNS[self.instances[2].0].year,
-        ]
+    // Starting years of the three worst paths. For randomly generated paths, these are path
+    // numbers instead.
+    pub fn worst_starting_years(&self) -> Vec<i32> {
+        self.instances
+            .iter()
+            .take(3)
+            .map(|x| self.paths[x.0].id)
+            .collect()
     }
     pub fn years_elapsed(&self) -> usize {
         self.year
     }
     pub fn median_instance(&self) -> &Instance {
-        &self.instances[self.instances.len() / 2].1
+        self.instance(&Measure::Median)
     }
     pub fn worst_instance(&self) -> &Instance {
-        &self.instances[0].1
+        self.instance(&Measure::Worst)
+    }
+    pub fn instance(&self, m: &Measure) -> &Instance {
+        &self.instances[self.instance_index(m)].1
+    }
+    // Position in the sorted instances of the one selected by m. For the mean, this is the
+    // instance whose real value is closest to the mean real value.
+    fn instance_index(&self, m: &Measure) -> usize {
+        let n = self.instances.len();
+        match m {
+            Measure::Median => n / 2,
+            Measure::Worst => 0,
+            Measure::Best => n - 1,
+            Measure::Mean => {
+                let mean = self.measure(m, |i| i.inflation_adjusted(i.value()));
+                let mut best = 0;
+                for (j, x) in self.instances.iter().enumerate() {
+                    let v = x.1.inflation_adjusted(x.1.value());
+                    let b = &self.instances[best].1;
+                    if (v - mean).abs() < (b.inflation_adjusted(b.value()) - mean).abs() {
+                        best = j;
+                    }
+                }
+                best
+            }
+            // Configs that skipped validation may ask for a percentile outside 0..=100, which
+            // picks the nearest end rather than indexing past the instances. NaN picks the worst.
+            Measure::Percentile(p) => {
+                (p.clamp(0.0, 100.0) / 100.0 * (n - 1) as f64).round() as usize
+            }
+        }
+    }
+    // Evaluates f on the instance selected by m, or averages it over all instances for the mean.
+    pub fn measure<F: Fn(&Instance) -> f64>(&self, m: &Measure, f: F) -> f64 {
+        match m {
+            Measure::Mean => {
+                self.instances.iter().map(|x| f(&x.1)).sum::<f64>() / self.instances.len() as f64
+            }
+            _ => f(self.instance(m)),
+        }
+    }
+    // Like measure, but ranks instances by f rather than by real value, so that percentiles are
+    // of f itself.
+    pub fn ranked<F: Fn(&Instance) -> f64>(&self, m: &Measure, f: F) -> f64 {
+        match m {
+            Measure::Mean => self.measure(m, f),
+            _ => {
+                let mut v: Vec<f64> = self.instances.iter().map(|x| f(&x.1)).collect();
+                v.sort_by(|a, b| a.total_cmp(b));
+                v[self.instance_index(m)]
+            }
+        }
+    }
+    // Id of the path that ranked picks. For the mean, the path whose f is closest to the mean.
+    pub fn ranked_path<F: Fn(&Instance) -> f64>(&self, m: &Measure, f: F) -> i32 {
+        let mut v: Vec<(f64, i32)> = self
+            .instances
+            .iter()
+            .map(|x| (f(&x.1), self.paths[x.0].id))
+            .collect();
+        match m {
+            Measure::Mean => {
+                let mean = v.iter().map(|x| x.0).sum::<f64>() / v.len() as f64;
+                v.sort_by(|a, b| (a.0 - mean).abs().total_cmp(&(b.0 - mean).abs()));
+                v[0].1
+            }
+            _ => {
+                v.sort_by(|a, b| a.0.total_cmp(&b.0));
+                v[self.instance_index(m)].1
+            }
+        }
+    }
+    // Like ranked, for values where larger is worse, so that the worst is the largest. Percentiles
+    // are still of f itself, so the 90th percentile is larger than the 10th.
+    pub fn ranked_largest_worst<F: Fn(&Instance) -> f64>(&self, m: &Measure, f: F) -> f64 {
+        match m {
+            Measure::Worst => self.ranked(&Measure::Best, f),
+            Measure::Best => self.ranked(&Measure::Worst, f),
+            _ => self.ranked(m, f),
+        }
     }
     pub fn length_years(&self) -> usize {
         self.phases.iter().map(|x| x.years).sum()
     }
     pub fn success_ratio(&self) -> f64 {
-        self.instances.iter().filter(|x| x.1.value() > 0.0).count() as f64
-            / self.instances.len() as f64
+        self.success_ratio_above(0.0)
+    }
+    // Fraction of paths that have not run out of money and are worth at least min_real_value.
+    pub fn success_ratio_above(&self, min_real_value: f64) -> f64 {
+        self.successes(min_real_value).len() as f64 / self.instances.len() as f64
+    }
+    // Ids of those paths.
+    pub fn successes(&self, min_real_value: f64) -> Vec<i32> {
+        self.instances
+            .iter()
+            .filter(|x| x.1.value() > 0.0 && x.1.inflation_adjusted(x.1.value()) >= min_real_value)
+            .map(|x| self.paths[x.0].id)
+            .collect()
     }
 }
 
-fn cfmt(x: f64) -> String {
-    format!("${}", (x.round() as i64).to_formatted_string(&Locale::en))
+// Reads the config's data files, for Scenario::with_data.
+pub fn load_data(is: &InitialState) -> Result<Vec<HistoricalYear>> {
+    load(&is.return_data).map_err(|message| {
+        ConfigError::Invalid(vec![Problem {
+            path: String::from("return_data"),
+            message,
+        }])
+        .into()
+    })
 }
 
-fn pfmt(x: f64) -> String {
-    format!("{:.1}%", 100.0 * x)
+// Value of one account, or all of them, in one instance.
+fn account_value(i: &Instance, a: &AccountType, inf: &InflationAdjustment) -> f64 {
+    let v = match a {
+        AccountType::Total => i.value(),
+        AccountType::PreTax => i.value_by_account().pre_tax,
+        AccountType::Roth => i.value_by_account().roth,
+        AccountType::AfterTax => i.value_by_account().after_tax,
+    };
+    match inf {
+        InflationAdjustment::Real => i.inflation_adjusted(v),
+        InflationAdjustment::Nominal => v,
+    }
 }
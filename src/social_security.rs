@@ -0,0 +1,79 @@
+use crate::config::SocialSecurity;
+
+// Full retirement age, in months, for someone born in the given year.
+fn full_retirement_age(birth_year: i32) -> i32 {
+    match birth_year {
+        y if y <= 1937 => 65 * 12,
+        y if y <= 1942 => 65 * 12 + 2 * (y - 1937),
+        y if y <= 1954 => 66 * 12,
+        y if y <= 1959 => 66 * 12 + 2 * (y - 1954),
+        _ => 67 * 12,
+    }
+}
+
+// Fraction of the primary insurance amount received when claiming at the given age. Early
+// benefits are reduced by 5/9 of 1% per month for the first 36 months before full retirement
+// age, and 5/12 of 1% per month before that. Delayed retirement credits are 2/3 of 1% per month.
+fn claiming_adjustment(birth_year: i32, claiming_age: i32) -> f64 {
+    debug_assert!(
+        (62..=70).contains(&claiming_age),
+        "Claiming age must be between 62 and 70, but is {}",
+        claiming_age
+    );
+    let months = claiming_age * 12 - full_retirement_age(birth_year);
+    if months < 0 {
+        let early = -months;
+        1.0 - early.min(36) as f64 * 5.0 / 900.0 - (early - 36).max(0) as f64 * 5.0 / 1200.0
+    } else {
+        1.0 + months as f64 * 2.0 / 300.0
+    }
+}
+
+// Nominal benefits received during the given calendar year. Benefits start in the year of the
+// claiming age, and the cost-of-living adjustment follows the inflation index.
+pub fn yearly_benefit(s: &SocialSecurity, year: i32, inflation: f64) -> f64 {
+    if year - s.birth_year < s.claiming_age {
+        0.0
+    } else {
+        12.0 * s.primary_insurance_amount
+            * claiming_adjustment(s.birth_year, s.claiming_age)
+            * inflation
+    }
+}
+
+#[cfg(test)]
+mod social_security_tests {
+    use crate::social_security::*;
+    #[cfg(test)]
+    use crate::{assert_eq_cents, assert_eq_decimal_places};
+
+    #[test]
+    fn full_retirement_age_test() {
+        assert_eq!(full_retirement_age(1937), 65 * 12);
+        assert_eq!(full_retirement_age(1940), 65 * 12 + 6);
+        assert_eq!(full_retirement_age(1950), 66 * 12);
+        assert_eq!(full_retirement_age(1957), 66 * 12 + 6);
+        assert_eq!(full_retirement_age(1960), 67 * 12);
+    }
+
+    #[test]
+    fn claiming_adjustment_test() {
+        assert_eq!(claiming_adjustment(1960, 67), 1.0);
+        assert_eq_decimal_places!(claiming_adjustment(1960, 62), 0.7, 6);
+        assert_eq_decimal_places!(claiming_adjustment(1960, 65), 1.0 - 0.2 * 2.0 / 3.0, 6);
+        assert_eq_decimal_places!(claiming_adjustment(1960, 70), 1.24, 6);
+        assert_eq_decimal_places!(claiming_adjustment(1950, 62), 0.75, 6);
+    }
+
+    #[test]
+    fn yearly_benefit_test() {
+        let s = SocialSecurity {
+            primary_insurance_amount: 2000.0,
+            birth_year: 1960,
+            claiming_age: 70,
+        };
+        assert_eq!(yearly_benefit(&s, 2029, 1.5), 0.0);
+        assert_eq_cents!(yearly_benefit(&s, 2030, 1.0), 29_760.0);
+        assert_eq_cents!(yearly_benefit(&s, 2031, 1.5), 44_640.0);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::config::{GrowthModel, InitialState, OutputFormat, Phase, PhaseType, SolveFor};
+use crate::error::{Error, Result};
+use crate::histret::HistoricalYear;
+use crate::report::{write_rows, ReportValue};
+use crate::scenario::{load_data, Scenario};
+use crate::validate::{ConfigError, Problem};
+
+use std::io;
+
+// Gives up looking for an amount on the other side of the target after this many doublings.
+const MAX_DOUBLINGS: usize = 60;
+// Working years beyond which the search gives up.
+const MAX_WORKING_YEARS: usize = 100;
+// Points on each side of a minimum in its success-rate curve.
+const CURVE_POINTS: usize = 3;
+
+#[derive(Debug)]
+pub struct Solution {
+    // Yearly withdrawal of the first withdrawal phase, in today's dollars.
+    pub amount: f64,
+    // The amount as a fraction of the initial balance, if there is one.
+    pub withdrawal_rate: Option<f64>,
+    pub success_rate: f64,
+    // Paths that succeed at the amount but fail if it is a cent higher. These are the starting
+    // years that limit the withdrawal.
+    pub binding_years: Vec<i32>,
+}
+
+impl Solution {
+    pub fn write(&self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        let keys: Vec<String> = ["Amount", "Withdrawal rate", "Success rate", "Binding years"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let row = vec![
+            ReportValue::Number(self.amount),
+            self.withdrawal_rate
+                .map_or(ReportValue::Text(String::new()), ReportValue::Percent),
+            ReportValue::Percent(self.success_rate),
+            ReportValue::Years(self.binding_years.clone()),
+        ];
+        write_rows(&keys, &keys, &[row], format, w)
+    }
+}
+
+// The smallest working years or yearly contribution that meets the target, with success rates
+// at nearby values.
+#[derive(Debug)]
+pub struct Minimum {
+    pub find: SolveFor,
+    pub value: f64,
+    pub success_rate: f64,
+    // Values in increasing order, and their success rates. Includes the minimum.
+    pub curve: Vec<(f64, f64)>,
+}
+
+impl Minimum {
+    pub fn write(&self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        let years = self.find == SolveFor::MinWorkingYears;
+        let keys: Vec<String> = [
+            if years {
+                "Working years"
+            } else {
+                "Contribution"
+            },
+            "Success rate",
+            "Minimum",
+        ]
+        .iter()
+        .map(|k| k.to_string())
+        .collect();
+        let rows: Vec<Vec<ReportValue>> = self
+            .curve
+            .iter()
+            .map(|(v, r)| {
+                vec![
+                    if years {
+                        ReportValue::Integer(*v as i64)
+                    } else {
+                        ReportValue::Number(*v)
+                    },
+                    ReportValue::Percent(*r),
+                    ReportValue::Text(String::from(if *v == self.value { "*" } else { "" })),
+                ]
+            })
+            .collect();
+        write_rows(&keys, &keys, &rows, format, w)
+    }
+}
+
+// The outcome of one run.
+struct Trial {
+    success_rate: f64,
+    successes: Vec<i32>,
+}
+
+// Amounts of one field of some phases, such as the withdrawal of withdrawal phases.
+type Field = fn(&mut Phase) -> Option<&mut f64>;
+
+// Runs a config, changing one value at a time, and checks results against its solve section.
+struct Solver {
+    is: InitialState,
+    // Read once, since every trial uses the same data files.
+    data: Vec<HistoricalYear>,
+    target: f64,
+    min_ending_balance: f64,
+}
+
+impl Solver {
+    fn new(mut is: InitialState) -> Result<Solver> {
+        let (target, min_ending_balance) = match &is.solve {
+            Some(s) => (s.success_rate / 100.0, s.min_ending_balance),
+            None => {
+                return Err(ConfigError::Invalid(vec![Problem {
+                    path: String::from("solve"),
+                    message: String::from("Missing the target success rate"),
+                }])
+                .into())
+            }
+        };
+        // Only the success rate is needed.
+        is.report.clear();
+        Ok(Solver {
+            data: load_data(&is)?,
+            is,
+            target,
+            min_ending_balance,
+        })
+    }
+    fn trial(&self) -> Result<Trial> {
+        let mut scenario = Scenario::with_data(&self.is, &self.data)?;
+        scenario.run()?;
+        Ok(Trial {
+            success_rate: scenario.success_ratio_above(self.min_ending_balance),
+            successes: scenario.successes(self.min_ending_balance),
+        })
+    }
+    fn meets(&self, t: &Trial) -> bool {
+        // Comparing fractions of a whole number of paths, so allow for rounding.
+        t.success_rate >= self.target - 1e-9
+    }
+    // The amount of the first phase that has one.
+    fn first(&mut self, field: Field) -> f64 {
+        self.is
+            .phases
+            .iter_mut()
+            .find_map(|p| field(p).copied())
+            .unwrap_or(0.0)
+    }
+    // Each phase's amount divided by the given total.
+    fn scale(&mut self, field: Field, total: f64) -> Vec<Option<f64>> {
+        self.is
+            .phases
+            .iter_mut()
+            .map(|p| field(p).map(|a| if total > 0.0 { *a / total } else { 1.0 }))
+            .collect()
+    }
+    // Sets each phase's amount to x times its scale.
+    fn set(&mut self, field: Field, scale: &[Option<f64>], x: f64) {
+        for (p, s) in self.is.phases.iter_mut().zip(scale) {
+            if let (Some(a), Some(s)) = (field(p), s) {
+                *a = x * s;
+            }
+        }
+    }
+    // Finds adjacent cents where below() changes from true to false, searching upwards from lo,
+    // for which it is true. Returns both ends.
+    fn boundary<F, B>(
+        &mut self,
+        lo: (u64, Trial),
+        guess: u64,
+        run: F,
+        below: B,
+    ) -> Result<[(u64, Trial); 2]>
+    where
+        F: Fn(&mut Solver, u64) -> Result<Trial>,
+        B: Fn(&Solver, &Trial) -> bool,
+    {
+        let mut lo = lo;
+        let mut hi = (guess, run(self, guess)?);
+        for _ in 0..MAX_DOUBLINGS {
+            if !below(self, &hi.1) {
+                break;
+            }
+            lo = hi;
+            let c = 2 * lo.0;
+            hi = (c, run(self, c)?);
+        }
+        if below(self, &hi.1) {
+            return Err(Error::NoSolution(String::from(
+                "The success rate does not cross the target at any amount",
+            )));
+        }
+        while hi.0 - lo.0 > 1 {
+            let c = (lo.0 + hi.0) / 2;
+            let t = run(self, c)?;
+            if below(self, &t) {
+                lo = (c, t);
+            } else {
+                hi = (c, t);
+            }
+        }
+        Ok([lo, hi])
+    }
+}
+
+// Finds the highest withdrawal that meets the target in the solve section of the config, using
+// bisection. Every withdrawal phase is scaled along with the first, so a plan that spends less in
+// later phases keeps its shape. Assumes that withdrawing more never makes more paths succeed.
+pub fn max_withdrawal(is: InitialState) -> Result<Solution> {
+    let mut s = Solver::new(is)?;
+    let first = s.first(withdrawal);
+    let scale = s.scale(withdrawal, first);
+    let b = &s.is.initial_balance;
+    let initial_balance = b.pre_tax.value + b.roth.value + b.after_tax.value + b.cash;
+
+    let run = move |s: &mut Solver, c: u64| {
+        s.set(withdrawal, &scale, dollars(c));
+        s.trial()
+    };
+    let none = run(&mut s, 0)?;
+    if !s.meets(&none) {
+        return Err(Error::NoSolution(format!(
+            "Only {:.1}% of paths succeed even without withdrawals",
+            100.0 * none.success_rate
+        )));
+    }
+    let guess = cents(first.max(initial_balance / 100.0)).max(1);
+    let [lo, hi] = s.boundary((0, none), guess, run, |s, t| s.meets(t))?;
+    let amount = dollars(lo.0);
+    let mut binding_years: Vec<i32> =
+        lo.1.successes
+            .iter()
+            .copied()
+            .filter(|id| !hi.1.successes.contains(id))
+            .collect();
+    binding_years.sort_unstable();
+    Ok(Solution {
+        amount,
+        withdrawal_rate: if initial_balance > 0.0 {
+            Some(amount / initial_balance)
+        } else {
+            None
+        },
+        success_rate: lo.1.success_rate,
+        binding_years,
+    })
+}
+
+// Finds the fewest years of the first Accumulation phase that meet the target. Tries every
+// number of years in turn, since historical paths that run past the end of the data are dropped,
+// so working longer can lower the success rate.
+pub fn min_working_years(is: InitialState) -> Result<Minimum> {
+    let mut s = Solver::new(is)?;
+    let i =
+        s.is.phases
+            .iter()
+            .position(|p| matches!(p.config, PhaseType::Accumulation(_)))
+            .ok_or_else(|| Error::NoSolution(String::from("There is no Accumulation phase")))?;
+    let rate = |s: &mut Solver, years: usize| -> Result<f64> {
+        s.is.phases[i].years = years;
+        Ok(s.trial()?.success_rate)
+    };
+    // Historical paths that run past the end of the data are dropped, so the plan can't be longer
+    // than the data. Trying one year anyway reports a plan that doesn't fit at all.
+    let max_years = match s.is.growth_model {
+        GrowthModel::HistoricalPath => {
+            let other = s.is.phases.iter().map(|p| p.years).sum::<usize>() - s.is.phases[i].years;
+            s.data
+                .len()
+                .saturating_sub(other)
+                .clamp(1, MAX_WORKING_YEARS)
+        }
+        _ => MAX_WORKING_YEARS,
+    };
+    let mut curve = Vec::new();
+    for years in 1..=max_years {
+        let r = rate(&mut s, years)?;
+        curve.push((years as f64, r));
+        if r >= s.target - 1e-9 {
+            for more in years + 1..=(years + CURVE_POINTS).min(max_years) {
+                curve.push((more as f64, rate(&mut s, more)?));
+            }
+            return Ok(Minimum {
+                find: SolveFor::MinWorkingYears,
+                value: years as f64,
+                success_rate: r,
+                curve: curve.split_off((years - 1).saturating_sub(CURVE_POINTS)),
+            });
+        }
+    }
+    Err(Error::NoSolution(format!(
+        "The target is not met with up to {} working years",
+        curve.len()
+    )))
+}
+
+// Finds the lowest total yearly contribution to the first Accumulation phase that meets the
+// target, using bisection. Every Accumulation phase is scaled along with it, keeping the split
+// between accounts. Assumes that contributing more never makes fewer paths succeed.
+pub fn min_contribution(is: InitialState) -> Result<Minimum> {
+    let mut s = Solver::new(is)?;
+    let fields: [Field; 3] = [pre_tax, roth, after_tax];
+    let total: f64 = fields.iter().map(|f| s.first(*f)).sum();
+    if total <= 0.0 {
+        return Err(Error::NoSolution(String::from(
+            "The first Accumulation phase has no contributions to scale",
+        )));
+    }
+    let scales: Vec<Vec<Option<f64>>> = fields.iter().map(|f| s.scale(*f, total)).collect();
+    let run = |s: &mut Solver, c: u64| {
+        for (f, scale) in fields.iter().zip(&scales) {
+            s.set(*f, scale, dollars(c));
+        }
+        s.trial()
+    };
+    let none = run(&mut s, 0)?;
+    let minimum = if s.meets(&none) {
+        0
+    } else {
+        let guess = cents(total);
+        let [_, hi] = s.boundary((0, none), guess, run, |s, t| !s.meets(t))?;
+        hi.0
+    };
+    // Steps of a tenth of the minimum on either side.
+    let step = (minimum / 10).max(1);
+    let mut curve = Vec::new();
+    for k in 0..=2 * CURVE_POINTS as u64 {
+        if let Some(c) = (minimum + k * step).checked_sub(CURVE_POINTS as u64 * step) {
+            curve.push((dollars(c), run(&mut s, c)?.success_rate));
+        }
+    }
+    let success_rate = curve[curve.len() - 1 - CURVE_POINTS].1;
+    Ok(Minimum {
+        find: SolveFor::MinContribution,
+        value: dollars(minimum),
+        success_rate,
+        curve,
+    })
+}
+
+// Amounts are found to the nearest cent.
+fn dollars(cents: u64) -> f64 {
+    cents as f64 / 100.0
+}
+
+fn cents(dollars: f64) -> u64 {
+    (dollars * 100.0).ceil() as u64
+}
+
+// The yearly withdrawal of a withdrawal phase.
+fn withdrawal(p: &mut Phase) -> Option<&mut f64> {
+    match &mut p.config {
+        PhaseType::SimpleWithdrawAndRebalance(w) => Some(&mut w.amount),
+        PhaseType::WithdrawTaxAware(w) => Some(&mut w.living_expenses),
+        _ => None,
+    }
+}
+
+fn pre_tax(p: &mut Phase) -> Option<&mut f64> {
+    match &mut p.config {
+        PhaseType::Accumulation(c) => Some(&mut c.pre_tax),
+        _ => None,
+    }
+}
+
+fn roth(p: &mut Phase) -> Option<&mut f64> {
+    match &mut p.config {
+        PhaseType::Accumulation(c) => Some(&mut c.roth),
+        _ => None,
+    }
+}
+
+fn after_tax(p: &mut Phase) -> Option<&mut f64> {
+    match &mut p.config {
+        PhaseType::Accumulation(c) => Some(&mut c.after_tax),
+        _ => None,
+    }
+}
@@ -0,0 +1,287 @@
+use crate::config::{OutputFormat, ReportField, Sweep, SweepAxis};
+use crate::error::Result;
+use crate::histret::HistoricalYear;
+use crate::report::{write_rows, ReportValue};
+use crate::scenario::{load_data, Scenario};
+use crate::validate::{from_value, from_value_with_data, ConfigError, Problem};
+
+use rayon::prelude::*;
+use serde_yaml::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The metric for every combination of values on one or two axes. Rows follow the first axis,
+// and columns the second.
+#[derive(Debug)]
+pub struct Grid {
+    pub metric: ReportField,
+    pub axes: Vec<SweepAxis>,
+    pub values: Vec<Vec<ReportValue>>,
+}
+
+impl Grid {
+    pub fn write(&self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        let mut keys = vec![self.axes[0].name()];
+        match self.axes.get(1) {
+            Some(a) => keys.extend((0..a.values.len()).map(|i| a.label(i))),
+            None => keys.push(self.metric.key()),
+        }
+        let rows: Vec<Vec<ReportValue>> = self
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let mut row = vec![ReportValue::Text(self.axes[0].label(i))];
+                row.extend(r.iter().cloned());
+                row
+            })
+            .collect();
+        write_rows(&keys, &keys, &rows, format, w)
+    }
+}
+
+// Runs the config once for each point on the grid. Relative data file paths are resolved
+// against dir.
+pub fn sweep(config: &Value, dir: &Path, s: &Sweep) -> Result<Grid> {
+    let (first, second) = match s.axes.as_slice() {
+        [a] => (a, None),
+        [a, b] => (a, Some(b)),
+        _ => return Err(invalid("sweep.axes", "Expected one or two axes")),
+    };
+    // Data files are read once for every point that doesn't change them.
+    let base = from_value(config.clone(), dir)?;
+    let data = load_data(&base)?;
+    // Every field is filled in, with defaults for those the file leaves out, so that an axis can
+    // set any of them while a misspelled path is an error. Data file paths are left as written,
+    // since they are resolved against dir again.
+    let mut full = serde_yaml::to_value(&base).map_err(ConfigError::Parse)?;
+    if let Some(r) = config.get("return_data") {
+        full["return_data"] = r.clone();
+    }
+    let config = &full;
+    let data = (base.return_data.as_slice(), data.as_slice());
+    // Every point is an independent run, so run them in parallel. Results are collected in grid
+    // order so the output, and the first error, are the same as in a serial run.
+    let columns = second.map_or(1, |b| b.values.len());
+    let results = (0..first.values.len() * columns)
+        .into_par_iter()
+        .map(|k| {
+            let mut c = config.clone();
+            set_all(&mut c, first, &first.values[k / columns])?;
+            if let Some(b) = second {
+                set_all(&mut c, b, &b.values[k % columns])?;
+            }
+            run(&c, dir, data, &s.metric)
+        })
+        .collect::<Vec<Result<ReportValue>>>()
+        .into_iter()
+        .collect::<Result<Vec<ReportValue>>>()?;
+    let values = results.chunks(columns).map(|r| r.to_vec()).collect();
+    Ok(Grid {
+        metric: s.metric.clone(),
+        axes: s.axes.clone(),
+        values,
+    })
+}
+
+// Runs a config with only the metric in its report, and returns the metric's final value. The
+// data is used if it was read from the config's data files.
+fn run(
+    config: &Value,
+    dir: &Path,
+    data: (&[PathBuf], &[HistoricalYear]),
+    metric: &ReportField,
+) -> Result<ReportValue> {
+    let mut is = from_value_with_data(config.clone(), dir, Some(data))?;
+    is.report = vec![metric.clone()];
+    let mut scenario = if is.return_data == data.0 {
+        Scenario::with_data(&is, data.1)?
+    } else {
+        Scenario::new(&is)?
+    };
+    scenario.run()?;
+    Ok(scenario.value(metric))
+}
+
+// Sets every path of the axis to its part of x.
+fn set_all(config: &mut Value, axis: &SweepAxis, x: &Value) -> Result<()> {
+    match axis.paths.as_slice() {
+        [p] => set(config, p, x.clone()),
+        paths => match x.as_sequence() {
+            Some(parts) if parts.len() == paths.len() => {
+                for (p, v) in paths.iter().zip(parts) {
+                    set(config, p, v.clone())?;
+                }
+                Ok(())
+            }
+            _ => Err(invalid(
+                &axis.name(),
+                &format!("Expected a list of {} values", paths.len()),
+            )),
+        },
+    }
+}
+
+// Replaces the value at a YAML path, which must already exist.
+pub fn set(config: &mut Value, path: &str, x: Value) -> Result<()> {
+    let mut v = config;
+    let parts: Vec<&str> = path.split('.').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let (key, indices) = parse_part(part).ok_or_else(|| invalid(path, "Malformed path"))?;
+        let last = i + 1 == parts.len() && indices.is_empty();
+        let m = v
+            .as_mapping_mut()
+            .ok_or_else(|| invalid(path, &format!("{} is not a map", key)))?;
+        v = m
+            .get_mut(&Value::String(key.to_string()))
+            .ok_or_else(|| invalid(path, &format!("No field named {}", key)))?;
+        if last {
+            *v = x;
+            return Ok(());
+        }
+        for (j, &n) in indices.iter().enumerate() {
+            let s = v
+                .as_sequence_mut()
+                .ok_or_else(|| invalid(path, &format!("{} is not a list", key)))?;
+            let len = s.len();
+            let e = s
+                .get_mut(n)
+                .ok_or_else(|| invalid(path, &format!("{} has only {} entries", key, len)))?;
+            if i + 1 == parts.len() && j + 1 == indices.len() {
+                *e = x;
+                return Ok(());
+            }
+            v = e;
+        }
+    }
+    Err(invalid(path, "Empty path"))
+}
+
+// Splits "phases[0]" into ("phases", [0]).
+fn parse_part(part: &str) -> Option<(&str, Vec<usize>)> {
+    let (key, rest) = match part.find('[') {
+        Some(i) => part.split_at(i),
+        None => (part, ""),
+    };
+    let mut indices = Vec::new();
+    let mut rest = rest;
+    while !rest.is_empty() {
+        let end = rest.find(']')?;
+        indices.push(rest.get(1..end)?.parse().ok()?);
+        rest = &rest[end + 1..];
+    }
+    if key.is_empty() {
+        None
+    } else {
+        Some((key, indices))
+    }
+}
+
+fn invalid(path: &str, message: &str) -> crate::Error {
+    ConfigError::Invalid(vec![Problem {
+        path: path.to_string(),
+        message: message.to_string(),
+    }])
+    .into()
+}
+
+#[cfg(test)]
+mod sweep_tests {
+    use crate::sweep::*;
+
+    fn config() -> Value {
+        serde_yaml::from_str(
+            "
+phases:
+- config:
+    SimpleWithdrawAndRebalance: { amount: 40, bond_percent: 50 }
+  years: 30
+tax:
+  filing_status: Single
+  changes:
+  - year: { Calendar: 2026 }
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_axis() {
+        let a: SweepAxis = "phases[0].years=20, 30".parse().unwrap();
+        assert_eq!(a.paths, vec!["phases[0].years"]);
+        assert_eq!(a.values, vec![Value::from(20), Value::from(30)]);
+        assert_eq!(a.name(), "phases[0].years");
+        assert_eq!(a.label(1), "30");
+        assert!("phases[0].years".parse::<SweepAxis>().is_err());
+    }
+
+    #[test]
+    fn set_several_paths() {
+        let mut c = config();
+        let a = SweepAxis {
+            name: Some(String::from("Bonds and years")),
+            paths: vec![
+                String::from("phases[0].config.SimpleWithdrawAndRebalance.bond_percent"),
+                String::from("phases[0].years"),
+            ],
+            values: vec![serde_yaml::from_str("[25, 20]").unwrap()],
+            labels: Vec::new(),
+        };
+        set_all(&mut c, &a, &a.values[0]).unwrap();
+        assert_eq!(a.label(0), "[25,20]");
+        let p = &c["phases"][0];
+        assert_eq!(
+            p["config"]["SimpleWithdrawAndRebalance"]["bond_percent"],
+            Value::from(25)
+        );
+        assert_eq!(p["years"], Value::from(20));
+        assert!(set_all(&mut c, &a, &Value::from(1)).is_err());
+    }
+
+    #[test]
+    fn set_values() {
+        let mut c = config();
+        set(
+            &mut c,
+            "phases[0].config.SimpleWithdrawAndRebalance.amount",
+            Value::from(35),
+        )
+        .unwrap();
+        set(
+            &mut c,
+            "tax.filing_status",
+            Value::from("MarriedFilingJointly"),
+        )
+        .unwrap();
+        set(&mut c, "tax.changes[0]", Value::from("x")).unwrap();
+        let expected: Value = serde_yaml::from_str(
+            "
+phases:
+- config:
+    SimpleWithdrawAndRebalance: { amount: 35, bond_percent: 50 }
+  years: 30
+tax:
+  filing_status: MarriedFilingJointly
+  changes:
+  - x
+",
+        )
+        .unwrap();
+        assert_eq!(c, expected);
+    }
+
+    #[test]
+    fn set_missing() {
+        let mut c = config();
+        assert!(set(&mut c, "phases[1].years", Value::from(1)).is_err());
+        assert!(set(&mut c, "growth_model.Bootstrap.seed", Value::from(1)).is_err());
+        assert!(set(&mut c, "phases[x].years", Value::from(1)).is_err());
+        assert!(set(
+            &mut c,
+            "phases[0].config.SimpleWithdrawAndRebalance.amout",
+            Value::from(1)
+        )
+        .is_err());
+        assert!(set(&mut c, "expense_ratio", Value::from(1)).is_err());
+    }
+}
@@ -1,265 +1,652 @@
-// Calculates taxes on retirement income for a married couple in Illinois, filing
-// jointly. Assumes that neither has wages, so there is no FICA tax.
-pub fn tax(i: f64, cg: f64) -> f64 {
-    let ill = illinois(i, cg);
-    let fed = federal(i, cg);
-    assert!(
-        ill >= 0.0,
-        "illinois tax = {}, income = {}, cg = {}",
-        ill,
-        i,
-        cg
-    );
-    assert!(
-        fed >= 0.0,
-        "federal tax = {}, income = {}, cg = {}",
-        fed,
-        i,
-        cg
-    );
-    ill + fed
+use crate::config::{
+    BracketIndexing, FederalTaxLaw, FederalTaxTable, FilingStatus, StateTax, TaxBracket, TaxConfig,
+    TaxLawChange, TaxYear,
+};
+
+use crate::histret::HistoricalYear;
+
+use serde::Deserialize;
+
+// Built-in federal tables for each filing status.
+#[derive(Deserialize)]
+struct FederalTaxTables {
+    single: FederalTaxTable,
+    married_filing_jointly: FederalTaxTable,
+    head_of_household: FederalTaxTable,
 }
 
-// Calculates marginal rate for income.
-fn marginal_rate(i: f64, cg: f64) -> f64 {
-    tax(i + 1.0, cg) - tax(i, cg)
+const FEDERAL_2020: &str = include_str!("tax/federal_2020.yaml");
+const FEDERAL_2017: &str = include_str!("tax/federal_2017.yaml");
+
+// The year whose dollars each built-in table is in. Custom tables have none.
+fn table_year(l: &FederalTaxLaw) -> Option<i32> {
+    match l {
+        FederalTaxLaw::Tcja2020 => Some(2020),
+        FederalTaxLaw::PreTcja2017 => Some(2017),
+        FederalTaxLaw::Custom(_) => None,
+    }
 }
 
-// Calculates the approximate greatest income for which the marginal tax rate is less than r.
-// It will under-estimate the correct upper limit by no more than $100.
-fn marginal_rate_to_income(r: f64) -> Option<f64> {
-    let mut low = 0.0;
-    let mut high = 1_000_000.0;
-    if r < marginal_rate(low, 0.0) || r > marginal_rate(high, 0.0) {
-        return Option::None;
-    }
-    loop {
-        if high - low < 100.0 {
-            return Some(low);
-        }
-        let guess = (low + high) / 2.0;
-        let mr = marginal_rate(guess, 0.0);
-        if r < mr {
-            high = guess;
-        } else {
-            low = guess;
-        }
+pub fn federal_2020(s: FilingStatus) -> FederalTaxTable {
+    built_in_federal(FEDERAL_2020, s)
+}
+
+pub fn federal_tables(l: &FederalTaxLaw, s: FilingStatus) -> FederalTaxTable {
+    match l {
+        FederalTaxLaw::Tcja2020 => federal_2020(s),
+        FederalTaxLaw::PreTcja2017 => built_in_federal(FEDERAL_2017, s),
+        FederalTaxLaw::Custom(t) => t.clone(),
     }
 }
 
-fn illinois(i: f64, cg: f64) -> f64 {
-    0.0495 * (i + cg.max(0.0))
+fn built_in_federal(data: &str, s: FilingStatus) -> FederalTaxTable {
+    let tables: FederalTaxTables =
+        serde_yaml::from_str(data).expect("Failed to parse built-in federal tax tables");
+    match s {
+        FilingStatus::Single => tables.single,
+        FilingStatus::MarriedFilingJointly => tables.married_filing_jointly,
+        FilingStatus::HeadOfHousehold => tables.head_of_household,
+    }
 }
 
-fn federal(i: f64, cg: f64) -> f64 {
-    let fi = fed_income(i);
-    let fcg = fed_cg(i, cg);
-    let niit = fed_niit(i, cg);
-    assert!(
-        fi >= 0.0,
-        "federal income tax = {}, income = {}, cg = {}",
-        fi,
-        i,
-        cg
-    );
-    assert!(
-        fcg >= 0.0,
-        "federal cap gains tax = {}, income = {}, cg = {}",
-        fcg,
-        i,
-        cg
-    );
-    assert!(
-        niit >= 0.0,
-        "fed niit = {}, income = {}, cg = {}",
-        niit,
-        i,
-        cg
-    );
-    fi + fcg + niit
+// The tax profiles in force over the simulation, as tax law changes.
+#[derive(Debug, Clone)]
+pub struct TaxSchedule {
+    // Sorted by the simulation year in which each profile takes effect.
+    profiles: Vec<(usize, TaxProfile)>,
 }
 
-#[rustfmt::skip]
-const FED_INCOME_BRACKETS: [(f64, f64); 7] = [
-    (0.37, 622_050.0),
-    (0.35, 414_700.0),
-    (0.32, 326_600.0),
-    (0.24, 171_050.0),
-    (0.22, 80_250.0),
-    (0.12, 19_750.0),
-    (0.10, 0.0),
-];
-fn fed_income(i: f64) -> f64 {
-    let mut tax = 0.0;
-    let income = (i - 24_800.0).max(0.0);
-    if income > FED_INCOME_BRACKETS[0].1 {
-        tax += FED_INCOME_BRACKETS[0].0 * (income - FED_INCOME_BRACKETS[0].1);
-    }
-    for j in 1..FED_INCOME_BRACKETS.len() {
-        if income > FED_INCOME_BRACKETS[j].1 {
-            tax += FED_INCOME_BRACKETS[j].0
-                * (income.min(FED_INCOME_BRACKETS[j - 1].1) - FED_INCOME_BRACKETS[j].1)
+impl TaxSchedule {
+    // Calendar years are converted to simulation years counting from start_year. Changes before
+    // the start of the simulation take effect immediately. Built-in federal tables are brought
+    // from their own year to start_year dollars with the inflation in data.
+    pub fn new(c: &TaxConfig, start_year: i32, data: &[HistoricalYear]) -> TaxSchedule {
+        let fallback = c
+            .table_inflation
+            .map_or_else(|| mean_inflation(data), |r| r / 100.0);
+        let to_start = |l: &FederalTaxLaw| {
+            table_year(l).map_or(1.0, |y| price_change(data, fallback, y, start_year))
+        };
+        let mut changes: Vec<(usize, &TaxLawChange)> = c
+            .changes
+            .iter()
+            .map(|ch| {
+                let y = match ch.year {
+                    TaxYear::Calendar(y) => (y - start_year).max(0) as usize,
+                    TaxYear::Simulation(y) => y,
+                };
+                (y, ch)
+            })
+            .collect();
+        changes.sort_by_key(|x| x.0);
+        let mut first = TaxProfile::new(c);
+        if c.federal.is_none() {
+            first.scale_federal(to_start(&FederalTaxLaw::Tcja2020));
         }
+        let mut profiles = vec![(0, first)];
+        for (y, ch) in changes {
+            let mut p = profiles.last().unwrap().1.changed(ch);
+            if let Some(l) = &ch.federal {
+                p.scale_federal(to_start(l));
+            }
+            profiles.push((y, p));
+        }
+        TaxSchedule { profiles }
+    }
+
+    // The profile in force during simulation year y.
+    pub fn profile(&self, y: usize) -> &TaxProfile {
+        &self.profiles.iter().rev().find(|p| p.0 <= y).unwrap().1
     }
-    tax
 }
 
-#[rustfmt::skip]
-const FED_CG_BRACKETS: [(f64, f64); 3] = [
-    (0.2, 496_600.0),
-    (0.15, 80_000.0),
-    (0.0, 0.0)
-];
-fn fed_cg(i: f64, cg: f64) -> f64 {
-    #[cfg(test)]
-    println!("=============== income = {}, cg = {}", i, cg);
-    let mut tax = 0.0;
-    let income = i + cg;
-    #[cfg(test)]
-    println!(
-        "bracket {}, {}%",
-        FED_CG_BRACKETS[0].1,
-        100.0 * FED_CG_BRACKETS[0].0
-    );
-    if income > FED_CG_BRACKETS[0].1 {
-        #[cfg(test)]
-        println!(
-            "eligible cg = {}, taxed at {}%",
-            (income - FED_CG_BRACKETS[0].1).min(cg),
-            100.0 * FED_CG_BRACKETS[0].0
+// Federal and state taxes for a household. Assumes that nobody has wages, so there is no FICA
+// tax.
+#[derive(Debug, Clone)]
+pub struct TaxProfile {
+    pub filing_status: FilingStatus,
+    federal: FederalTaxTable,
+    state: StateTax,
+    indexing: BracketIndexing,
+}
+
+impl TaxProfile {
+    pub fn new(c: &TaxConfig) -> TaxProfile {
+        let mut federal = c
+            .federal
+            .clone()
+            .unwrap_or_else(|| federal_2020(c.filing_status));
+        sort_brackets(&mut federal.income_brackets);
+        sort_brackets(&mut federal.capital_gains_brackets);
+        let mut state = c.state.clone();
+        if let StateTax::Progressive(b) = &mut state {
+            sort_brackets(b);
+        }
+        TaxProfile {
+            filing_status: c.filing_status,
+            federal,
+            state,
+            indexing: c.bracket_indexing,
+        }
+    }
+
+    // A copy of this profile, under changed tax law.
+    fn changed(&self, ch: &TaxLawChange) -> TaxProfile {
+        let mut p = self.clone();
+        if let Some(l) = &ch.federal {
+            p.federal = federal_tables(l, self.filing_status);
+            sort_brackets(&mut p.federal.income_brackets);
+            sort_brackets(&mut p.federal.capital_gains_brackets);
+        }
+        if let Some(s) = &ch.state {
+            p.state = s.clone();
+            if let StateTax::Progressive(b) = &mut p.state {
+                sort_brackets(b);
+            }
+        }
+        p
+    }
+
+    // Multiplies every federal dollar amount by f.
+    fn scale_federal(&mut self, f: f64) {
+        let t = &mut self.federal;
+        t.standard_deduction *= f;
+        t.niit_threshold *= f;
+        for b in t
+            .income_brackets
+            .iter_mut()
+            .chain(t.capital_gains_brackets.iter_mut())
+        {
+            b.threshold *= f;
+        }
+    }
+
+    // Grows the bracket index by one year of inflation, according to the indexing policy.
+    pub fn index_brackets(&self, index: f64, inflation: f64) -> f64 {
+        match self.indexing {
+            BracketIndexing::Cpi => index * (1.0 + inflation),
+            BracketIndexing::ChainedCpi { lag } => index * (1.0 + inflation - lag / 100.0),
+            BracketIndexing::Frozen => index,
+        }
+    }
+
+    // Calculates nominal taxes when brackets have grown by a factor of index.
+    pub fn indexed_tax(&self, index: f64, i: f64, cg: f64) -> f64 {
+        index * self.tax(i / index, cg / index)
+    }
+
+    // Calculates taxes on $i of ordinary income and $cg of long-term capital gains.
+    pub fn tax(&self, i: f64, cg: f64) -> f64 {
+        let state = self.state_tax(i, cg);
+        let fed = self.federal(i, cg);
+        debug_assert!(
+            state >= 0.0,
+            "state tax = {}, income = {}, cg = {}",
+            state,
+            i,
+            cg
         );
-        tax += FED_CG_BRACKETS[0].0 * (income - FED_CG_BRACKETS[0].1).min(cg);
-    }
-    for j in 1..FED_CG_BRACKETS.len() {
-        #[cfg(test)]
-        println!(
-            "bracket {}, {}%",
-            FED_CG_BRACKETS[j].1,
-            100.0 * FED_CG_BRACKETS[j].0
+        debug_assert!(
+            fed >= 0.0,
+            "federal tax = {}, income = {}, cg = {}",
+            fed,
+            i,
+            cg
         );
-        #[cfg(test)]
-        println!(
-            "income in prior brackets: {}",
-            (income - FED_CG_BRACKETS[j - 1].1).max(0.0)
+        state + fed
+    }
+
+    // The taxable part of $ss of Social Security benefits, when there is $other income. Up to
+    // 85% of benefits are taxable, depending on provisional income, which is other income plus
+    // half of benefits. The thresholds are not indexed to inflation.
+    pub fn taxable_social_security(&self, other: f64, ss: f64) -> f64 {
+        let (base, adjusted_base) = match self.filing_status {
+            FilingStatus::MarriedFilingJointly => (32_000.0, 44_000.0),
+            FilingStatus::Single | FilingStatus::HeadOfHousehold => (25_000.0, 34_000.0),
+        };
+        let provisional = other + ss / 2.0;
+        if provisional <= base {
+            0.0
+        } else if provisional <= adjusted_base {
+            (0.5 * ss).min(0.5 * (provisional - base))
+        } else {
+            (0.85 * ss).min(
+                0.85 * (provisional - adjusted_base) + (0.5 * ss).min(0.5 * (adjusted_base - base)),
+            )
+        }
+    }
+
+    // The most ordinary income taxed by the federal income tax brackets at no more than the given
+    // rate, in percent, including the standard deduction. Ignores state tax and NIIT, and
+    // under-estimates by no more than $100. Infinite if no bracket has a higher rate.
+    pub fn bracket_ceiling(&self, rate: f64) -> f64 {
+        let mut federal = TaxProfile {
+            state: StateTax::None,
+            ..self.clone()
+        };
+        federal.federal.niit_threshold = f64::INFINITY;
+        // Allow for rounding error in the marginal rate within a bracket.
+        federal
+            .marginal_rate_to_income(rate / 100.0 + 1e-6)
+            .unwrap_or(f64::INFINITY)
+    }
+
+    // Calculates marginal rate for income.
+    fn marginal_rate(&self, i: f64, cg: f64) -> f64 {
+        self.tax(i + 1.0, cg) - self.tax(i, cg)
+    }
+
+    // Calculates the approximate greatest income for which the marginal tax rate is less than r.
+    // It will under-estimate the correct upper limit by no more than $100.
+    fn marginal_rate_to_income(&self, r: f64) -> Option<f64> {
+        // Search past the top bracket, however far the brackets have been scaled.
+        let top = |b: &[TaxBracket]| b.last().map_or(0.0, |x| x.threshold);
+        let state_top = match &self.state {
+            StateTax::Progressive(b) => top(b),
+            StateTax::None | StateTax::Flat(_) => 0.0,
+        };
+        let federal_top = top(&self.federal.income_brackets) + self.federal.standard_deduction;
+        let mut low = 0.0;
+        let mut high = (federal_top.max(state_top) + 1_000.0).max(1_000_000.0);
+        if r < self.marginal_rate(low, 0.0) || r > self.marginal_rate(high, 0.0) {
+            return Option::None;
+        }
+        loop {
+            if high - low < 100.0 {
+                return Some(low);
+            }
+            let guess = (low + high) / 2.0;
+            let mr = self.marginal_rate(guess, 0.0);
+            if r < mr {
+                high = guess;
+            } else {
+                low = guess;
+            }
+        }
+    }
+
+    fn state_tax(&self, i: f64, cg: f64) -> f64 {
+        let income = i + cg.max(0.0);
+        match &self.state {
+            StateTax::None => 0.0,
+            StateTax::Flat(r) => r / 100.0 * income,
+            StateTax::Progressive(b) => bracket_tax(income, b),
+        }
+    }
+
+    fn federal(&self, i: f64, cg: f64) -> f64 {
+        let fi = self.fed_income(i);
+        let fcg = self.fed_cg(i, cg);
+        let niit = self.fed_niit(i, cg);
+        debug_assert!(
+            fi >= 0.0,
+            "federal income tax = {}, income = {}, cg = {}",
+            fi,
+            i,
+            cg
         );
-        let prev_cg = (income - FED_CG_BRACKETS[j - 1].1).max(0.0).min(cg);
-        #[cfg(test)]
-        println!("cg in prior brackets: {}", prev_cg);
-        #[cfg(test)]
-        println!(
-            "income in this bracket: {}",
-            (income.min(FED_CG_BRACKETS[j - 1].1) - FED_CG_BRACKETS[j].1).max(0.0)
+        debug_assert!(
+            fcg >= 0.0,
+            "federal cap gains tax = {}, income = {}, cg = {}",
+            fcg,
+            i,
+            cg
         );
-        let eligible_cg = (income.min(FED_CG_BRACKETS[j - 1].1) - FED_CG_BRACKETS[j].1)
-            .max(0.0)
-            .min(cg)
-            - prev_cg;
-        #[cfg(test)]
-        println!("cg in this bracket: {}", eligible_cg);
-        #[cfg(test)]
-        println!(
-            "eligible cg = {}, taxed at {}%",
-            eligible_cg,
-            100.0 * FED_CG_BRACKETS[j].0
+        debug_assert!(
+            niit >= 0.0,
+            "fed niit = {}, income = {}, cg = {}",
+            niit,
+            i,
+            cg
         );
-        tax += eligible_cg * FED_CG_BRACKETS[j].0;
+        fi + fcg + niit
+    }
+
+    fn fed_income(&self, i: f64) -> f64 {
+        bracket_tax(
+            (i - self.federal.standard_deduction).max(0.0),
+            &self.federal.income_brackets,
+        )
+    }
+
+    // Capital gains are stacked on top of ordinary income, and each bracket's rate applies to
+    // the capital gains that fall within it.
+    fn fed_cg(&self, i: f64, cg: f64) -> f64 {
+        let b = &self.federal.capital_gains_brackets;
+        let mut tax = 0.0;
+        for j in 0..b.len() {
+            let top = b.get(j + 1).map_or(f64::INFINITY, |x| x.threshold);
+            let eligible_cg = ((i + cg).min(top) - i.max(b[j].threshold)).max(0.0);
+            tax += eligible_cg * b[j].rate / 100.0;
+        }
+        tax
+    }
+
+    // 3.8% of total income over the threshold. Assumes we are retired, and income is coming
+    // from interest and dividends, not wages.
+    fn fed_niit(&self, i: f64, cg: f64) -> f64 {
+        0.038 * (i + cg - self.federal.niit_threshold).max(0.0)
+    }
+}
+
+// How much prices grew from the start of calendar year from to the start of to, or shrank if to is
+// earlier. Years missing from data grow by fallback.
+fn price_change(data: &[HistoricalYear], fallback: f64, from: i32, to: i32) -> f64 {
+    let growth = |y: i32| {
+        1.0 + data
+            .iter()
+            .find(|h| h.year == y)
+            .map_or(fallback, |h| h.inflation)
+    };
+    if to >= from {
+        (from..to).map(growth).product()
+    } else {
+        1.0 / (to..from).map(growth).product::<f64>()
     }
-    tax
 }
 
-// 3.8% of total income over $250k. Assumes we are retired, and income is coming
-// from interest and dividends, not wages.
-fn fed_niit(i: f64, cg: f64) -> f64 {
-    0.038 * (i + cg - 250_000.0).max(0.0)
+fn mean_inflation(data: &[HistoricalYear]) -> f64 {
+    if data.is_empty() {
+        0.0
+    } else {
+        data.iter().map(|h| h.inflation).sum::<f64>() / data.len() as f64
+    }
 }
 
-// Calculates the value of investments to sell in order to have $l in living expenses
-// after taxes, if those investments generate $i in interest in dividends, and have
-// appreciated such that cg_ratio (between 0 and 1) of their value is unrealized
-// capital gains.
-pub fn how_much_to_sell(l: f64, i: f64, cg_ratio: f64) -> f64 {
-    assert!(cg_ratio <= 1.0);
-    let mut guess = (l - i).max(0.0);
-    assert!(
-        guess >= 0.0,
-        "guess = {}, l = {}, i = {}, cg_ratio = {}",
-        guess,
-        l,
-        i,
-        cg_ratio
-    );
-    while i + guess - tax(i, guess * cg_ratio) < l {
-        // Linear search in $1k increments. We can do better, but this is OK for now.
-        guess += 1000.0;
-    }
-    guess
+fn sort_brackets(b: &mut [TaxBracket]) {
+    b.sort_by(|x, y| x.threshold.partial_cmp(&y.threshold).unwrap());
+}
+
+// Tax on income under progressive brackets, sorted by threshold.
+fn bracket_tax(income: f64, b: &[TaxBracket]) -> f64 {
+    let mut tax = 0.0;
+    for j in 0..b.len() {
+        let top = b.get(j + 1).map_or(f64::INFINITY, |x| x.threshold);
+        if income > b[j].threshold {
+            tax += b[j].rate / 100.0 * (income.min(top) - b[j].threshold);
+        }
+    }
+    tax
 }
 
 mod tax_tests {
     #[cfg(test)]
     use super::*;
     #[cfg(test)]
+    use crate::histret::RETURNS;
+    #[cfg(test)]
     use crate::{assert_eq_cents, assert_eq_decimal_places};
 
+    #[cfg(test)]
+    fn profile(filing_status: FilingStatus, state: StateTax) -> TaxProfile {
+        TaxProfile::new(&TaxConfig {
+            filing_status,
+            state,
+            federal: None,
+            bracket_indexing: BracketIndexing::Cpi,
+            changes: Vec::new(),
+            table_inflation: None,
+        })
+    }
+
+    // Married filing jointly, in Illinois.
+    #[cfg(test)]
+    fn mfj() -> TaxProfile {
+        TaxProfile::new(&TaxConfig::default())
+    }
+
     #[test]
     fn tax_test() {
-        assert_eq_cents!(illinois(50_000.0, 75_000.0), 6187.5);
-        assert_eq!(fed_income(50_000.0), 2_629.0);
-        assert_eq!(fed_cg(50_000.0, 75_000.0), 6_750.0);
-        assert_eq!(fed_niit(50_000.0, 75_000.0), 0.0);
-        assert_eq_cents!(tax(50_000.0, 75_000.0), 6187.5 + 2_629.0 + 6_750.0);
+        let p = mfj();
+        assert_eq_cents!(p.state_tax(50_000.0, 75_000.0), 6187.5);
+        assert_eq!(p.fed_income(50_000.0), 2_629.0);
+        assert_eq!(p.fed_cg(50_000.0, 75_000.0), 6_750.0);
+        assert_eq!(p.fed_niit(50_000.0, 75_000.0), 0.0);
+        assert_eq_cents!(p.tax(50_000.0, 75_000.0), 6187.5 + 2_629.0 + 6_750.0);
     }
 
     #[test]
     fn illinois_test() {
-        assert_eq!(illinois(0.0, 0.0), 0.0);
-        assert_eq!(illinois(100.0, 0.0), 4.95);
-        assert_eq!(illinois(0.0, 100.0), 4.95);
-        assert_eq_cents!(illinois(25_000.0, 25_000.0), 2475.0);
-        assert_eq!(illinois(501_000.0, 0.0), 24_799.5);
+        let p = mfj();
+        assert_eq!(p.state_tax(0.0, 0.0), 0.0);
+        assert_eq!(p.state_tax(100.0, 0.0), 4.95);
+        assert_eq!(p.state_tax(0.0, 100.0), 4.95);
+        assert_eq_cents!(p.state_tax(25_000.0, 25_000.0), 2475.0);
+        assert_eq!(p.state_tax(501_000.0, 0.0), 24_799.5);
+    }
+
+    #[test]
+    fn state_test() {
+        let p = profile(FilingStatus::MarriedFilingJointly, StateTax::None);
+        assert_eq!(p.state_tax(100_000.0, 100_000.0), 0.0);
+        let p = profile(
+            FilingStatus::MarriedFilingJointly,
+            StateTax::Progressive(vec![
+                TaxBracket {
+                    rate: 5.0,
+                    threshold: 10_000.0,
+                },
+                TaxBracket {
+                    rate: 0.0,
+                    threshold: 0.0,
+                },
+            ]),
+        );
+        assert_eq!(p.state_tax(5_000.0, 0.0), 0.0);
+        assert_eq!(p.state_tax(20_000.0, 10_000.0), 1_000.0);
+        assert_eq!(p.state_tax(20_000.0, -10_000.0), 500.0);
     }
 
     #[test]
     fn fed_income_test() {
-        assert_eq!(fed_income(20_000.0), 0.0);
-        assert_eq!(fed_income(24_800.0 + 1000.0), 100.0);
-        assert_eq!(fed_income(50_000.0), 2_629.0);
-        assert_eq!(fed_income(125_000.0), 13_624.0);
-        assert_eq!(fed_income(250_000.0), 42_207.0);
-        assert_eq!(fed_income(350_000.0), 66_207.0);
-        assert_eq!(fed_income(450_000.0), 98_410.0);
-        assert_eq!(fed_income(750_000.0), 205_473.0);
+        let p = mfj();
+        assert_eq!(p.fed_income(20_000.0), 0.0);
+        assert_eq!(p.fed_income(24_800.0 + 1000.0), 100.0);
+        assert_eq!(p.fed_income(50_000.0), 2_629.0);
+        assert_eq!(p.fed_income(125_000.0), 13_624.0);
+        assert_eq!(p.fed_income(250_000.0), 42_207.0);
+        assert_eq!(p.fed_income(350_000.0), 66_207.0);
+        assert_eq!(p.fed_income(450_000.0), 98_410.0);
+        assert_eq!(p.fed_income(750_000.0), 205_473.0);
+    }
+
+    #[test]
+    fn fed_income_filing_status_test() {
+        let p = profile(FilingStatus::Single, StateTax::None);
+        assert_eq!(p.fed_income(12_400.0), 0.0);
+        assert_eq_cents!(
+            p.fed_income(62_400.0),
+            987.5 + 0.12 * 30_250.0 + 0.22 * 9_875.0
+        );
+        let p = profile(FilingStatus::HeadOfHousehold, StateTax::None);
+        assert_eq!(p.fed_income(18_650.0), 0.0);
+        assert_eq_cents!(p.fed_income(18_650.0 + 20_000.0), 1_410.0 + 0.12 * 5_900.0);
     }
 
     #[test]
     fn fed_cg_test() {
-        assert_eq!(fed_cg(0.0, 20_000.0), 0.0);
-        assert_eq!(fed_cg(80_000.0, 20_000.0), 3_000.0);
-        assert_eq!(fed_cg(400_000.0, 100.0), 15.00);
-        assert_eq!(fed_cg(600_000.0, 100.0), 20.00);
-        assert_eq!(fed_cg(490_000.0, 10_000.0), 0.15 * 6_600.0 + 0.20 * 3_400.0);
-        assert_eq!(fed_cg(900_000.0, 0.0), 0.0);
+        let p = mfj();
+        assert_eq!(p.fed_cg(0.0, 20_000.0), 0.0);
+        assert_eq!(p.fed_cg(80_000.0, 20_000.0), 3_000.0);
+        assert_eq!(p.fed_cg(400_000.0, 100.0), 15.00);
+        assert_eq!(p.fed_cg(600_000.0, 100.0), 20.00);
+        assert_eq!(
+            p.fed_cg(490_000.0, 10_000.0),
+            0.15 * 6_600.0 + 0.20 * 3_400.0
+        );
+        assert_eq!(p.fed_cg(900_000.0, 0.0), 0.0);
+        // Capital gains spanning all three brackets.
+        assert_eq_cents!(
+            p.fed_cg(50_000.0, 500_000.0),
+            0.15 * 416_600.0 + 0.20 * 53_400.0
+        );
     }
 
     #[test]
     fn fed_niit_test() {
-        assert_eq!(fed_niit(100_000.0, 100_000.0), 0.0);
-        assert_eq!(fed_niit(130_000.0, 130_000.0), 380.0);
+        let p = mfj();
+        assert_eq!(p.fed_niit(100_000.0, 100_000.0), 0.0);
+        assert_eq!(p.fed_niit(130_000.0, 130_000.0), 380.0);
+        let p = profile(FilingStatus::Single, StateTax::None);
+        assert_eq!(p.fed_niit(100_000.0, 110_000.0), 380.0);
     }
 
     #[test]
-    fn how_much_to_sell_test() {
-        assert_eq!(how_much_to_sell(180_000.0, 50_000.0, 0.5), 146_000.0);
+    fn custom_federal_test() {
+        let p = TaxProfile::new(&TaxConfig {
+            filing_status: FilingStatus::Single,
+            state: StateTax::None,
+            federal: Some(FederalTaxTable {
+                standard_deduction: 10_000.0,
+                income_brackets: vec![TaxBracket {
+                    rate: 20.0,
+                    threshold: 0.0,
+                }],
+                capital_gains_brackets: vec![TaxBracket {
+                    rate: 10.0,
+                    threshold: 0.0,
+                }],
+                niit_threshold: 1_000_000.0,
+            }),
+            bracket_indexing: BracketIndexing::Cpi,
+            changes: Vec::new(),
+            table_inflation: None,
+        });
+        assert_eq!(p.tax(60_000.0, 10_000.0), 11_000.0);
     }
 
     #[test]
     fn marginal_rate_test() {
-        assert_eq_decimal_places!(marginal_rate(200_000.0, 10_000.0), 0.2895, 4);
-        assert_eq_decimal_places!(marginal_rate(250_000.0, 0.0), 0.3275, 4);
+        let p = mfj();
+        assert_eq_decimal_places!(p.marginal_rate(200_000.0, 10_000.0), 0.2895, 4);
+        assert_eq_decimal_places!(p.marginal_rate(250_000.0, 0.0), 0.3275, 4);
     }
 
     #[test]
     fn marginal_rate_to_income_test() {
-        assert_eq_decimal_places!(marginal_rate_to_income(0.3).unwrap(), 249939.0, 0);
+        assert_eq_decimal_places!(mfj().marginal_rate_to_income(0.3).unwrap(), 249939.0, 0);
+    }
+
+    #[test]
+    fn index_brackets_test() {
+        let mut c = TaxConfig::default();
+        assert_eq_decimal_places!(mfj().index_brackets(1.1, 0.03), 1.133, 6);
+        c.bracket_indexing = BracketIndexing::ChainedCpi { lag: 0.25 };
+        assert_eq_decimal_places!(TaxProfile::new(&c).index_brackets(1.1, 0.03), 1.13025, 6);
+        c.bracket_indexing = BracketIndexing::Frozen;
+        assert_eq!(TaxProfile::new(&c).index_brackets(1.1, 0.03), 1.1);
+    }
+
+    #[test]
+    fn indexed_tax_test() {
+        let p = mfj();
+        assert_eq!(
+            p.indexed_tax(1.0, 50_000.0, 75_000.0),
+            p.tax(50_000.0, 75_000.0)
+        );
+        assert_eq_cents!(
+            p.indexed_tax(2.0, 100_000.0, 150_000.0),
+            2.0 * p.tax(50_000.0, 75_000.0)
+        );
+        // Unindexed brackets push more income into higher brackets.
+        assert!(
+            p.indexed_tax(1.0, 100_000.0, 150_000.0) > p.indexed_tax(2.0, 100_000.0, 150_000.0)
+        );
+    }
+
+    #[test]
+    fn pre_tcja_test() {
+        let mut p = mfj();
+        p.federal = federal_tables(
+            &FederalTaxLaw::PreTcja2017,
+            FilingStatus::MarriedFilingJointly,
+        );
+        assert_eq!(p.fed_income(50_000.0), 3_447.5);
+        assert_eq!(p.fed_cg(50_000.0, 75_000.0), 7_365.0);
+    }
+
+    #[test]
+    fn schedule_test() {
+        let c = TaxConfig {
+            changes: vec![
+                TaxLawChange {
+                    year: TaxYear::Simulation(10),
+                    federal: None,
+                    state: Some(StateTax::None),
+                },
+                TaxLawChange {
+                    year: TaxYear::Calendar(2026),
+                    federal: Some(FederalTaxLaw::PreTcja2017),
+                    state: None,
+                },
+            ],
+            ..TaxConfig::default()
+        };
+        // The 2020 tables are already in 2020 dollars.
+        let s = TaxSchedule::new(&c, 2020, &RETURNS);
+        assert_eq!(s.profile(5).tax(50_000.0, 0.0), mfj().tax(50_000.0, 0.0));
+        // The 2017 tables grow with inflation of 2.130%, 2.443% and 2.290% in 2017 to 2019, so the
+        // standard deduction is $22,260.36 and the 10% bracket ends at $19,959.41.
+        let pre_tcja = s.profile(6).tax(50_000.0, 0.0);
+        assert_eq_cents!(pre_tcja, 3_162.98 + 2_475.0);
+        assert_eq!(s.profile(9).tax(50_000.0, 0.0), pre_tcja);
+        assert_eq_cents!(s.profile(10).tax(50_000.0, 0.0), 3_162.98);
+
+        // Changes before the start of the simulation apply immediately. Years after the data
+        // grow by table_inflation.
+        let s = TaxSchedule::new(
+            &TaxConfig {
+                table_inflation: Some(2.0),
+                ..c
+            },
+            2030,
+            &RETURNS,
+        );
+        assert_eq_cents!(s.profile(0).tax(50_000.0, 0.0), 2_307.76 + 2_475.0);
+    }
+
+    #[test]
+    fn taxable_social_security_test() {
+        let p = mfj();
+        assert_eq!(p.taxable_social_security(10_000.0, 30_000.0), 0.0);
+        assert_eq!(p.taxable_social_security(25_000.0, 30_000.0), 4_000.0);
+        assert_eq!(p.taxable_social_security(40_000.0, 30_000.0), 15_350.0);
+        assert_eq!(p.taxable_social_security(100_000.0, 30_000.0), 25_500.0);
+        let p = profile(FilingStatus::Single, StateTax::None);
+        assert_eq!(p.taxable_social_security(25_000.0, 30_000.0), 9_600.0);
+    }
+
+    #[test]
+    fn bracket_ceiling_test() {
+        let p = mfj();
+        let near = |rate: f64, exact: f64| {
+            let c = p.bracket_ceiling(rate);
+            assert!(c <= exact && c > exact - 100.0, "{} for {}%", c, rate);
+        };
+        near(0.0, 24_800.0);
+        near(12.0, 105_050.0);
+        near(22.0, 195_850.0);
+        near(23.0, 195_850.0);
+        // Neither state tax nor NIIT counts.
+        near(32.0, 24_800.0 + 414_700.0);
+        assert_eq!(p.bracket_ceiling(37.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn bracket_ceiling_far_future() {
+        // By 2100, at 3% a year past the data, the 35% bracket ends well past $1M.
+        let s = TaxSchedule::new(
+            &TaxConfig {
+                table_inflation: Some(3.0),
+                ..TaxConfig::default()
+            },
+            2100,
+            &RETURNS,
+        );
+        let p = s.profile(0);
+        let c = p.bracket_ceiling(35.0);
+        assert!(c.is_finite() && c > 1_000_000.0, "{}", c);
+        assert!(p.bracket_ceiling(32.0) < c);
+        assert_eq!(p.bracket_ceiling(37.0), f64::INFINITY);
     }
 }
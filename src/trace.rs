@@ -0,0 +1,105 @@
+use crate::config::OutputFormat;
+use crate::instance::Instance;
+use crate::report::{write_rows, ReportValue};
+
+use std::io;
+
+// Year-by-year state of every instance, for auditing individual paths.
+#[derive(Debug)]
+pub struct Trace {
+    // If set, only the path with this id is recorded.
+    path: Option<i32>,
+    // Calendar year in which the simulation starts.
+    start_year: i32,
+    keys: Vec<String>,
+    // Path id, years elapsed, and the row.
+    rows: Vec<(i32, usize, Vec<ReportValue>)>,
+}
+
+impl Trace {
+    pub fn new(path: Option<i32>, start_year: i32) -> Trace {
+        Trace {
+            path,
+            start_year,
+            keys: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+    pub fn record(&mut self, id: i32, year: usize, i: &Instance) {
+        if self.path.is_some_and(|p| p != id) {
+            return;
+        }
+        let t = i.trace();
+        if self.keys.is_empty() {
+            self.keys = vec![
+                String::from("Path"),
+                String::from("Year"),
+                String::from("Calendar year"),
+            ];
+            self.keys.extend(t.iter().map(|x| x.0.clone()));
+        }
+        let mut row = vec![
+            ReportValue::Integer(id as i64),
+            ReportValue::Integer(year as i64),
+            ReportValue::Integer(self.start_year as i64 + year as i64),
+        ];
+        row.extend(t.into_iter().map(|x| x.1));
+        self.rows.push((id, year, row));
+    }
+    // Writes rows ordered by path, then year.
+    pub fn write(&mut self, format: &OutputFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        self.rows.sort_by_key(|r| (r.0, r.1));
+        let rows: Vec<Vec<ReportValue>> = self.rows.iter().map(|r| r.2.clone()).collect();
+        write_rows(&self.keys, &self.keys, &rows, format, w)
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use crate::account::{Account, Joint};
+    use crate::config::Portfolio;
+    use crate::trace::*;
+
+    fn instance(v: f64) -> Instance {
+        let joint = |a| Joint::new(a, &Portfolio::default()).unwrap();
+        Instance::new(
+            joint(Account::new(v, 0.0).unwrap()),
+            joint(Account::new(0.0, 0.0).unwrap()),
+            Account::new(0.0, 0.0).unwrap(),
+            0.0,
+            2026,
+        )
+    }
+
+    #[test]
+    fn record() {
+        let mut t = Trace::new(None, 2026);
+        t.record(1966, 1, &instance(200.0));
+        t.record(1928, 1, &instance(300.0));
+        t.record(1966, 0, &instance(100.0));
+        let mut out = Vec::new();
+        t.write(&OutputFormat::Csv, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(
+            lines[0].starts_with("Path,Year,Calendar year,PreTax stocks UsStocks,PreTax bonds TBonds,Roth stocks UsStocks")
+        );
+        assert!(
+            lines[0].ends_with(",Spending,Max drawdown,Perfect withdrawal rate,Inflation index")
+        );
+        assert!(lines[1].starts_with("1928,1,2027,300,0,"));
+        assert!(lines[2].starts_with("1966,0,2026,100,0,"));
+        assert!(lines[3].starts_with("1966,1,2027,200,0,"));
+        assert!(lines[3].ends_with(",1"));
+    }
+
+    #[test]
+    fn filter() {
+        let mut t = Trace::new(Some(1966), 2026);
+        t.record(1966, 0, &instance(100.0));
+        t.record(1928, 0, &instance(300.0));
+        assert_eq!(t.rows.len(), 1);
+        assert_eq!(t.rows[0].0, 1966);
+    }
+}
@@ -0,0 +1,1178 @@
+use crate::config::{
+    Allocation, AssetClass, CashBucket, ConversionAmount, FederalTaxLaw, FilingStatus, GrowthModel,
+    InitialState, Measure, PhaseType, Portfolio, ReportField, ReturnDistribution, SolveFor,
+    SpendingRule, StateTax, TaxBracket, Weight,
+};
+use crate::growth::{try_cholesky, with_row};
+use crate::histret::{self, HistoricalYear};
+
+use serde_yaml::Value;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The oldest anyone in the plan can be when it starts.
+const MAX_AGE: i32 = 120;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(serde_yaml::Error),
+    Invalid(Vec<Problem>),
+}
+
+// A single invalid value, located by its YAML path, such as phases[1].config.Accumulation.roth.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config: {}", e),
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid config:")?;
+                for p in problems {
+                    write!(f, "\n  {}: {}", p.path, p.message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Reads, parses and validates a config file. Relative data file paths are resolved against the
+// file's directory.
+pub fn load(path: &Path) -> Result<InitialState, ConfigError> {
+    let text = fs::read_to_string(path).map_err(ConfigError::Read)?;
+    check(&text, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+// Parses and validates a config. Relative data file paths are resolved against the current
+// directory.
+pub fn from_str(text: &str) -> Result<InitialState, ConfigError> {
+    check(text, Path::new(""))
+}
+
+fn check(text: &str, dir: &Path) -> Result<InitialState, ConfigError> {
+    from_value(serde_yaml::from_str(text).map_err(ConfigError::Parse)?, dir)
+}
+
+// Validates a parsed config. Relative data file paths are resolved against dir.
+pub fn from_value(value: Value, dir: &Path) -> Result<InitialState, ConfigError> {
+    from_value_with_data(value, dir, None)
+}
+
+// Like from_value, but with data already read from the given data files. They are only used if
+// they are the config's return_data.
+pub fn from_value_with_data(
+    value: Value,
+    dir: &Path,
+    data: Option<(&[PathBuf], &[HistoricalYear])>,
+) -> Result<InitialState, ConfigError> {
+    let mut problems = check_contributions(&value);
+    let mut is: InitialState = serde_yaml::from_value(value).map_err(ConfigError::Parse)?;
+    is.resolve_paths(dir);
+    let data = data.and_then(|(files, d)| (files == is.return_data.as_slice()).then_some(d));
+    if let Err(ConfigError::Invalid(p)) = check_with_data(&is, data) {
+        problems.extend(p);
+    }
+    if problems.is_empty() {
+        Ok(is)
+    } else {
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+// Reports every invalid value in the config, rather than just the first.
+pub fn validate(is: &InitialState) -> Result<(), ConfigError> {
+    check_with_data(is, None)
+}
+
+// Data files are read to check return series, unless their data is given.
+fn check_with_data(is: &InitialState, data: Option<&[HistoricalYear]>) -> Result<(), ConfigError> {
+    let mut v = Validator {
+        problems: Vec::new(),
+    };
+    v.initial_balance(is);
+    v.phases(is);
+    v.percent("expense_ratio", is.expense_ratio);
+    v.growth_model(&is.growth_model);
+    v.portfolio(is, data);
+    v.social_security(is);
+    v.spouse(is);
+    v.tax(is);
+    v.report(is);
+    v.sweep(is);
+    v.solve(is);
+    if v.problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(v.problems))
+    }
+}
+
+// Things that are allowed, but probably not what was meant.
+pub fn warnings(is: &InitialState) -> Vec<Problem> {
+    let mut v = Validator {
+        problems: Vec::new(),
+    };
+    v.unused_report_fields(is);
+    v.problems
+}
+
+// Unknown fields are ignored when parsing, so contributions given in a withdrawal phase would
+// silently do nothing.
+fn check_contributions(value: &Value) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let phases = match value.get("phases").and_then(|p| p.as_sequence()) {
+        Some(p) => p,
+        None => return problems,
+    };
+    for (i, phase) in phases.iter().enumerate() {
+        for kind in &["SimpleWithdrawAndRebalance", "WithdrawTaxAware"] {
+            let c = match phase.get("config").and_then(|c| c.get(kind)) {
+                Some(c) => c,
+                None => continue,
+            };
+            for field in &["pre_tax", "roth", "after_tax", "target_bond_percent"] {
+                if c.get(field).is_some() {
+                    problems.push(Problem {
+                        path: format!("phases[{}].config.{}.{}", i, kind, field),
+                        message: String::from(
+                            "Contributions are not allowed in a withdrawal phase. \
+                             Use an Accumulation phase instead.",
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    problems
+}
+
+struct Validator {
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn problem(&mut self, path: &str, message: String) {
+        self.problems.push(Problem {
+            path: path.to_string(),
+            message,
+        });
+    }
+    fn non_negative(&mut self, path: &str, x: f64) {
+        if x < 0.0 || x.is_nan() {
+            self.problem(path, format!("Should not be negative, but was {}", x));
+        }
+    }
+    fn percent(&mut self, path: &str, x: f64) {
+        if !(0.0..=100.0).contains(&x) {
+            self.problem(path, format!("Should be between 0 and 100, but was {}", x));
+        }
+    }
+    // Someone alive when the plan starts.
+    fn birth_year(&mut self, path: &str, year: i32, start_year: i32) {
+        if !(start_year - MAX_AGE..=start_year).contains(&year) {
+            self.problem(
+                path,
+                format!(
+                    "Should be between {} and the start_year {}, but was {}",
+                    start_year - MAX_AGE,
+                    start_year,
+                    year
+                ),
+            );
+        }
+    }
+
+    fn initial_balance(&mut self, is: &InitialState) {
+        let b = &is.initial_balance;
+        self.allocation("initial_balance.pre_tax", &b.pre_tax);
+        self.allocation("initial_balance.roth", &b.roth);
+        self.allocation("initial_balance.after_tax", &b.after_tax);
+        if let Some(basis) = b.after_tax_cost_basis {
+            self.non_negative("initial_balance.after_tax_cost_basis", basis);
+            if basis > b.after_tax.value {
+                self.problem(
+                    "initial_balance.after_tax_cost_basis",
+                    format!(
+                        "Cost basis {} is more than the after-tax value {}",
+                        basis, b.after_tax.value
+                    ),
+                );
+            }
+        }
+        self.non_negative("initial_balance.cash", b.cash);
+    }
+    fn allocation(&mut self, path: &str, a: &Allocation) {
+        self.non_negative(&format!("{}.value", path), a.value);
+        self.percent(&format!("{}.bond_percent", path), a.bond_percent);
+    }
+
+    fn phases(&mut self, is: &InitialState) {
+        if is.phases.is_empty() {
+            self.problem("phases", String::from("The plan has no phases"));
+        } else if is.phases.iter().all(|p| p.years == 0) {
+            self.problem("phases", String::from("The plan lasts 0 years"));
+        }
+        for (i, p) in is.phases.iter().enumerate() {
+            let path = format!("phases[{}]", i);
+            if p.years == 0 && is.phases.len() > 1 {
+                self.problem(
+                    &format!("{}.years", path),
+                    String::from("Should be positive"),
+                );
+            }
+            match &p.config {
+                PhaseType::Accumulation(c) => {
+                    let path = format!("{}.config.Accumulation", path);
+                    self.non_negative(&format!("{}.pre_tax", path), c.pre_tax);
+                    self.non_negative(&format!("{}.roth", path), c.roth);
+                    self.non_negative(&format!("{}.after_tax", path), c.after_tax);
+                    self.percent(
+                        &format!("{}.target_bond_percent", path),
+                        c.target_bond_percent,
+                    );
+                }
+                PhaseType::Growth => (),
+                PhaseType::SimpleWithdrawAndRebalance(w) => {
+                    let path = format!("{}.config.SimpleWithdrawAndRebalance", path);
+                    self.withdrawal(&format!("{}.amount", path), w.amount);
+                    self.percent(&format!("{}.bond_percent", path), w.bond_percent);
+                    self.cash_bucket(&path, &w.cash_bucket);
+                    self.spending(&format!("{}.spending", path), &w.spending);
+                }
+                PhaseType::WithdrawTaxAware(w) => {
+                    let path = format!("{}.config.WithdrawTaxAware", path);
+                    self.withdrawal(&format!("{}.living_expenses", path), w.living_expenses);
+                    self.birth_year(&format!("{}.birth_year", path), w.birth_year, is.start_year);
+                    self.percent(&format!("{}.bond_percent", path), w.bond_percent);
+                    if let Some(c) = &w.roth_conversion {
+                        match c.amount {
+                            ConversionAmount::FillBracket(r) => self.percent(
+                                &format!("{}.roth_conversion.amount.FillBracket", path),
+                                r,
+                            ),
+                            ConversionAmount::Fixed(a) => self
+                                .non_negative(&format!("{}.roth_conversion.amount.Fixed", path), a),
+                        }
+                    }
+                    self.cash_bucket(&path, &w.cash_bucket);
+                    self.spending(&format!("{}.spending", path), &w.spending);
+                }
+            }
+        }
+    }
+    fn withdrawal(&mut self, path: &str, x: f64) {
+        if x < 0.0 {
+            self.problem(
+                path,
+                format!(
+                    "Withdrawals should not be negative, but was {}. Use an Accumulation phase \
+                     for contributions.",
+                    x
+                ),
+            );
+        }
+    }
+    fn cash_bucket(&mut self, path: &str, c: &Option<CashBucket>) {
+        if let Some(c) = c {
+            self.non_negative(&format!("{}.cash_bucket.years", path), c.years);
+        }
+    }
+
+    fn spending(&mut self, path: &str, r: &SpendingRule) {
+        match r {
+            SpendingRule::Constant => (),
+            SpendingRule::PercentOfPortfolio(p) => {
+                self.percent(&format!("{}.PercentOfPortfolio", path), *p)
+            }
+            SpendingRule::VariablePercentage(r) => {
+                if *r <= -100.0 || r.is_nan() {
+                    self.problem(
+                        &format!("{}.VariablePercentage", path),
+                        format!("Should be more than -100, but was {}", r),
+                    );
+                }
+            }
+            SpendingRule::Guardrails(g) => {
+                let path = format!("{}.Guardrails", path);
+                self.non_negative(&format!("{}.upper", path), g.upper);
+                self.percent(&format!("{}.lower", path), g.lower);
+                self.percent(&format!("{}.adjustment", path), g.adjustment);
+            }
+            SpendingRule::FloorAndCeiling(f) => {
+                let path = format!("{}.FloorAndCeiling", path);
+                self.percent(&format!("{}.percent", path), f.percent);
+                self.non_negative(&format!("{}.floor", path), f.floor);
+                if f.ceiling < f.floor {
+                    self.problem(
+                        &format!("{}.ceiling", path),
+                        format!(
+                            "Should be at least the floor {}, but was {}",
+                            f.floor, f.ceiling
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn growth_model(&mut self, m: &GrowthModel) {
+        match m {
+            GrowthModel::HistoricalPath => (),
+            GrowthModel::Bootstrap(b) => self.paths("growth_model.Bootstrap.paths", b.paths),
+            GrowthModel::BlockBootstrap(b) => {
+                self.paths("growth_model.BlockBootstrap.paths", b.paths);
+                if b.block_years == 0 {
+                    self.problem(
+                        "growth_model.BlockBootstrap.block_years",
+                        String::from("Should be positive"),
+                    );
+                }
+            }
+            GrowthModel::Parametric(p) => {
+                self.paths("growth_model.Parametric.paths", p.paths);
+                self.distribution("growth_model.Parametric.stocks", &p.stocks);
+                self.distribution("growth_model.Parametric.bonds", &p.bonds);
+                self.non_negative(
+                    "growth_model.Parametric.inflation.std_dev",
+                    p.inflation.std_dev,
+                );
+                if !(0.0..1.0).contains(&p.inflation.persistence) {
+                    self.problem(
+                        "growth_model.Parametric.inflation.persistence",
+                        format!(
+                            "Should be at least 0 and less than 1, but was {}",
+                            p.inflation.persistence
+                        ),
+                    );
+                }
+                let c = &p.correlation;
+                if try_cholesky(c).is_none() {
+                    self.problem(
+                        "growth_model.Parametric.correlation",
+                        String::from("Should be positive definite"),
+                    );
+                }
+                for (i, row) in c.iter().enumerate() {
+                    for (j, &x) in row.iter().enumerate() {
+                        let path = format!("growth_model.Parametric.correlation[{}][{}]", i, j);
+                        if i == j && x != 1.0 {
+                            self.problem(&path, format!("Should be 1, but was {}", x));
+                        } else if !(-1.0..=1.0).contains(&x) {
+                            self.problem(
+                                &path,
+                                format!("Should be between -1 and 1, but was {}", x),
+                            );
+                        } else if j < i && x != c[j][i] {
+                            self.problem(
+                                &path,
+                                format!("Should equal [{}][{}], {}", j, i, c[j][i]),
+                            );
+                        }
+                    }
+                }
+                if let Some(a) = &p.aaabonds {
+                    self.distribution("growth_model.Parametric.aaabonds", &a.returns);
+                    for (i, &x) in a.correlation.iter().enumerate() {
+                        if !(-1.0..=1.0).contains(&x) {
+                            self.problem(
+                                &format!("growth_model.Parametric.aaabonds.correlation[{}]", i),
+                                format!("Should be between -1 and 1, but was {}", x),
+                            );
+                        }
+                    }
+                    if try_cholesky(c).is_some()
+                        && try_cholesky(&with_row(c, &a.correlation)).is_none()
+                    {
+                        self.problem(
+                            "growth_model.Parametric.aaabonds.correlation",
+                            String::from("Should leave the whole correlation positive definite"),
+                        );
+                    }
+                }
+            }
+            GrowthModel::Fixed(f) => {
+                self.non_negative("growth_model.Fixed.dividend_yield", f.dividend_yield);
+                // Prices would fall to nothing or less, and real values are divided by them.
+                if f.inflation <= -100.0 || f.inflation.is_nan() {
+                    self.problem(
+                        "growth_model.Fixed.inflation",
+                        format!("Should be more than -100, but was {}", f.inflation),
+                    );
+                }
+            }
+        }
+    }
+    fn paths(&mut self, path: &str, n: usize) {
+        if n == 0 {
+            self.problem(path, String::from("Should be positive"));
+        }
+    }
+    fn distribution(&mut self, path: &str, d: &ReturnDistribution) {
+        self.non_negative(&format!("{}.std_dev", path), d.std_dev);
+        self.non_negative(&format!("{}.income_yield", path), d.income_yield);
+    }
+
+    fn portfolio(&mut self, is: &InitialState, data: Option<&[HistoricalYear]>) {
+        // Data files are only read if everything else about them checks out.
+        let historical = matches!(
+            is.growth_model,
+            GrowthModel::HistoricalPath
+                | GrowthModel::Bootstrap(_)
+                | GrowthModel::BlockBootstrap(_)
+        );
+        let series = if is.return_data.is_empty() {
+            Some(Vec::new())
+        } else if let Some(d) = data {
+            Some(d[0].series.keys().cloned().collect())
+        } else {
+            match histret::load(&is.return_data) {
+                Ok(data) => Some(data[0].series.keys().cloned().collect()),
+                Err(e) => {
+                    self.problem("return_data", e);
+                    None
+                }
+            }
+        };
+        let ib = &is.initial_balance;
+        let mut portfolios = vec![(String::from("portfolio"), &is.portfolio)];
+        let mut accounts = vec![
+            ("initial_balance.pre_tax", &ib.pre_tax),
+            ("initial_balance.roth", &ib.roth),
+            ("initial_balance.after_tax", &ib.after_tax),
+        ];
+        if let Some(sp) = &is.spouse {
+            accounts.push(("spouse.pre_tax", &sp.pre_tax));
+            accounts.push(("spouse.roth", &sp.roth));
+        }
+        for (path, a) in accounts {
+            if let Some(p) = &a.portfolio {
+                portfolios.push((format!("{}.portfolio", path), p));
+            }
+        }
+        // Parametric models only give Aaa bonds their own returns when asked to.
+        let aaabonds =
+            !matches!(&is.growth_model, GrowthModel::Parametric(p) if p.aaabonds.is_none());
+        for (prefix, p) in portfolios {
+            self.portfolio_weights(&prefix, p, historical, aaabonds, &series);
+        }
+    }
+    fn portfolio_weights(
+        &mut self,
+        prefix: &str,
+        p: &Portfolio,
+        historical: bool,
+        aaabonds: bool,
+        series: &Option<Vec<String>>,
+    ) {
+        for (part, weights) in &[("stocks", &p.stocks), ("bonds", &p.bonds)] {
+            let path = format!("{}.{}", prefix, part);
+            self.weights(&path, weights);
+            for (i, w) in weights.iter().enumerate() {
+                if w.asset == AssetClass::AaaBonds && !aaabonds {
+                    self.problem(
+                        &format!("{}[{}].asset", path, i),
+                        String::from("AaaBonds need growth_model.Parametric.aaabonds"),
+                    );
+                }
+                if let AssetClass::Series(name) = &w.asset {
+                    let path = format!("{}[{}].asset", path, i);
+                    if !historical {
+                        self.problem(
+                            &path,
+                            String::from("Return series need a historical growth model"),
+                        );
+                    } else if let Some(s) = series {
+                        if !s.contains(name) {
+                            self.problem(
+                                &path,
+                                format!("No return_data file has a {} series", name),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn weights(&mut self, path: &str, weights: &[Weight]) {
+        if weights.is_empty() {
+            self.problem(path, String::from("No asset classes given"));
+            return;
+        }
+        for (i, w) in weights.iter().enumerate() {
+            self.percent(&format!("{}[{}].percent", path, i), w.percent);
+        }
+        let total: f64 = weights.iter().map(|w| w.percent).sum();
+        if (total - 100.0).abs() > 1e-6 {
+            self.problem(path, format!("Weights add up to {}%, not 100%", total));
+        }
+    }
+
+    fn social_security(&mut self, is: &InitialState) {
+        for (i, s) in is.social_security.iter().enumerate() {
+            self.non_negative(
+                &format!("social_security[{}].primary_insurance_amount", i),
+                s.primary_insurance_amount,
+            );
+            self.birth_year(
+                &format!("social_security[{}].birth_year", i),
+                s.birth_year,
+                is.start_year,
+            );
+            if !(62..=70).contains(&s.claiming_age) {
+                self.problem(
+                    &format!("social_security[{}].claiming_age", i),
+                    format!("Should be between 62 and 70, but was {}", s.claiming_age),
+                );
+            }
+        }
+    }
+
+    fn spouse(&mut self, is: &InitialState) {
+        let s = match &is.spouse {
+            Some(s) => s,
+            None => return,
+        };
+        self.birth_year("spouse.birth_year", s.birth_year, is.start_year);
+        self.allocation("spouse.pre_tax", &s.pre_tax);
+        self.allocation("spouse.roth", &s.roth);
+        if is.tax.filing_status != FilingStatus::MarriedFilingJointly {
+            self.problem(
+                "tax.filing_status",
+                format!(
+                    "Should be MarriedFilingJointly for a couple, but was {:?}",
+                    is.tax.filing_status
+                ),
+            );
+        }
+        // The survivor files as single, but custom tables are only given for filing jointly.
+        if s.first_death.is_some() {
+            let message = || {
+                String::from(
+                    "Custom federal tables can't be used with spouse.first_death, since the \
+                     survivor files as single",
+                )
+            };
+            if is.tax.federal.is_some() {
+                self.problem("tax.federal", message());
+            }
+            for (i, c) in is.tax.changes.iter().enumerate() {
+                if let Some(FederalTaxLaw::Custom(_)) = c.federal {
+                    self.problem(&format!("tax.changes[{}].federal", i), message());
+                }
+            }
+        }
+    }
+
+    fn tax(&mut self, is: &InitialState) {
+        self.state_tax("tax.state", &is.tax.state);
+        if let Some(f) = &is.tax.federal {
+            self.non_negative("tax.federal.standard_deduction", f.standard_deduction);
+            self.brackets("tax.federal.income_brackets", &f.income_brackets);
+            self.brackets(
+                "tax.federal.capital_gains_brackets",
+                &f.capital_gains_brackets,
+            );
+        }
+        for (i, c) in is.tax.changes.iter().enumerate() {
+            if let Some(s) = &c.state {
+                self.state_tax(&format!("tax.changes[{}].state", i), s);
+            }
+        }
+        if let Some(r) = is.tax.table_inflation {
+            if r <= -100.0 || r.is_nan() {
+                self.problem(
+                    "tax.table_inflation",
+                    format!("Should be more than -100, but was {}", r),
+                );
+            }
+        }
+    }
+    fn state_tax(&mut self, path: &str, s: &StateTax) {
+        match s {
+            StateTax::None => (),
+            StateTax::Flat(r) => self.percent(&format!("{}.Flat", path), *r),
+            StateTax::Progressive(b) => self.brackets(&format!("{}.Progressive", path), b),
+        }
+    }
+    fn brackets(&mut self, path: &str, brackets: &[TaxBracket]) {
+        for (i, b) in brackets.iter().enumerate() {
+            self.percent(&format!("{}[{}].rate", path, i), b.rate);
+            self.non_negative(&format!("{}[{}].threshold", path, i), b.threshold);
+        }
+    }
+
+    fn report(&mut self, is: &InitialState) {
+        for (path, f) in report_fields(is) {
+            if let Some(Measure::Percentile(p)) = measure(f) {
+                self.percent(&format!("{}.Percentile", path), *p);
+            }
+        }
+    }
+    // Some fields are only computed by particular phases, and would always report zero otherwise.
+    // They are still valid, for instance to compare against a config that has those phases.
+    fn unused_report_fields(&mut self, is: &InitialState) {
+        let tax_aware: Vec<_> = is
+            .phases
+            .iter()
+            .filter_map(|p| match &p.config {
+                PhaseType::WithdrawTaxAware(w) => Some(w),
+                _ => None,
+            })
+            .collect();
+        let withdrawal = is.phases.iter().any(|p| {
+            matches!(
+                p.config,
+                PhaseType::SimpleWithdrawAndRebalance(_) | PhaseType::WithdrawTaxAware(_)
+            )
+        });
+        let cash_bucket = is.phases.iter().any(|p| match &p.config {
+            PhaseType::SimpleWithdrawAndRebalance(w) => w.cash_bucket.is_some(),
+            PhaseType::WithdrawTaxAware(w) => w.cash_bucket.is_some(),
+            _ => false,
+        });
+        for (path, f) in report_fields(is) {
+            let unsupported = match f {
+                ReportField::RequiredMinimumDistribution(_)
+                | ReportField::Taxes(_)
+                | ReportField::TaxRate(_)
+                    if tax_aware.is_empty() =>
+                {
+                    Some("a WithdrawTaxAware phase")
+                }
+                ReportField::SocialSecurity(_)
+                    if tax_aware.is_empty() || is.social_security.is_empty() =>
+                {
+                    Some("social_security benefits and a WithdrawTaxAware phase")
+                }
+                ReportField::RothConversion(_)
+                    if !tax_aware.iter().any(|w| w.roth_conversion.is_some()) =>
+                {
+                    Some("a WithdrawTaxAware phase with a roth_conversion")
+                }
+                ReportField::Cash(_) if is.initial_balance.cash == 0.0 && !cash_bucket => {
+                    Some("initial_balance.cash or a cash_bucket")
+                }
+                ReportField::Spending(_)
+                | ReportField::YearsUntilDepletion(_)
+                | ReportField::ShortfallTotal(_)
+                | ReportField::ShortfallYears(_)
+                | ReportField::PerfectWithdrawalRate(_)
+                | ReportField::PerfectWithdrawalRateStartingYear(_)
+                    if !withdrawal =>
+                {
+                    Some("a withdrawal phase")
+                }
+                _ => None,
+            };
+            if let Some(u) = unsupported {
+                self.problem(
+                    &path,
+                    format!("{} would always be 0 without {}", field_name(f), u),
+                );
+            }
+        }
+    }
+    fn solve(&mut self, is: &InitialState) {
+        let s = match &is.solve {
+            Some(s) => s,
+            None => return,
+        };
+        self.percent("solve.success_rate", s.success_rate);
+        self.non_negative("solve.min_ending_balance", s.min_ending_balance);
+        if !is.phases.iter().any(|p| {
+            matches!(
+                p.config,
+                PhaseType::SimpleWithdrawAndRebalance(_) | PhaseType::WithdrawTaxAware(_)
+            )
+        }) {
+            self.problem(
+                "solve",
+                String::from("There is no withdrawal phase to solve for"),
+            );
+        }
+        // MaxWithdrawal scales the phases' amounts, so it can't find anything if the amount
+        // doesn't decide spending. Doubling the amount would never fail, and the search would
+        // give up with no solution.
+        if s.find == SolveFor::MaxWithdrawal {
+            for (i, p) in is.phases.iter().enumerate() {
+                let (name, spending) = match &p.config {
+                    PhaseType::SimpleWithdrawAndRebalance(w) => {
+                        ("SimpleWithdrawAndRebalance", &w.spending)
+                    }
+                    PhaseType::WithdrawTaxAware(w) => ("WithdrawTaxAware", &w.spending),
+                    _ => continue,
+                };
+                let why = match spending {
+                    SpendingRule::PercentOfPortfolio(_) | SpendingRule::VariablePercentage(_) => {
+                        "spending is a share of the portfolio, and doesn't depend on the amount"
+                    }
+                    SpendingRule::FloorAndCeiling(_) => {
+                        "the amount only bounds spending, which is a share of the portfolio"
+                    }
+                    SpendingRule::Constant | SpendingRule::Guardrails(_) => continue,
+                };
+                self.problem(
+                    &format!("phases[{}].config.{}.spending", i, name),
+                    format!("Can't solve for MaxWithdrawal, because {}", why),
+                );
+            }
+        }
+        if s.find != SolveFor::MaxWithdrawal
+            && !is
+                .phases
+                .iter()
+                .any(|p| matches!(p.config, PhaseType::Accumulation(_)))
+        {
+            self.problem(
+                "solve.find",
+                format!("{:?} needs an Accumulation phase", s.find),
+            );
+        }
+    }
+    fn sweep(&mut self, is: &InitialState) {
+        let s = match &is.sweep {
+            Some(s) => s,
+            None => return,
+        };
+        if s.axes.is_empty() || s.axes.len() > 2 {
+            self.problem("sweep.axes", String::from("Expected one or two axes"));
+        }
+        for (i, a) in s.axes.iter().enumerate() {
+            let path = format!("sweep.axes[{}]", i);
+            if a.paths.is_empty() {
+                self.problem(&format!("{}.paths", path), String::from("No paths"));
+            }
+            if a.values.is_empty() {
+                self.problem(&format!("{}.values", path), String::from("No values"));
+            }
+            if !a.labels.is_empty() && a.labels.len() != a.values.len() {
+                self.problem(
+                    &format!("{}.labels", path),
+                    format!("Expected a label for each of the {} values", a.values.len()),
+                );
+            }
+            if a.paths.len() > 1 {
+                for (j, v) in a.values.iter().enumerate() {
+                    if v.as_sequence().map(|x| x.len()) != Some(a.paths.len()) {
+                        self.problem(
+                            &format!("{}.values[{}]", path, j),
+                            format!("Expected a list of {} values, one per path", a.paths.len()),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn measure(f: &ReportField) -> Option<&Measure> {
+    match f {
+        ReportField::Value(m, _, _)
+        | ReportField::StartingYear(m)
+        | ReportField::InterestAndDividends(m)
+        | ReportField::StocksSold(m)
+        | ReportField::StocksBought(m)
+        | ReportField::CapitalGains(m)
+        | ReportField::RequiredMinimumDistribution(m)
+        | ReportField::Taxes(m)
+        | ReportField::SocialSecurity(m)
+        | ReportField::RothConversion(m)
+        | ReportField::Cash(m)
+        | ReportField::ExpensesDoubleCheck(m)
+        | ReportField::TaxRate(m)
+        | ReportField::Spending(m)
+        | ReportField::YearsUntilDepletion(m)
+        | ReportField::ShortfallTotal(m)
+        | ReportField::ShortfallYears(m)
+        | ReportField::MaxDrawdown(m)
+        | ReportField::UlcerIndex(m)
+        | ReportField::PerfectWithdrawalRate(m)
+        | ReportField::PerfectWithdrawalRateStartingYear(m)
+        | ReportField::BondPercent(m, _)
+        | ReportField::CapGainsPercent(m) => Some(m),
+        _ => None,
+    }
+}
+
+// Report fields and the sweep metric, with their paths.
+fn report_fields(is: &InitialState) -> impl Iterator<Item = (String, &ReportField)> {
+    let metric = is
+        .sweep
+        .as_ref()
+        .map(|s| (String::from("sweep.metric"), &s.metric));
+    is.report
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (format!("report[{}]", i), f))
+        .chain(metric)
+}
+
+// The variant name, without its arguments.
+fn field_name(f: &ReportField) -> String {
+    let s = format!("{:?}", f);
+    s.split('(').next().unwrap_or(&s).to_string()
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use crate::validate::*;
+
+    const VALID: &str = "
+initial_balance:
+  pre_tax: { value: 100, bond_percent: 40 }
+  roth: { value: 100, bond_percent: 40 }
+  after_tax: { value: 100, bond_percent: 40 }
+  after_tax_cost_basis: 80
+phases:
+- config:
+    SimpleWithdrawAndRebalance:
+      amount: 12
+      bond_percent: 40
+  years: 30
+expense_ratio: 0.1
+report:
+- YearsElapsed
+- Value: [Median, Total, Real]
+";
+
+    fn problems(text: &str) -> Vec<Problem> {
+        match from_str(text) {
+            Err(ConfigError::Invalid(p)) => p,
+            r => panic!("Expected problems, got {:?}", r),
+        }
+    }
+    fn paths(text: &str) -> Vec<String> {
+        problems(text).into_iter().map(|p| p.path).collect()
+    }
+
+    #[test]
+    fn valid() {
+        from_str(VALID).unwrap();
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(matches!(
+            from_str("initial_balance: 3"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        let text = VALID
+            .replace("pre_tax: { value: 100", "pre_tax: { value: -1")
+            .replace(
+                "roth: { value: 100, bond_percent: 40",
+                "roth: { value: 100, bond_percent: 140",
+            )
+            .replace("expense_ratio: 0.1", "expense_ratio: -0.1");
+        assert_eq!(
+            paths(&text),
+            vec![
+                "initial_balance.pre_tax.value",
+                "initial_balance.roth.bond_percent",
+                "expense_ratio"
+            ]
+        );
+    }
+
+    #[test]
+    fn birth_years() {
+        let text = VALID.replace(
+            "SimpleWithdrawAndRebalance:\n      amount: 12\n      bond_percent: 40",
+            "WithdrawTaxAware:\n      living_expenses: 12\n      birth_year: 2031\n      bond_percent: 40",
+        ) + "
+start_year: 2030
+social_security:
+- { primary_insurance_amount: 2000, birth_year: 1900, claiming_age: 67 }
+- { primary_insurance_amount: 2000, birth_year: 1965, claiming_age: 61 }
+spouse:
+  birth_year: 2035
+  pre_tax: { value: 100, bond_percent: 40 }
+  roth: { value: 100, bond_percent: 40 }
+tax:
+  filing_status: MarriedFilingJointly
+";
+        let p = problems(&text);
+        assert_eq!(
+            p.iter().map(|p| p.path.as_str()).collect::<Vec<&str>>(),
+            vec![
+                "phases[0].config.WithdrawTaxAware.birth_year",
+                "social_security[0].birth_year",
+                "social_security[1].claiming_age",
+                "spouse.birth_year"
+            ]
+        );
+        assert_eq!(
+            p[0].message,
+            "Should be between 1910 and the start_year 2030, but was 2031"
+        );
+    }
+
+    #[test]
+    fn fixed_inflation() {
+        let text = VALID.to_string()
+            + "growth_model:\n  Fixed: { stocks: 5, bonds: 2, cash: 0, dividend_yield: 2, inflation: -100, adjustment: Real }\n";
+        assert_eq!(
+            problems(&text),
+            vec![Problem {
+                path: String::from("growth_model.Fixed.inflation"),
+                message: String::from("Should be more than -100, but was -100"),
+            }]
+        );
+    }
+
+    #[test]
+    fn basis_greater_than_value() {
+        let p = problems(&VALID.replace("after_tax_cost_basis: 80", "after_tax_cost_basis: 120"));
+        assert_eq!(
+            p,
+            vec![Problem {
+                path: String::from("initial_balance.after_tax_cost_basis"),
+                message: String::from("Cost basis 120 is more than the after-tax value 100"),
+            }]
+        );
+    }
+
+    #[test]
+    fn contributions_in_withdrawal_phase() {
+        let text = VALID.replace("      amount: 12\n", "      amount: -12\n      roth: 10\n");
+        assert_eq!(
+            paths(&text),
+            vec![
+                "phases[0].config.SimpleWithdrawAndRebalance.roth",
+                "phases[0].config.SimpleWithdrawAndRebalance.amount",
+            ]
+        );
+        let text = VALID.replace("      amount: 12\n", "      amount: -12\n");
+        assert_eq!(
+            paths(&text),
+            vec!["phases[0].config.SimpleWithdrawAndRebalance.amount"]
+        );
+    }
+
+    #[test]
+    fn zero_length_plan() {
+        assert_eq!(
+            paths(&VALID.replace("years: 30", "years: 0")),
+            vec!["phases"]
+        );
+    }
+
+    #[test]
+    fn unsupported_report_fields() {
+        let text = VALID.to_string()
+            + "- Taxes: Median\n- Cash: Median\n- StocksSold:\n    Percentile: 101\n";
+        assert_eq!(paths(&text), vec!["report[4].Percentile"]);
+        let text = VALID.to_string() + "- Taxes: Median\n- Cash: Median\n";
+        let w: Vec<String> = warnings(&from_str(&text).unwrap())
+            .into_iter()
+            .map(|p| p.path)
+            .collect();
+        assert_eq!(w, vec!["report[2]", "report[3]"]);
+    }
+
+    #[test]
+    fn spending_rules() {
+        let text = |r: &str| {
+            VALID.replace(
+                "      bond_percent: 40\n  years",
+                &format!("      bond_percent: 40\n      spending: {}\n  years", r),
+            )
+        };
+        let rule = |r: &str| paths(&text(r));
+        from_str(&text("Constant")).unwrap();
+        from_str(&text("{Guardrails: {}}")).unwrap();
+        assert_eq!(
+            rule("{PercentOfPortfolio: 101}"),
+            vec!["phases[0].config.SimpleWithdrawAndRebalance.spending.PercentOfPortfolio"]
+        );
+        assert_eq!(
+            rule("{Guardrails: {upper: -1, adjustment: 200}}"),
+            vec![
+                "phases[0].config.SimpleWithdrawAndRebalance.spending.Guardrails.upper",
+                "phases[0].config.SimpleWithdrawAndRebalance.spending.Guardrails.adjustment",
+            ]
+        );
+        assert_eq!(
+            rule("{FloorAndCeiling: {percent: 4, floor: 90, ceiling: 80}}"),
+            vec!["phases[0].config.SimpleWithdrawAndRebalance.spending.FloorAndCeiling.ceiling"]
+        );
+    }
+
+    #[test]
+    fn spouse() {
+        let text = VALID.to_string()
+            + "
+spouse:
+  birth_year: 1960
+  pre_tax: { value: -1, bond_percent: 40 }
+  roth: { value: 100, bond_percent: 40 }
+  first_death: { person: Spouse, year: 2040 }
+tax:
+  filing_status: Single
+  federal: &t
+    { standard_deduction: 0, income_brackets: [], capital_gains_brackets: [], niit_threshold: 0 }
+  changes:
+  - year: { Calendar: 2030 }
+    federal: PreTcja2017
+  - year: { Calendar: 2035 }
+    federal: { Custom: *t }
+";
+        assert_eq!(
+            paths(&text),
+            vec![
+                "spouse.pre_tax.value",
+                "tax.filing_status",
+                "tax.federal",
+                "tax.changes[1].federal"
+            ]
+        );
+    }
+
+    #[test]
+    fn portfolio_weights() {
+        let text = VALID.to_string()
+            + "portfolio:\n  bonds:\n  - { asset: TBonds, percent: 50 }\n  - { asset: { Series: Reits }, percent: 40 }\n";
+        assert_eq!(
+            problems(&text),
+            vec![
+                Problem {
+                    path: String::from("portfolio.bonds"),
+                    message: String::from("Weights add up to 90%, not 100%"),
+                },
+                Problem {
+                    path: String::from("portfolio.bonds[1].asset"),
+                    message: String::from("No return_data file has a Reits series"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn account_portfolio() {
+        let text = VALID.replace(
+            "roth: { value: 100, bond_percent: 40 }",
+            "roth:\n    value: 100\n    bond_percent: 40\n    portfolio:\n      stocks: [{ asset: UsStocks, percent: 90 }]",
+        );
+        assert_eq!(paths(&text), vec!["initial_balance.roth.portfolio.stocks"]);
+    }
+
+    #[test]
+    fn parametric_aaabonds() {
+        let parametric = "
+growth_model:
+  Parametric:
+    paths: 10
+    seed: 1
+    stocks: { mean: 10, std_dev: 18, income_yield: 2 }
+    bonds: { mean: 5, std_dev: 7, income_yield: 4 }
+    inflation: { mean: 3, std_dev: 2, persistence: 0.6 }
+";
+        let text = VALID.to_string()
+            + parametric
+            + "portfolio:\n  bonds: [{ asset: AaaBonds, percent: 100 }]\n";
+        assert_eq!(
+            problems(&text),
+            vec![Problem {
+                path: String::from("portfolio.bonds[0].asset"),
+                message: String::from("AaaBonds need growth_model.Parametric.aaabonds"),
+            }]
+        );
+        let aaabonds = "    aaabonds:
+      { mean: 6, std_dev: 8, income_yield: 5, correlation: [0.1, 0.9, 2] }
+";
+        assert_eq!(
+            paths(&(VALID.to_string() + parametric + aaabonds)),
+            vec![
+                "growth_model.Parametric.aaabonds.correlation[2]",
+                "growth_model.Parametric.aaabonds.correlation"
+            ]
+        );
+        let aaabonds = "    aaabonds:
+      { mean: 6, std_dev: 8, income_yield: 5, correlation: [0.9, 0.9, 0.9] }
+    correlation: [[1, -0.9, 0], [-0.9, 1, 0], [0, 0, 1]]
+";
+        assert_eq!(
+            paths(&(VALID.to_string() + parametric + aaabonds)),
+            vec!["growth_model.Parametric.aaabonds.correlation"]
+        );
+    }
+
+    #[test]
+    fn sweep() {
+        let text = format!(
+            "{}{}",
+            VALID,
+            "
+sweep:
+  metric: {StocksSold: {Percentile: 101}}
+  axes:
+  - paths: ['phases[0].years', expense_ratio]
+    values: [[20, 0.1], 30]
+    labels: [a]
+"
+        );
+        assert_eq!(
+            paths(&text),
+            vec![
+                "sweep.metric.Percentile",
+                "sweep.axes[0].labels",
+                "sweep.axes[0].values[1]"
+            ]
+        );
+    }
+
+    #[test]
+    fn solve() {
+        let text = format!(
+            "{}{}",
+            VALID.replace(
+                "SimpleWithdrawAndRebalance:\n      amount: 12\n      bond_percent: 40",
+                "Growth"
+            ),
+            "
+solve:
+  find: MinWorkingYears
+  success_rate: 150
+  min_ending_balance: -1
+"
+        );
+        assert_eq!(
+            paths(&text),
+            vec![
+                "solve.success_rate",
+                "solve.min_ending_balance",
+                "solve",
+                "solve.find"
+            ]
+        );
+        let text = format!(
+            "{}{}",
+            VALID.replace(
+                "amount: 12\n",
+                "amount: 12\n      spending: { PercentOfPortfolio: 4 }\n"
+            ),
+            "
+solve:
+  success_rate: 90
+"
+        );
+        assert_eq!(
+            paths(&text),
+            vec!["phases[0].config.SimpleWithdrawAndRebalance.spending"]
+        );
+    }
+}
@@ -1,21 +1,27 @@
-use isim::assert_eq_decimal_places;
-use isim::config::InitialState;
+use isim::config::{
+    AccountType, AssetClass, BracketIndexing, InflationAdjustment, InitialState, Measure,
+    OutputFormat, Phase, PhaseType, Portfolio, ReportField, Solve, SolveFor, SpendingRule,
+    StateTax, Sweep, Weight, YearlyContribution,
+};
+use isim::histret::RETURNS;
+use isim::report::ReportValue;
 use isim::scenario::Scenario;
+use isim::solve;
+use isim::sweep;
+use isim::validate;
+use isim::Error;
+use isim::{assert_eq_cents, assert_eq_decimal_places};
 
-use serde_yaml;
 use std::env;
 use std::fs;
 use std::path;
 
 #[cfg(test)]
 fn config(name: &str) -> InitialState {
-    let config = path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str())
-        .join("examples")
-        .join(name);
-    let config = serde_yaml::from_str(
-        fs::read_to_string(config)
-            .expect("Failed to read config")
-            .as_str(),
+    let config = validate::load(
+        &path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str())
+            .join("examples")
+            .join(name),
     )
     .unwrap();
     println!("{:#?}", config);
@@ -25,8 +31,8 @@ fn config(name: &str) -> InitialState {
 #[test]
 fn bond_growth() {
     let config = config("bond_growth.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
     let rv = scenario
         .median_instance()
         .inflation_adjusted(scenario.median_instance().value());
@@ -42,8 +48,8 @@ fn bond_growth() {
 #[test]
 fn expense_ratio() {
     let config = config("expense_ratio.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
     let rv = scenario
         .median_instance()
         .inflation_adjusted(scenario.median_instance().value());
@@ -59,8 +65,8 @@ fn expense_ratio() {
 #[test]
 fn stock_growth() {
     let config = config("stock_growth.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
     let rv = scenario
         .median_instance()
         .inflation_adjusted(scenario.median_instance().value());
@@ -76,8 +82,8 @@ fn stock_growth() {
 #[test]
 fn mixed_growth() {
     let config = config("mixed_growth.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
     let rv = scenario
         .median_instance()
         .inflation_adjusted(scenario.median_instance().value());
@@ -93,13 +99,687 @@ fn mixed_growth() {
 #[test]
 fn four_percent_rule() {
     let config = config("4_percent_rule.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
 }
 
 #[test]
 fn early_retirement() {
     let config = config("early_retirement.yaml");
-    let mut scenario = Scenario::new(&config);
-    scenario.run();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+}
+
+#[test]
+fn bootstrap() {
+    let config = config("bootstrap.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq_decimal_places!(scenario.success_ratio(), 0.949, 3);
+    let rv = scenario
+        .median_instance()
+        .inflation_adjusted(scenario.median_instance().value());
+    assert_eq!(rv.round(), 1344.0);
+}
+
+#[test]
+fn parametric() {
+    let config = config("parametric.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let rv = scenario
+        .median_instance()
+        .inflation_adjusted(scenario.median_instance().value());
+    assert_eq!(rv.round(), 2560.0);
+}
+
+#[test]
+fn fixed_growth() {
+    let config = config("fixed_growth.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let rv = scenario
+        .median_instance()
+        .inflation_adjusted(scenario.median_instance().value());
+    assert_eq_cents!(rv, 1000.0 * 1.05_f64.powi(20));
+    assert_eq_cents!(
+        scenario.median_instance().value(),
+        1000.0 * (1.05_f64 * 1.03).powi(20)
+    );
+    assert_eq!(scenario.success_ratio(), 1.0);
+}
+
+#[test]
+fn fixed_early_retirement() {
+    let mut config = config("fixed_early_retirement.yaml");
+    for (years, enough) in [(15, false), (16, true)].iter() {
+        config.phases[0].years = *years;
+        let mut scenario = Scenario::new(&config).unwrap();
+        scenario.run().unwrap();
+        let v = scenario.median_instance().value();
+        // Contributions are made at the end of each year, so this is an ordinary annuity.
+        assert_eq_cents!(v, 50_000.0 * (1.06_f64.powi(*years as i32) - 1.0) / 0.06);
+        assert_eq!(v >= 50_000.0 / 0.04, *enough);
+    }
+}
+
+#[test]
+fn measures() {
+    let config = config("bootstrap.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let real_value = |i: &isim::instance::Instance| i.inflation_adjusted(i.value());
+    let worst = scenario.measure(&Measure::Worst, real_value);
+    let p10 = scenario.measure(&Measure::Percentile(10.0), real_value);
+    let median = scenario.measure(&Measure::Median, real_value);
+    let mean = scenario.measure(&Measure::Mean, real_value);
+    let best = scenario.measure(&Measure::Best, real_value);
+    assert_eq!(
+        worst,
+        scenario.measure(&Measure::Percentile(0.0), real_value)
+    );
+    assert_eq!(
+        best,
+        scenario.measure(&Measure::Percentile(100.0), real_value)
+    );
+    // Percentiles outside 0..=100 pick the nearest end rather than panicking.
+    assert_eq!(
+        best,
+        scenario.measure(&Measure::Percentile(150.0), real_value)
+    );
+    assert_eq!(
+        worst,
+        scenario.measure(&Measure::Percentile(-5.0), real_value)
+    );
+    assert!(worst <= p10 && p10 <= median && median <= best);
+    assert!(worst <= mean && mean <= best);
+    assert_eq!(worst, 0.0);
+    assert_eq!(p10.round(), 191.0);
+    assert_eq!(mean.round(), 1892.0);
+    // The instance closest to the mean.
+    let near_mean = real_value(scenario.instance(&Measure::Mean));
+    assert!((near_mean - mean).abs() < 20.0);
+    // Bond and capital gains percents come from the instance the measure picks.
+    let bonds = |m| scenario.value(&ReportField::BondPercent(m, AccountType::Total));
+    assert_eq!(bonds(Measure::Worst), ReportValue::Percent(0.0));
+    assert_eq!(
+        bonds(Measure::Percentile(10.0)),
+        ReportValue::Percent(
+            scenario
+                .instance(&Measure::Percentile(10.0))
+                .bond_fraction()
+        )
+    );
+    let best = scenario.instance(&Measure::Best);
+    assert_eq!(
+        scenario.value(&ReportField::CapGainsPercent(Measure::Best)),
+        ReportValue::Percent(best.capital_gains() / best.value())
+    );
+}
+
+#[test]
+fn csv_report() {
+    let config = config("4_percent_rule.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let mut out = Vec::new();
+    scenario.write_report(&OutputFormat::Csv, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 32);
+    assert_eq!(
+        lines[0],
+        "Year,\"Value(Median, Total, Real)\",\"Bond%, Median, Roth\",\"Value(Worst, Total, Real)\",Success rate,Worst Years"
+    );
+    assert_eq!(lines[1], "0,1000,0.5,1000,1,1928 1929 1930");
+}
+
+#[test]
+fn trace() {
+    let mut config = config("4_percent_rule.yaml");
+    config.start_year = 2000;
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.trace(Some(1966));
+    scenario.run().unwrap();
+    let mut out = Vec::new();
+    scenario.write_trace(&OutputFormat::Csv, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 32);
+    assert!(lines[1].starts_with("1966,0,2000,0,0,500,500,"));
+    assert!(lines[31].starts_with("1966,30,2030,"));
+    assert!(lines.iter().skip(1).all(|l| l.starts_with("1966,")));
+}
+
+#[test]
+fn trace_asset_classes() {
+    let config = config("diversified_bonds.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.trace(Some(1966));
+    scenario.run().unwrap();
+    let mut out = Vec::new();
+    scenario.write_trace(&OutputFormat::Csv, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].contains(
+        ",Roth stocks UsStocks,Roth bonds TBonds,Roth bonds AaaBonds,Roth bonds TBills,"
+    ));
+    // The Roth account starts with 500 in stocks, and bonds split 40/40/20.
+    assert!(lines[1].contains(",500,200,200,100,"));
+}
+
+#[test]
+fn account_portfolio() {
+    let mut config = config("diversified_bonds.yaml");
+    config.initial_balance.roth.portfolio = Some(Portfolio {
+        stocks: config.portfolio.stocks.clone(),
+        bonds: vec![Weight {
+            asset: AssetClass::TBills,
+            percent: 100.0,
+        }],
+    });
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.trace(Some(1966));
+    scenario.run().unwrap();
+    let mut out = Vec::new();
+    scenario.write_trace(&OutputFormat::Csv, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    // Other accounts still hold the top-level portfolio's classes.
+    assert!(lines[0].contains(
+        ",PreTax bonds TBonds,PreTax bonds AaaBonds,PreTax bonds TBills,Roth stocks UsStocks,Roth bonds TBills,AfterTax"
+    ));
+    assert!(lines[1].contains(",500,500,"));
+    // T-bills alone do worse than the top-level mix of bonds.
+    let i = scenario.median_instance();
+    assert_eq!(i.inflation_adjusted(i.value()).round(), 823.0);
+}
+
+#[test]
+fn trace_not_enabled() {
+    let config = config("4_percent_rule.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert!(scenario
+        .write_trace(&OutputFormat::Csv, &mut Vec::new())
+        .is_err());
+}
+
+#[test]
+fn start_year() {
+    let mut config = config("tcja_sunset.yaml");
+    config.report = vec![ReportField::YearsElapsed, ReportField::CalendarYear];
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let mut out = Vec::new();
+    scenario.write_report(&OutputFormat::Csv, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "Year,Calendar year");
+    assert_eq!(lines[1], "0,2026");
+    assert_eq!(lines[31], "30,2056");
+    let taxes = scenario.median_instance().income.taxes;
+
+    // Starting earlier leaves more years before the first person's RMDs and the tax law change.
+    config.start_year = 2016;
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_ne!(scenario.median_instance().income.taxes, taxes);
+}
+
+#[test]
+fn tax_profile() {
+    let mut config = config("tax_profile.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let taxes = scenario.median_instance().income.taxes;
+    assert!(taxes > 0.0);
+
+    config.tax.state = StateTax::None;
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert!(scenario.median_instance().income.taxes < taxes);
+}
+
+#[test]
+fn bracket_indexing() {
+    let mut config = config("tax_profile.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let taxes = scenario.median_instance().income.taxes;
+
+    config.tax.bracket_indexing = BracketIndexing::ChainedCpi { lag: 0.25 };
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let chained_taxes = scenario.median_instance().income.taxes;
+    assert!(chained_taxes > taxes);
+
+    config.tax.bracket_indexing = BracketIndexing::Frozen;
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert!(scenario.median_instance().income.taxes > chained_taxes);
+}
+
+#[test]
+fn tax_law_changes() {
+    let mut config = config("tcja_sunset.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let taxes = scenario.median_instance().income.taxes;
+
+    config.tax.changes.clear();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert!(scenario.median_instance().income.taxes < taxes);
+}
+
+#[test]
+fn social_security() {
+    let mut config = config("social_security.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let success = scenario.success_ratio();
+    assert!(scenario.median_instance().income.social_security > 0.0);
+
+    config.social_security.clear();
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq!(scenario.median_instance().income.social_security, 0.0);
+    assert!(scenario.success_ratio() < success);
+}
+
+#[test]
+fn roth_conversion() {
+    let mut config = config("roth_conversion.yaml");
+    config.phases[0].years = 10;
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let i = scenario.median_instance();
+    assert!(i.income.roth_converted > 0.0);
+    let roth = i.value_by_account().roth;
+    assert!(roth > 0.0);
+
+    if let PhaseType::WithdrawTaxAware(w) = &mut config.phases[0].config {
+        w.roth_conversion = None;
+    }
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq!(scenario.median_instance().value_by_account().roth, 0.0);
+}
+
+#[test]
+fn bucket_strategy() {
+    let config = config("bucket_strategy.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    // Once stocks and bonds run out, cash pays for what it can, and then runs out too.
+    assert_eq!(scenario.success_ratio(), 57.0 / 64.0);
+    let i = scenario.median_instance();
+    assert_eq!(i.inflation_adjusted(i.value()).round(), 819.0);
+    assert_eq!(i.inflation_adjusted(i.cash()).round(), 45.0);
+}
+
+#[test]
+fn guardrails() {
+    let config = config("guardrails.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq!(scenario.success_ratio(), 1.0);
+    let spending = |m| match scenario.value(&ReportField::Spending(m)) {
+        ReportValue::Currency(x) => x.round(),
+        v => panic!("Expected a currency value, got {:?}", v),
+    };
+    assert_eq!(spending(Measure::Percentile(10.0)), 25.0);
+    assert_eq!(spending(Measure::Median), 42.0);
+    assert_eq!(spending(Measure::Percentile(90.0)), 83.0);
+}
+
+#[test]
+fn percent_of_portfolio() {
+    let mut config = config("4_percent_rule.yaml");
+    if let PhaseType::SimpleWithdrawAndRebalance(w) = &mut config.phases[0].config {
+        w.spending = SpendingRule::PercentOfPortfolio(4.0);
+    }
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    // A fixed fraction of what is left never runs out, but spending falls with the portfolio.
+    assert_eq!(scenario.success_ratio(), 1.0);
+    let i = scenario.worst_instance();
+    assert_eq!(i.inflation_adjusted(i.value()).round(), 614.0);
+    assert_eq_cents!(i.inflation_adjusted(i.income.spending), 23.28);
+}
+
+#[test]
+fn shortfall() {
+    let config = config("4_percent_rule.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let value = |f| scenario.value(&f);
+    // The worst path ran out after 26 years, and could not pay for the last 5.
+    assert_eq!(
+        value(ReportField::YearsUntilDepletion(Measure::Worst)),
+        ReportValue::Integer(26)
+    );
+    assert_eq!(
+        value(ReportField::YearsUntilDepletion(Measure::Percentile(10.0))),
+        ReportValue::Integer(30)
+    );
+    assert_eq!(
+        value(ReportField::ShortfallYears(Measure::Worst)),
+        ReportValue::Integer(5)
+    );
+    assert_eq!(
+        value(ReportField::ShortfallYears(Measure::Median)),
+        ReportValue::Integer(0)
+    );
+    match value(ReportField::ShortfallTotal(Measure::Worst)) {
+        ReportValue::Currency(x) => {
+            assert_eq_cents!(x, 172.45);
+        }
+        v => panic!("Expected a currency value, got {:?}", v),
+    }
+    let i = scenario.worst_instance();
+    assert_eq!(i.years_until_depletion(), Some(26));
+    assert_eq!(i.income.spending, 0.0);
+    assert!(i.income.target_spending > 0.0);
+}
+
+#[test]
+fn risk_metrics() {
+    let config = config("risk_metrics.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    let value = |f| match scenario.value(&f) {
+        ReportValue::Currency(x) | ReportValue::Percent(x) => x,
+        v => panic!("Expected a number, got {:?}", v),
+    };
+    assert_eq_decimal_places!(
+        value(ReportField::PerfectWithdrawalRate(Measure::Worst)),
+        0.0371,
+        4
+    );
+    assert_eq_decimal_places!(
+        value(ReportField::PerfectWithdrawalRate(Measure::Median)),
+        0.0572,
+        4
+    );
+    assert_eq!(
+        scenario.value(&ReportField::PerfectWithdrawalRateStartingYear(
+            Measure::Worst
+        )),
+        ReportValue::Integer(1966)
+    );
+    assert_eq!(
+        scenario.value(&ReportField::PerfectWithdrawalRateStartingYear(
+            Measure::Best
+        )),
+        ReportValue::Integer(1982)
+    );
+    // Paths that ran out fell all the way.
+    assert_eq!(value(ReportField::MaxDrawdown(Measure::Worst)), 1.0);
+    assert_eq_decimal_places!(value(ReportField::MaxDrawdown(Measure::Median)), 0.453, 3);
+    assert_eq_decimal_places!(value(ReportField::UlcerIndex(Measure::Median)), 0.244, 3);
+    // Percentiles are of the value itself, so the 90th is the larger.
+    assert_eq_decimal_places!(
+        value(ReportField::MaxDrawdown(Measure::Percentile(10.0))),
+        0.147,
+        3
+    );
+    assert_eq_decimal_places!(
+        value(ReportField::MaxDrawdown(Measure::Percentile(90.0))),
+        0.934,
+        3
+    );
+    assert_eq_decimal_places!(value(ReportField::MaxDrawdown(Measure::Best)), 0.128, 3);
+    assert_eq!(
+        value(ReportField::ValueStdDev(
+            AccountType::Total,
+            InflationAdjustment::Real
+        ))
+        .round(),
+        1215.0
+    );
+}
+
+#[test]
+fn couple() {
+    let run = |config: &InitialState| {
+        let mut scenario = Scenario::new(config).unwrap();
+        scenario.run().unwrap();
+        let i = scenario.median_instance();
+        (i.income.rmd, i.income.social_security)
+    };
+
+    // After the first death, the survivor keeps only the larger benefit.
+    let mut config = config("couple.yaml");
+    let (_, survivor) = run(&config);
+    config.spouse.as_mut().unwrap().first_death = None;
+    let (_, both) = run(&config);
+    assert!(survivor < both);
+
+    // The younger spouse's pre-tax account has no RMDs yet.
+    config.set_years(3).unwrap();
+    let (rmd, _) = run(&config);
+    assert!(rmd > 0.0);
+    let spouse = config.spouse.as_mut().unwrap();
+    config.initial_balance.pre_tax.value += spouse.pre_tax.value;
+    spouse.pre_tax.value = 0.0;
+    let (older_owner_rmd, _) = run(&config);
+    assert!(rmd < older_owner_rmd);
+}
+
+#[test]
+fn diversified_bonds() {
+    let config = config("diversified_bonds.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq_decimal_places!(scenario.success_ratio(), 0.938, 3);
+    let i = scenario.median_instance();
+    assert_eq!(i.inflation_adjusted(i.value()).round(), 1008.0);
+}
+
+#[test]
+fn external_data() {
+    let config = config("external_data.yaml");
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq!(scenario.worst_starting_years()[0], 2000);
+    assert_eq_decimal_places!(scenario.success_ratio(), 1.0, 3);
+    let i = scenario.worst_instance();
+    assert_eq!(i.inflation_adjusted(i.value()).round(), 649.0);
+}
+
+#[test]
+fn examples_are_valid() {
+    let dir = path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str()).join("examples");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some("yaml".as_ref()) {
+            if let Err(e) = validate::load(&path) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid_config_is_an_error() {
+    let mut config = config("diversified_bonds.yaml");
+    config.initial_balance.roth.bond_percent = 150.0;
+    match validate::validate(&config) {
+        Err(e) => assert!(e.to_string().contains("initial_balance.roth.bond_percent")),
+        r => panic!("Expected a config error, got {:?}", r),
+    }
+}
+
+#[test]
+fn sweep() {
+    let dir = path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str()).join("examples");
+    let text = fs::read_to_string(dir.join("4_percent_rule.yaml")).unwrap();
+    let s = Sweep {
+        metric: ReportField::Value(
+            Measure::Median,
+            AccountType::Total,
+            InflationAdjustment::Real,
+        ),
+        axes: vec![
+            "phases[0].config.SimpleWithdrawAndRebalance.amount=40,50"
+                .parse()
+                .unwrap(),
+            "phases[0].years=20,30".parse().unwrap(),
+        ],
+    };
+    let grid = sweep::sweep(&serde_yaml::from_str(&text).unwrap(), &dir, &s).unwrap();
+    let values: Vec<Vec<f64>> = grid
+        .values
+        .iter()
+        .map(|r| {
+            r.iter()
+                .map(|v| match v {
+                    ReportValue::Currency(x) => x.round(),
+                    _ => panic!("Expected a currency value, got {:?}", v),
+                })
+                .collect()
+        })
+        .collect();
+    assert_eq!(values, vec![vec![1277.0, 985.0], vec![792.0, 489.0]]);
+}
+
+#[test]
+fn sweep_default_field() {
+    let dir = path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str()).join("examples");
+    let text = fs::read_to_string(dir.join("4_percent_rule.yaml")).unwrap();
+    // The file leaves out start_year.
+    let config: serde_yaml::Value = serde_yaml::from_str(&text).unwrap();
+    let mut s = Sweep {
+        metric: ReportField::SuccessRate,
+        axes: vec!["start_year=2000,2010".parse().unwrap()],
+    };
+    let grid = sweep::sweep(&config, &dir, &s).unwrap();
+    assert_eq!(grid.values.len(), 2);
+    s.axes = vec!["start_yaer=2000,2010".parse().unwrap()];
+    assert!(sweep::sweep(&config, &dir, &s).is_err());
+}
+
+#[test]
+fn command_line_overrides() {
+    let mut config = config("4_percent_rule.yaml");
+    config.set_years(20).unwrap();
+    assert!(config.set_seed(1).is_err());
+    let mut scenario = Scenario::new(&config).unwrap();
+    scenario.run().unwrap();
+    assert_eq!(scenario.length_years(), 20);
+    assert_eq_decimal_places!(scenario.success_ratio(), 1.0, 3);
+
+    let mut bootstrap = self::config("bootstrap.yaml");
+    bootstrap.set_seed(7).unwrap();
+    assert!(bootstrap.set_years(0).is_err());
+}
+
+#[test]
+fn parallel_runs_match_serial() {
+    let report = || {
+        let config = config("bootstrap.yaml");
+        let mut scenario = Scenario::new(&config).unwrap();
+        scenario.run().unwrap();
+        let mut out = Vec::new();
+        scenario.write_report(&OutputFormat::Csv, &mut out).unwrap();
+        out
+    };
+    let sweep = || {
+        let dir =
+            path::Path::new(env::var("CARGO_MANIFEST_DIR").unwrap().as_str()).join("examples");
+        let text = fs::read_to_string(dir.join("parametric.yaml")).unwrap();
+        let s = Sweep {
+            metric: ReportField::SuccessRate,
+            axes: vec!["expense_ratio=0,1,2".parse().unwrap()],
+        };
+        let grid = sweep::sweep(&serde_yaml::from_str(&text).unwrap(), &dir, &s).unwrap();
+        grid.values
+    };
+    let serial = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let parallel = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+    assert_eq!(serial.install(report), parallel.install(report));
+    assert_eq!(serial.install(sweep), parallel.install(sweep));
+}
+
+#[test]
+fn max_withdrawal() {
+    let solution = solve::max_withdrawal(config("4_percent_rule.yaml")).unwrap();
+    assert_eq_cents!(solution.amount, 38.88);
+    assert_eq_decimal_places!(solution.withdrawal_rate.unwrap(), 0.0389, 4);
+    assert_eq_decimal_places!(solution.success_rate, 0.953, 3);
+    assert_eq!(solution.binding_years, vec![1969]);
+
+    let mut rich = config("4_percent_rule.yaml");
+    rich.solve.as_mut().unwrap().min_ending_balance = 500.0;
+    let solution = solve::max_withdrawal(rich).unwrap();
+    assert_eq_cents!(solution.amount, 33.47);
+
+    let mut impossible = config("4_percent_rule.yaml");
+    impossible.solve.as_mut().unwrap().min_ending_balance = 1e9;
+    assert!(matches!(
+        solve::max_withdrawal(impossible),
+        Err(Error::NoSolution(_))
+    ));
+}
+
+#[test]
+fn min_working_years_and_contribution() {
+    // Save 40 a year, then spend 40 a year for 30 years.
+    let plan = |find: SolveFor| {
+        let mut config = config("4_percent_rule.yaml");
+        config.initial_balance.roth.value = 0.0;
+        config.phases.insert(
+            0,
+            Phase {
+                config: PhaseType::Accumulation(YearlyContribution {
+                    pre_tax: 0.0,
+                    roth: 40.0,
+                    after_tax: 0.0,
+                    target_bond_percent: 50.0,
+                }),
+                years: 20,
+            },
+        );
+        config.solve = Some(Solve {
+            find,
+            success_rate: 90.0,
+            min_ending_balance: 0.0,
+        });
+        config
+    };
+    let m = solve::min_working_years(plan(SolveFor::MinWorkingYears)).unwrap();
+    assert_eq!(m.value, 20.0);
+    assert_eq_decimal_places!(m.success_rate, 1.0, 3);
+    let years: Vec<f64> = m.curve.iter().map(|x| x.0).collect();
+    assert_eq!(years, vec![17.0, 18.0, 19.0, 20.0, 21.0, 22.0, 23.0]);
+    assert_eq_decimal_places!(m.curve[2].1, 0.844, 3);
+
+    // Working long enough to use up the historical data still doesn't get there.
+    let mut rich = plan(SolveFor::MinWorkingYears);
+    rich.solve.as_mut().unwrap().min_ending_balance = 1e9;
+    match solve::min_working_years(rich) {
+        Err(Error::NoSolution(m)) => assert_eq!(
+            m,
+            format!(
+                "The target is not met with up to {} working years",
+                RETURNS.len() - 30
+            )
+        ),
+        r => panic!("Expected no solution, got {:?}", r),
+    }
+
+    let m = solve::min_contribution(plan(SolveFor::MinContribution)).unwrap();
+    assert_eq_cents!(m.value, 38.57);
+    assert_eq_decimal_places!(m.success_rate, 0.909, 3);
+    assert_eq!(m.curve.len(), 7);
+    assert_eq_cents!(m.curve[2].0, 34.72);
+    assert_eq_decimal_places!(m.curve[2].1, 0.659, 3);
 }